@@ -0,0 +1,57 @@
+//! Contains the set of addresses and storage keys accessed by a transaction
+
+use ethereum_types::{H160, H256};
+use std::collections::HashSet;
+use typed_transaction::AccessListItem;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+/// Addresses and storage keys that have been accessed during the current transaction, and
+/// are therefore warm
+pub struct AccessSet {
+    addresses: HashSet<H160>,
+    storage_keys: HashSet<(H160, H256)>,
+}
+
+impl AccessSet {
+    /// Creates and returns an AccessSet pre-populated with the entries of an access list
+    pub fn from_access_list(access_list: &[AccessListItem]) -> AccessSet {
+        let mut access_set = AccessSet::default();
+        for item in access_list {
+            access_set.insert_address(item.address);
+            for key in &item.storage_keys {
+                access_set.insert_storage_key(item.address, *key);
+            }
+        }
+        access_set
+    }
+
+    /// Marks `address` as accessed, returning whether it was cold
+    pub fn insert_address(&mut self, address: H160) -> bool {
+        self.addresses.insert(address)
+    }
+
+    /// Marks storage slot `key` of `address` as accessed, returning whether it was cold
+    pub fn insert_storage_key(&mut self, address: H160, key: H256) -> bool {
+        self.storage_keys.insert((address, key))
+    }
+
+    /// Marks `address` as cold again, undoing its access
+    pub fn remove_address(&mut self, address: H160) {
+        self.addresses.remove(&address);
+    }
+
+    /// Marks storage slot `key` of `address` as cold again, undoing its access
+    pub fn remove_storage_key(&mut self, address: H160, key: H256) {
+        self.storage_keys.remove(&(address, key));
+    }
+
+    /// Returns whether `address` has been accessed
+    pub fn contains_address(&self, address: H160) -> bool {
+        self.addresses.contains(&address)
+    }
+
+    /// Returns whether storage slot `key` of `address` has been accessed
+    pub fn contains_storage_key(&self, address: H160, key: H256) -> bool {
+        self.storage_keys.contains(&(address, key))
+    }
+}
@@ -1,66 +1,190 @@
 //! Contains functionality related to dealing with Accounts
 
+use eip712::TypedData;
+use errors::KeystoreError;
+use ethereum_types::H160;
 use failure::Error;
+use hd_wallet;
 use hmac::Hmac;
 use keys;
+use keys::{PassphraseProvider, Password};
 use openssl::symm;
-use rand::distributions::Standard;
 use rand::rngs::OsRng;
-use rand::Rng;
-use rustc_serialize::hex::ToHex;
+use rand::RngCore;
+use rustc_serialize::hex::{FromHex, ToHex};
+use scrypt;
 use secp256k1;
 use secp256k1::key::{PublicKey, SecretKey};
 use sha2::Sha256;
 use sha3::{Digest, Keccak256};
 use std::collections::HashMap;
 use std::fmt;
-use std::fs::File;
-use std::path::PathBuf;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
 use std::string::ToString;
 use uuid;
 
+/// Number of PBKDF2 iterations used by default, the same as geth
+pub const DEFAULT_PBKDF2_ITERATIONS: usize = 262_144;
+/// Standard scrypt CPU/memory cost used by geth
+pub const DEFAULT_SCRYPT_N: usize = 262_144;
+/// Standard scrypt block size used by geth
+pub const DEFAULT_SCRYPT_R: usize = 8;
+/// Standard scrypt parallelization used by geth
+pub const DEFAULT_SCRYPT_P: usize = 1;
+/// Largest amount of memory, `128 * r * n` bytes, a key file may make scrypt use. This is what
+/// geth's standard parameters need.
+pub const MAX_SCRYPT_MEMORY: usize = 256 * 1024 * 1024;
+/// Largest amount of work, `n * r * p`, a key file may make scrypt do. This is twice what
+/// geth's standard parameters need.
+pub const MAX_SCRYPT_WORK: usize = 1 << 22;
+/// Length of the key derived from the passphrase
+const DKLEN: usize = 32;
+/// The only cipher defined by Web3 Secret Storage
+const CIPHER: &str = "aes-128-ctr";
+
 #[derive(Default, Debug, Serialize, Deserialize)]
-/// Basic Account structure for Fantom system
+/// Basic Account structure for Fantom system. It serializes to a Web3 Secret Storage (keystore
+/// v3) file.
 pub struct Account {
     /// Public address that can be used to send coins to this account
+    #[serde(default)]
     address: String,
+    /// Struct that contains various crypto options for this account
+    crypto: AccountCrypto,
     /// A unique ID for the account. This is different from the public address
     id: String,
     /// What version this account is
     version: usize,
-    /// Struct that contains various crypto options for this account
-    crypto: AccountCrypto,
     /// Contains the base data directory
+    #[serde(skip)]
     base_directory: PathBuf,
 }
 
 impl Account {
-    /// Creates and returns a new Account
-    pub fn new(id: String, version: usize, base_directory: PathBuf) -> Result<Account, Error> {
-        let secp = secp256k1::Secp256k1::with_caps(secp256k1::ContextFlag::Full);
-        let mut hasher = Keccak256::new();
+    /// Creates a new Account for a randomly generated key pair and returns it with the secret
+    /// key. The crypto fields are left empty, as the key is not encrypted yet;
+    /// `from_secret_key` creates an account that holds it encrypted.
+    pub fn new(
+        id: String,
+        version: usize,
+        base_directory: PathBuf,
+    ) -> Result<(Account, SecretKey), Error> {
         let (p, s) = keys::generate_random_keypair()?;
-        let public_vec = p.serialize_vec(&secp, false).to_vec();
-        hasher.input(public_vec);
-        let public_result = hasher.result().to_hex().to_string();
-        let end = public_result.len();
-        let start = end - 40;
-        let address = public_result[start..end].to_string();
-
-        Ok(Account {
-            address,
-            crypto: AccountCrypto::new(p, s),
+        let account = Account {
+            address: Account::get_address(p).0.to_hex(),
+            crypto: AccountCrypto::new(),
             id,
             version,
             base_directory,
+        };
+        Ok((account, s))
+    }
+
+    /// Imports a hex encoded private key, with or without its 0x prefix
+    pub fn from_private_key(
+        private_key: &str,
+        password: &Password,
+        kdf: Kdf,
+        base_directory: PathBuf,
+    ) -> Result<Account, Error> {
+        let hex = private_key.trim_start_matches("0x");
+        let context = secp256k1::Secp256k1::with_caps(secp256k1::ContextFlag::Full);
+        let secret_key = match hex.from_hex() {
+            Ok(ref bytes) if bytes.len() == 32 => SecretKey::from_slice(&context, bytes)
+                .map_err(|_| KeystoreError::InvalidPrivateKey)?,
+            _ => return Err(KeystoreError::InvalidPrivateKey.into()),
+        };
+        Account::from_secret_key(&secret_key, password, kdf, base_directory)
+    }
+
+    /// Imports the Ethereum account with the given index of a BIP-39 mnemonic, the key at
+    /// `m/44'/60'/0'/0/index`
+    pub fn from_mnemonic(
+        phrase: &str,
+        passphrase: &str,
+        index: u32,
+        password: &Password,
+        kdf: Kdf,
+        base_directory: PathBuf,
+    ) -> Result<Account, Error> {
+        let seed = hd_wallet::mnemonic_to_seed(phrase, passphrase)?;
+        let key =
+            hd_wallet::ExtendedKey::master(&seed)?.derive_path(&hd_wallet::ethereum_path(index))?;
+        Account::from_secret_key(&key.secret_key, password, kdf, base_directory)
+    }
+
+    /// Creates and returns a new version 3 Account that holds `secret_key` encrypted with a
+    /// key derived from `password`
+    pub fn from_secret_key(
+        secret_key: &SecretKey,
+        password: &Password,
+        kdf: Kdf,
+        base_directory: PathBuf,
+    ) -> Result<Account, Error> {
+        let context = secp256k1::Secp256k1::with_caps(secp256k1::ContextFlag::Full);
+        let public_key = PublicKey::from_secret_key(&context, secret_key)?;
+        Ok(Account {
+            address: Account::get_address(public_key).0.to_hex(),
+            crypto: AccountCrypto::encrypt(secret_key, password, kdf)?,
+            id: uuid::Uuid::new_v4().to_hyphenated().to_string(),
+            version: 3,
+            base_directory,
         })
     }
 
+    /// Decrypts and returns the secret key of the account. Fails with
+    /// `KeystoreError::InvalidPassword` if the MAC shows that `password` is wrong.
+    pub fn decrypt(&self, password: &Password) -> Result<SecretKey, Error> {
+        self.crypto.decrypt(password)
+    }
+
+    /// Decrypts the secret key of the account and signs EIP-712 typed data with it, returning
+    /// the 65 byte r || s || v signature
+    pub fn sign_typed_data(
+        &self,
+        password: &Password,
+        typed_data: &TypedData,
+    ) -> Result<[u8; 65], Error> {
+        typed_data.sign(&self.decrypt(password)?)
+    }
+
     /// Gets and returns the ID of the account
     pub fn get_id(&self) -> String {
         self.id.clone()
     }
 
+    /// Gets and returns the address recorded in the key file
+    pub fn address(&self) -> Result<H160, Error> {
+        keys::parse_address(&self.address)
+            .map_err(|_| KeystoreError::InvalidField("address").into())
+    }
+
+    /// Checks that this is a version 3 key file whose address, cipher and KDF parameters are
+    /// well formed and supported, without decrypting it
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.version != 3 {
+            return Err(KeystoreError::InvalidField("version").into());
+        }
+        self.address()?;
+        self.crypto.validate()
+    }
+
+    /// Gets and returns the address recorded in the key file, with its EIP-55 checksum
+    pub fn checksum_address(&self) -> Result<String, Error> {
+        Ok(keys::to_checksum_address(&self.address()?))
+    }
+
+    /// Part of the Builder, allows setting the ID of the account
+    pub fn with_id(mut self, id: String) -> Account {
+        self.id = id;
+        self
+    }
+
     /// Part of the Builder, allows setting of the cipher algorithm
     pub fn with_cipher(mut self, cipher: String) -> Account {
         self.crypto.cipher = Some(cipher);
@@ -108,123 +232,121 @@ impl Account {
         self
     }
 
-    /// Generates the ciphertext version of the secret key
-    pub fn generate_cipher_text(secret_key: &secp256k1::key::SecretKey) -> (String, Vec<u8>) {
-        let mut generator = OsRng::default();
-        // This section generates the ciphertext version of the secret key
-        let cipher = symm::Cipher::aes_128_ctr();
-        let mut key: Vec<u8> = vec![];
-        let mut iv: Vec<u8> = vec![];
-        for _ in 0..16 {
-            key.push(generator.gen());
-            iv.push(generator.gen());
-        }
-        // Takes the slice of data containing the secret key and encrypts it
-        let data: &[u8] = &secret_key[0..secret_key.len()];
-        let ciphertext =
-            symm::encrypt(cipher, &key, Some(&iv), data).expect("Unable to encrypt secret key");
-        // Return the hex and iv
-        (ciphertext.to_hex(), iv)
-    }
-
-    /// Saves an account to a file in JSON format
-    pub fn save(&self, base_dir: &str, filename: &str) -> Result<(), Error> {
-        let path = std::path::PathBuf::from(base_dir.to_string() + filename);
-        match File::create(path) {
-            Ok(_) => Ok(()),
-            Err(e) => Err(Error::from(AccountError::new(&format!(
+    /// Saves an account to a file in JSON format. The JSON is written to a temporary file,
+    /// readable only by its owner, that then replaces `filename`, so a crash never leaves a
+    /// truncated key file behind.
+    pub fn save(&self, base_dir: &str, filename: &str) -> Result<PathBuf, Error> {
+        let path = Account::key_file_path(base_dir, filename);
+        let temp_path = Account::key_file_path(base_dir, &format!(".{}.tmp", filename));
+        let json = serde_json::to_vec(self)?;
+        let result = Account::create_key_file(&temp_path).and_then(|mut file| {
+            file.write_all(&json)?;
+            file.sync_all()
+        });
+        if let Err(e) = result.and_then(|_| fs::rename(&temp_path, &path)) {
+            let _ = fs::remove_file(&temp_path);
+            return Err(Error::from(AccountError::new(&format!(
                 "There was an error saving: {:?}",
                 e
-            )))),
+            ))));
         }
+        Ok(path)
     }
 
-    /// Gets the address of the account
-    pub fn get_address(public_key: PublicKey) -> String {
-        let context_flag = secp256k1::ContextFlag::Full;
-        let context = secp256k1::Secp256k1::with_caps(context_flag);
-        public_key.serialize_vec(&context, false).to_hex()
+    /// Loads an account from a key file written by `save`, geth or any other Web3 Secret
+    /// Storage implementation
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Account, Error> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        let mut account: Account = serde_json::from_reader(file)?;
+        account.base_directory = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        Ok(account)
     }
 
-    /// Gets the path to the keyfile for an account
-    pub fn key_file_path(base_dir: &str, filename: &str) -> PathBuf {
-        std::path::PathBuf::from(base_dir.to_string() + filename)
+    /// Opens `path` for writing a key file, readable only by its owner on unix
+    fn create_key_file(path: &Path) -> std::io::Result<File> {
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        options.open(path)
     }
 
-    /// Generates an ID, the ciphertext, and iv.
-    pub fn generate_ancillary_data(
-        &mut self,
-        public_key: PublicKey,
-        secret_key: SecretKey,
-    ) -> Result<Box<Account>, Error> {
-        self.id = uuid::Uuid::new_v4().to_hyphenated().to_string();
-        let (ciphertext, iv) = Account::generate_cipher_text(&secret_key);
-        let address = Account::get_address(public_key);
-        Account::account_from_passphrase(&self.id.as_bytes(), &iv.to_hex(), &ciphertext, &address)
+    /// Gets the address of the account that owns `public_key`
+    pub fn get_address(public_key: PublicKey) -> H160 {
+        keys::public_key_to_address(&public_key)
     }
 
+    /// Gets the path to the keyfile for an account
+    pub fn key_file_path(base_dir: &str, filename: &str) -> PathBuf {
+        Path::new(base_dir).join(filename)
+    }
+
+    /// Gets the name geth gives to the key file of the account: the UTC creation time followed
+    /// by the address, such as `UTC--2016-03-22T12-57-55.920751759Z--<address>`
     pub fn get_account_filename(&self) -> String {
         let now = chrono::Utc::now();
         "UTC--".to_string()
-            + &now.format("%Y-%m-%d").to_string()
-            + "T"
-            + &now.format("%H-%M-%SZ").to_string()
+            + &now.format("%Y-%m-%dT%H-%M-%S%.9fZ").to_string()
             + "--"
-            + &self.get_id()
-            + ".json"
+            + &self.address
     }
 
-    pub fn account_from_passphrase(
-        iv: &[u8],
-        ciphertext: &str,
-        _address: &str,
+    /// Prompts for a passphrase and creates a new account with a random key, encrypted with
+    /// the passphrase
+    pub fn account_from_passphrase(base_directory: &str) -> Result<Box<Account>, Error> {
+        Account::account_from_provider(&keys::Prompt, Kdf::default(), base_directory)
+    }
+
+    /// Creates a new account with a random key, encrypted with the passphrase supplied by
+    /// `provider`. Passing a `Password`, an `EnvVar`, a `PasswordFile` or a callback allows
+    /// creating accounts without a terminal.
+    pub fn account_from_provider<P: PassphraseProvider + ?Sized>(
+        provider: &P,
+        kdf: Kdf,
         base_directory: &str,
     ) -> Result<Box<Account>, Error> {
         // This is the passphrase we'll use to encrypt their secret key, and they will need to
         // provide to decrypt it
-        let generator = OsRng::default();
-
-        let passphrase = match keys::get_passphrase() {
+        let passphrase = match provider.passphrase() {
             Ok(passphrase) => passphrase,
             Err(e) => {
                 return Err(Error::from(AccountError { details: e }));
             }
         };
+        let (_, secret_key) = keys::generate_random_keypair()?;
+        let account =
+            Account::from_secret_key(&secret_key, &passphrase, kdf, base_directory.into())?;
+        Ok(Box::new(account))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// Key derivation functions that can turn a passphrase into the key protecting an account
+pub enum Kdf {
+    /// PBKDF2 with HMAC-SHA256 and the given number of iterations
+    Pbkdf2 { iterations: usize },
+    /// scrypt with the given CPU/memory cost `n`, a power of two, block size `r` and
+    /// parallelization `p`
+    Scrypt { n: usize, r: usize, p: usize },
+}
 
-        let count: u32 = 64000 + rand::thread_rng().gen_range(0, 20000);
-        let salt: Vec<u8> = generator.sample_iter(Standard).take(16).collect();
-
-        // Pre-allocate an array to hold the derived key
-        let mut dk = [0u8; 32];
-        // Use the KDF to create a hashed string and put it into `dk`
-        pbkdf2::pbkdf2::<Hmac<Sha256>>(&passphrase.as_bytes(), &salt, count as usize, &mut dk);
-
-        // Now we need the MAC. This verifies authenticity of the signature.
-        let mut bytes_to_hash: Vec<u8> = vec![];
-        bytes_to_hash.extend(&dk[16..32]);
-        bytes_to_hash.extend(ciphertext.bytes());
-        let mut hasher = Keccak256::new();
-        hasher.input(&bytes_to_hash);
-        let mac = hasher.result();
-        let mac = mac.to_hex();
-        let id = uuid::Uuid::new_v4().to_hyphenated().to_string();
-        let bd: PathBuf = base_directory.into();
-        let new_account = Account::new(id, 3, bd)?;
-
-        Ok(Box::new(
-            new_account
-                .with_cipher("aes-128-ctr".to_string())
-                .with_ciphertext(ciphertext.to_string())
-                .with_cipher_params(iv.to_hex())
-                .with_kdf("pbkdf2".to_string())
-                .with_pdkdf2_params(
-                    dk.len(),
-                    salt.to_hex().to_string(),
-                    "hmac-sha256".to_string(),
-                    count as usize,
-                )
-                .with_mac(mac.to_string()),
-        ))
+impl Kdf {
+    /// Returns scrypt with the cost parameters geth uses by default
+    pub fn scrypt() -> Kdf {
+        Kdf::Scrypt {
+            n: DEFAULT_SCRYPT_N,
+            r: DEFAULT_SCRYPT_R,
+            p: DEFAULT_SCRYPT_P,
+        }
+    }
+}
+
+impl Default for Kdf {
+    fn default() -> Kdf {
+        Kdf::Pbkdf2 {
+            iterations: DEFAULT_PBKDF2_ITERATIONS,
+        }
     }
 }
 
@@ -243,22 +365,11 @@ pub struct AccountCrypto {
     kdfparams: AccountKDFParams,
     // Mac for the cipher text
     mac: Option<String>,
-    // Public key
-    public_key: Vec<u8>,
-    // Secret key
-    secret_key: Vec<u8>,
 }
 
 impl AccountCrypto {
-    /// Creates and returns a new AccountCrypto
-    pub fn new(public_key: PublicKey, secret_key: SecretKey) -> AccountCrypto {
-        let secp = secp256k1::Secp256k1::with_caps(secp256k1::ContextFlag::Full);
-        let mut secret_bytes = vec![];
-        for b in secret_key[..].iter().cloned() {
-            secret_bytes.push(b);
-        }
-        let public_bytes = public_key.serialize_vec(&secp, false).to_vec();
-
+    /// Creates and returns a new, empty AccountCrypto
+    pub fn new() -> AccountCrypto {
         AccountCrypto {
             cipher: None,
             ciphertext: None,
@@ -266,21 +377,184 @@ impl AccountCrypto {
             kdf: None,
             kdfparams: AccountKDFParams::new(),
             mac: None,
-            public_key: public_bytes,
-            secret_key: secret_bytes,
         }
     }
+
+    /// Encrypts `secret_key` with AES-128-CTR under the first half of the key derived from
+    /// `password`, using a random salt and IV
+    pub fn encrypt(
+        secret_key: &SecretKey,
+        password: &Password,
+        kdf: Kdf,
+    ) -> Result<AccountCrypto, Error> {
+        let mut generator = OsRng::default();
+        let mut salt = [0u8; 32];
+        let mut iv = [0u8; 16];
+        generator.fill_bytes(&mut salt);
+        generator.fill_bytes(&mut iv);
+        AccountCrypto::encrypt_with(secret_key, password, kdf, &salt, &iv)
+    }
+
+    fn encrypt_with(
+        secret_key: &SecretKey,
+        password: &Password,
+        kdf: Kdf,
+        salt: &[u8],
+        iv: &[u8],
+    ) -> Result<AccountCrypto, Error> {
+        let mut kdfparams = AccountKDFParams::new();
+        kdfparams.dklen = Some(DKLEN);
+        kdfparams.salt = Some(salt.to_hex());
+        let kdf_name = match kdf {
+            Kdf::Pbkdf2 { iterations } => {
+                kdfparams.c = Some(iterations);
+                kdfparams.prf = Some("hmac-sha256".to_string());
+                "pbkdf2"
+            }
+            Kdf::Scrypt { n, r, p } => {
+                kdfparams.n = Some(n);
+                kdfparams.r = Some(r);
+                kdfparams.p = Some(p);
+                "scrypt"
+            }
+        };
+        let dk = kdfparams.derive_key(kdf_name, password)?;
+        let ciphertext = symm::encrypt(
+            symm::Cipher::aes_128_ctr(),
+            &dk[..16],
+            Some(iv),
+            &secret_key[0..secret_key.len()],
+        )?;
+        let mut cipherparams = HashMap::new();
+        cipherparams.insert("iv".to_string(), iv.to_hex());
+        Ok(AccountCrypto {
+            cipher: Some(CIPHER.to_string()),
+            ciphertext: Some(ciphertext.to_hex()),
+            cipherparams,
+            kdf: Some(kdf_name.to_string()),
+            kdfparams,
+            mac: Some(mac(&dk, &ciphertext).to_hex()),
+        })
+    }
+
+    /// Checks that the cipher and KDF are supported and that every field needed to decrypt
+    /// the key is present and well formed
+    pub fn validate(&self) -> Result<(), Error> {
+        let (kdf, _, _, _) = self.fields()?;
+        self.kdfparams.validate(kdf)
+    }
+
+    /// Re-derives the key from `password` with the stored KDF parameters, checks it against
+    /// the MAC and decrypts the secret key
+    pub fn decrypt(&self, password: &Password) -> Result<SecretKey, Error> {
+        let (kdf, ciphertext, iv, expected_mac) = self.fields()?;
+        let dk = self.kdfparams.derive_key(kdf, password)?;
+        if mac(&dk, &ciphertext) != expected_mac {
+            return Err(KeystoreError::InvalidPassword.into());
+        }
+        let secret = symm::decrypt(
+            symm::Cipher::aes_128_ctr(),
+            &dk[..16],
+            Some(&iv),
+            &ciphertext,
+        )?;
+        let context = secp256k1::Secp256k1::with_caps(secp256k1::ContextFlag::Full);
+        Ok(SecretKey::from_slice(&context, &secret)?)
+    }
+
+    /// Checks the cipher and decodes the KDF name, the ciphertext, the IV and the MAC
+    fn fields(&self) -> Result<(&str, Vec<u8>, Vec<u8>, Vec<u8>), Error> {
+        let cipher = self
+            .cipher
+            .as_ref()
+            .ok_or(KeystoreError::InvalidField("cipher"))?;
+        if cipher != CIPHER {
+            return Err(KeystoreError::UnsupportedCipher(cipher.clone()).into());
+        }
+        let kdf = self
+            .kdf
+            .as_ref()
+            .map(String::as_str)
+            .ok_or(KeystoreError::InvalidField("kdf"))?;
+        let ciphertext = hex_field(self.ciphertext.as_ref(), "ciphertext")?;
+        let iv = hex_field(self.cipherparams.get("iv"), "iv")?;
+        if iv.len() != 16 {
+            return Err(KeystoreError::InvalidField("iv").into());
+        }
+        let mac = hex_field(self.mac.as_ref(), "mac")?;
+        Ok((kdf, ciphertext, iv, mac))
+    }
+}
+
+/// Decodes a hex field of a key file
+fn hex_field(value: Option<&String>, name: &'static str) -> Result<Vec<u8>, KeystoreError> {
+    value
+        .and_then(|value| value.from_hex().ok())
+        .ok_or(KeystoreError::InvalidField(name))
+}
+
+/// Computes the MAC of a key file: the Keccak hash of the second half of the derived key
+/// followed by the ciphertext
+fn mac(dk: &[u8], ciphertext: &[u8]) -> Vec<u8> {
+    let mut hasher = Keccak256::new();
+    hasher.input(&dk[16..32]);
+    hasher.input(ciphertext);
+    hasher.result().to_vec()
+}
+
+/// Derives `output.len()` bytes with scrypt, CPU/memory cost `n`, block size `r` and
+/// parallelization `p`
+fn derive_scrypt_key(
+    password: &[u8],
+    salt: &[u8],
+    n: usize,
+    r: usize,
+    p: usize,
+    output: &mut [u8],
+) -> Result<(), KeystoreError> {
+    let params = scrypt_params(n, r, p)?;
+    scrypt::scrypt(password, salt, &params, output)
+        .map_err(|_| KeystoreError::InvalidField("dklen"))
+}
+
+/// Checks scrypt parameters from a key file. Fails if they are invalid, which includes
+/// `n >= 2^(16 * r)`, or cost more than `MAX_SCRYPT_MEMORY` or `MAX_SCRYPT_WORK`.
+fn scrypt_params(n: usize, r: usize, p: usize) -> Result<scrypt::ScryptParams, KeystoreError> {
+    if n < 2 || !n.is_power_of_two() {
+        return Err(KeystoreError::InvalidField("n"));
+    }
+    if r == 0 {
+        return Err(KeystoreError::InvalidField("r"));
+    }
+    if p == 0 {
+        return Err(KeystoreError::InvalidField("p"));
+    }
+    let memory = n.checked_mul(r).and_then(|nr| nr.checked_mul(128));
+    let work = n.checked_mul(r).and_then(|nr| nr.checked_mul(p));
+    match (memory, work) {
+        (Some(memory), Some(work)) if memory <= MAX_SCRYPT_MEMORY && work <= MAX_SCRYPT_WORK => {}
+        _ => return Err(KeystoreError::ScryptCostTooHigh { n, r, p }),
+    }
+    scrypt::ScryptParams::new(n.trailing_zeros() as u8, r as u32, p as u32)
+        .map_err(|_| KeystoreError::InvalidField("n"))
 }
 
 #[derive(Default, Debug, Serialize, Deserialize)]
 /// Containers parameters specific to the key derivation functions
 pub struct AccountKDFParams {
-    dklen: Option<usize>,
-    prf: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     c: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dklen: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     n: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     p: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    prf: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     r: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     salt: Option<String>,
 }
 
@@ -288,15 +562,74 @@ impl AccountKDFParams {
     /// Create and return a new set of KDF parameters
     pub fn new() -> AccountKDFParams {
         AccountKDFParams {
-            dklen: None,
-            prf: None,
             c: None,
+            dklen: None,
             n: None,
             p: None,
+            prf: None,
             r: None,
             salt: None,
         }
     }
+
+    /// Checks that the parameters of the KDF named `kdf` are present and supported, without
+    /// deriving a key
+    fn validate(&self, kdf: &str) -> Result<(), Error> {
+        self.salt()?;
+        match kdf {
+            "pbkdf2" => {
+                self.pbkdf2_iterations()?;
+            }
+            "scrypt" => {
+                let (n, r, p) = self.scrypt_cost()?;
+                scrypt_params(n, r, p)?;
+            }
+            _ => return Err(KeystoreError::UnsupportedKdf(kdf.to_string()).into()),
+        }
+        Ok(())
+    }
+
+    /// Derives the key that protects an account from `password`, using the KDF named `kdf`
+    fn derive_key(&self, kdf: &str, password: &Password) -> Result<Vec<u8>, Error> {
+        let salt = self.salt()?;
+        let mut dk = vec![0u8; DKLEN];
+        match kdf {
+            "pbkdf2" => {
+                let c = self.pbkdf2_iterations()?;
+                pbkdf2::pbkdf2::<Hmac<Sha256>>(password.as_bytes(), &salt, c, &mut dk);
+            }
+            "scrypt" => {
+                let (n, r, p) = self.scrypt_cost()?;
+                derive_scrypt_key(password.as_bytes(), &salt, n, r, p, &mut dk)?;
+            }
+            _ => return Err(KeystoreError::UnsupportedKdf(kdf.to_string()).into()),
+        }
+        Ok(dk)
+    }
+
+    /// Checks the length of the derived key and decodes the salt
+    fn salt(&self) -> Result<Vec<u8>, KeystoreError> {
+        if self.dklen != Some(DKLEN) {
+            return Err(KeystoreError::InvalidField("dklen"));
+        }
+        hex_field(self.salt.as_ref(), "salt")
+    }
+
+    /// Checks the PRF of PBKDF2 and returns its number of iterations
+    fn pbkdf2_iterations(&self) -> Result<usize, KeystoreError> {
+        if self.prf.as_ref().map(String::as_str) != Some("hmac-sha256") {
+            return Err(KeystoreError::InvalidField("prf"));
+        }
+        self.c.ok_or(KeystoreError::InvalidField("c"))
+    }
+
+    /// Returns the scrypt parameters `n`, `r` and `p`
+    fn scrypt_cost(&self) -> Result<(usize, usize, usize), KeystoreError> {
+        let n = self.n.ok_or(KeystoreError::InvalidField("n"))?;
+        let r = self.r.ok_or(KeystoreError::InvalidField("r"))?;
+        let p = self.p.ok_or(KeystoreError::InvalidField("p"))?;
+        Ok((n, r, p))
+    }
 }
 
 #[derive(Debug, Fail)]
@@ -344,10 +677,352 @@ mod tests {
         assert_eq!(test_params.dklen, None);
     }
 
+    #[test]
+    fn create_account_returns_its_secret_key() {
+        let (account, secret_key) = Account::new("id".to_string(), 3, PathBuf::new()).unwrap();
+        let context = secp256k1::Secp256k1::with_caps(secp256k1::ContextFlag::Full);
+        let public_key = PublicKey::from_secret_key(&context, &secret_key).unwrap();
+        assert_eq!(account.address, Account::get_address(public_key).0.to_hex());
+        assert!(account.crypto.cipher.is_none());
+    }
+
     #[test]
     fn create_account_crypto() {
-        let (p, s) = get_test_secret_public();
-        let test_crypto = AccountCrypto::new(s, p);
+        let test_crypto = AccountCrypto::new();
         assert!(test_crypto.cipher.is_none());
     }
+
+    /// The PBKDF2 test vector of the Web3 Secret Storage definition
+    fn get_test_vector_secret() -> secp256k1::key::SecretKey {
+        let secp = secp256k1::Secp256k1::with_caps(secp256k1::ContextFlag::Full);
+        let secret = "7a28b5ba57c53603b0b07b56bba752f7784bf506fa95edc395f5cf6c7514fe9d"
+            .from_hex()
+            .unwrap();
+        secp256k1::key::SecretKey::from_slice(&secp, &secret).unwrap()
+    }
+
+    #[test]
+    fn encrypts_like_test_vector() {
+        let crypto = AccountCrypto::encrypt_with(
+            &get_test_vector_secret(),
+            &Password::from("testpassword"),
+            Kdf::default(),
+            &"ae3cd4e7013836a3df6bd7241b12db061dbe2c6785853cce422d148a624ce0bd"
+                .from_hex()
+                .unwrap(),
+            &"6087dab2f9fdbbfaddc31a909735c1e6".from_hex().unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            crypto.ciphertext.unwrap(),
+            "5318b4d5bcd28de64ee5559e671353e16f075ecae9f99c7a79a38af5f869aa46"
+        );
+        assert_eq!(
+            crypto.mac.unwrap(),
+            "517ead924a9d0dc3124507e3393d175ce3ff7c1e96529c6c555ce9e51205e9b2"
+        );
+    }
+
+    #[test]
+    fn serializes_as_keystore_v3() {
+        let account = Account::from_secret_key(
+            &get_test_vector_secret(),
+            &Password::from("testpassword"),
+            Kdf::Pbkdf2 { iterations: 2 },
+            PathBuf::new(),
+        )
+        .unwrap();
+        let json = serde_json::to_value(&account).unwrap();
+        assert_eq!(json["address"], "008aeeda4d805471df9b2a5b0f38a0c3bcba786b");
+        assert_eq!(json["version"], 3);
+        assert_eq!(json["crypto"]["cipher"], "aes-128-ctr");
+        assert_eq!(json["crypto"]["kdf"], "pbkdf2");
+        let kdfparams = json["crypto"]["kdfparams"].as_object().unwrap();
+        let mut names: Vec<&String> = kdfparams.keys().collect();
+        names.sort();
+        assert_eq!(names, vec!["c", "dklen", "prf", "salt"]);
+        assert!(json.get("base_directory").is_none());
+        assert!(json["crypto"].get("secret_key").is_none());
+        assert!(json["crypto"].get("public_key").is_none());
+    }
+
+    #[test]
+    fn decrypts_pbkdf2_test_vector() {
+        let account: Account = serde_json::from_str(
+            r#"{
+                "crypto" : {
+                    "cipher" : "aes-128-ctr",
+                    "cipherparams" : {
+                        "iv" : "6087dab2f9fdbbfaddc31a909735c1e6"
+                    },
+                    "ciphertext" : "5318b4d5bcd28de64ee5559e671353e16f075ecae9f99c7a79a38af5f869aa46",
+                    "kdf" : "pbkdf2",
+                    "kdfparams" : {
+                        "c" : 262144,
+                        "dklen" : 32,
+                        "prf" : "hmac-sha256",
+                        "salt" : "ae3cd4e7013836a3df6bd7241b12db061dbe2c6785853cce422d148a624ce0bd"
+                    },
+                    "mac" : "517ead924a9d0dc3124507e3393d175ce3ff7c1e96529c6c555ce9e51205e9b2"
+                },
+                "id" : "3198bc9c-6672-5ab3-d995-4942343ae5b6",
+                "version" : 3
+            }"#,
+        )
+        .unwrap();
+        let secret_key = account.decrypt(&Password::from("testpassword")).unwrap();
+        assert_eq!(secret_key, get_test_vector_secret());
+    }
+
+    #[test]
+    fn rejects_wrong_password() {
+        let account = Account::from_secret_key(
+            &get_test_vector_secret(),
+            &Password::from("testpassword"),
+            Kdf::Pbkdf2 { iterations: 2 },
+            PathBuf::new(),
+        )
+        .unwrap();
+        assert_eq!(
+            account.decrypt(&Password::from("testpassword")).unwrap(),
+            get_test_vector_secret()
+        );
+        let error = account
+            .decrypt(&Password::from("wrongpassword"))
+            .unwrap_err()
+            .downcast::<KeystoreError>()
+            .unwrap();
+        match error {
+            KeystoreError::InvalidPassword => (),
+            _ => panic!("expected an invalid password error, got {}", error),
+        }
+    }
+
+    #[test]
+    fn decrypts_scrypt_key_file() {
+        let account: Account = serde_json::from_str(
+            r#"{
+                "crypto" : {
+                    "cipher" : "aes-128-ctr",
+                    "cipherparams" : {
+                        "iv" : "83dbcc02d8ccb40e466191a123791e0e"
+                    },
+                    "ciphertext" : "01a05c7f05b697274227d8bd0825a6caa89967e24643426c0fcfa2fb663052d7",
+                    "kdf" : "scrypt",
+                    "kdfparams" : {
+                        "dklen" : 32,
+                        "n" : 1024,
+                        "p" : 1,
+                        "r" : 8,
+                        "salt" : "ab0c7876052600dd703518d6fc3fe8984592145b591fc8fb5c6d43190334ba19"
+                    },
+                    "mac" : "d60a6540bbdeaa746e4c7b4359c74e4bb0b679bedce5b4d129ad96150d200274"
+                },
+                "id" : "3198bc9c-6672-5ab3-d995-4942343ae5b6",
+                "version" : 3
+            }"#,
+        )
+        .unwrap();
+        let secret_key = account.decrypt(&Password::from("testpassword")).unwrap();
+        assert_eq!(secret_key, get_test_vector_secret());
+    }
+
+    #[test]
+    fn derives_scrypt_rfc_7914_vectors() {
+        let mut output = [0u8; 64];
+        derive_scrypt_key(b"", b"", 16, 1, 1, &mut output).unwrap();
+        assert_eq!(
+            output.to_hex(),
+            "77d6576238657b203b19ca42c18a0497f16b4844e3074ae8dfdffa3fede21442\
+             fcd0069ded0948f8326a753a0fc81f17e8d3e0fb2e0d3628cf35e20c38d18906"
+        );
+        derive_scrypt_key(b"password", b"NaCl", 1024, 8, 16, &mut output).unwrap();
+        assert_eq!(
+            output.to_hex(),
+            "fdbabe1c9d3472007856e7190d01e9fe7c6ad7cbc8237830e77376634b373162\
+             2eaf30d92e22a3886ff109279d9830dac727afb94a83ee6d8360cbdfa2cc0640"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_scrypt_parameters() {
+        let mut output = [0u8; 32];
+        // n = 2^18 with r = 1, as in the Web3 Secret Storage test vector, is above the
+        // n < 2^(16 * r) bound of RFC 7914
+        for &(n, r, p) in &[(1000, 8, 1), (1 << 18, 1, 8)] {
+            match derive_scrypt_key(b"", b"", n, r, p, &mut output) {
+                Err(KeystoreError::InvalidField("n")) => (),
+                other => panic!("expected an invalid n, got {:?}", other),
+            }
+        }
+        match derive_scrypt_key(b"", b"", 16, 0, 1, &mut output) {
+            Err(KeystoreError::InvalidField("r")) => (),
+            other => panic!("expected an invalid r, got {:?}", other),
+        }
+        for &(n, r, p) in &[(1 << 20, 8, 1), (1 << 18, 1, 1 << 20)] {
+            match derive_scrypt_key(b"", b"", n, r, p, &mut output) {
+                Err(KeystoreError::ScryptCostTooHigh { .. }) => (),
+                other => panic!("expected the cost to be too high, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn encrypts_with_scrypt() {
+        let account = Account::from_secret_key(
+            &get_test_vector_secret(),
+            &Password::from("testpassword"),
+            Kdf::Scrypt { n: 16, r: 8, p: 1 },
+            PathBuf::new(),
+        )
+        .unwrap();
+        let json = serde_json::to_value(&account).unwrap();
+        assert_eq!(json["crypto"]["kdf"], "scrypt");
+        assert_eq!(json["crypto"]["kdfparams"]["n"], 16);
+        assert!(json["crypto"]["kdfparams"].get("c").is_none());
+        assert_eq!(
+            account.decrypt(&Password::from("testpassword")).unwrap(),
+            get_test_vector_secret()
+        );
+    }
+
+    #[test]
+    fn rejects_expensive_scrypt_parameters() {
+        let error = Account::from_secret_key(
+            &get_test_vector_secret(),
+            &Password::from("testpassword"),
+            Kdf::Scrypt {
+                n: 1 << 30,
+                r: 8,
+                p: 1,
+            },
+            PathBuf::new(),
+        )
+        .unwrap_err()
+        .downcast::<KeystoreError>()
+        .unwrap();
+        match error {
+            KeystoreError::ScryptCostTooHigh { .. } => (),
+            _ => panic!("expected the scrypt cost to be too high, got {}", error),
+        }
+    }
+
+    #[test]
+    fn key_file_path_joins_directory() {
+        assert_eq!(
+            Account::key_file_path("keystore", "UTC--file"),
+            Path::new("keystore").join("UTC--file")
+        );
+    }
+
+    #[test]
+    fn saves_and_loads_key_file() {
+        let directory = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        fs::create_dir_all(&directory).unwrap();
+        let account = Account::from_secret_key(
+            &get_test_vector_secret(),
+            &Password::from("testpassword"),
+            Kdf::Pbkdf2 { iterations: 2 },
+            directory.clone(),
+        )
+        .unwrap();
+        let filename = account.get_account_filename();
+        assert!(filename.starts_with("UTC--"));
+        assert!(filename.ends_with("--008aeeda4d805471df9b2a5b0f38a0c3bcba786b"));
+
+        let path = account
+            .save(directory.to_str().unwrap(), &filename)
+            .unwrap();
+        assert_eq!(fs::read_dir(&directory).unwrap().count(), 1);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let loaded = Account::load(&path).unwrap();
+        assert_eq!(loaded.get_id(), account.get_id());
+        assert_eq!(loaded.address, account.address);
+        assert_eq!(loaded.base_directory, directory);
+        assert_eq!(
+            loaded.decrypt(&Password::from("testpassword")).unwrap(),
+            get_test_vector_secret()
+        );
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn creates_account_without_terminal() {
+        let password = Password::from("testpassword");
+        let account =
+            Account::account_from_provider(&password, Kdf::Pbkdf2 { iterations: 2 }, "").unwrap();
+        assert!(account.decrypt(&password).is_ok());
+
+        let failing = || -> Result<Password, String> { Err("no passphrase".to_string()) };
+        assert!(
+            Account::account_from_provider(&failing, Kdf::Pbkdf2 { iterations: 2 }, "").is_err()
+        );
+    }
+
+    #[test]
+    fn imports_private_keys_and_mnemonics() {
+        let password = Password::from("testpassword");
+        let kdf = Kdf::Pbkdf2 { iterations: 2 };
+        let account = Account::from_private_key(
+            "0x7a28b5ba57c53603b0b07b56bba752f7784bf506fa95edc395f5cf6c7514fe9d",
+            &password,
+            kdf,
+            PathBuf::new(),
+        )
+        .unwrap();
+        assert_eq!(account.address, "008aeeda4d805471df9b2a5b0f38a0c3bcba786b");
+        assert!(Account::from_private_key("0x1234", &password, kdf, PathBuf::new()).is_err());
+
+        let account = Account::from_mnemonic(
+            "test test test test test test test test test test test junk",
+            "",
+            0,
+            &password,
+            kdf,
+            PathBuf::new(),
+        )
+        .unwrap();
+        assert_eq!(
+            account.checksum_address().unwrap(),
+            "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266"
+        );
+    }
+
+    #[test]
+    fn signs_typed_data() {
+        let password = Password::from("testpassword");
+        let account = Account::from_private_key(
+            "0x7a28b5ba57c53603b0b07b56bba752f7784bf506fa95edc395f5cf6c7514fe9d",
+            &password,
+            Kdf::Pbkdf2 { iterations: 2 },
+            PathBuf::new(),
+        )
+        .unwrap();
+        let typed_data = TypedData::from_json(
+            r#"{
+                "types": {
+                    "EIP712Domain": [{ "name": "name", "type": "string" }],
+                    "Greeting": [{ "name": "text", "type": "string" }]
+                },
+                "primaryType": "Greeting",
+                "domain": { "name": "evm-rs" },
+                "message": { "text": "hello" }
+            }"#,
+        )
+        .unwrap();
+        let signature = account.sign_typed_data(&password, &typed_data).unwrap();
+        assert_eq!(
+            typed_data.recover_signer(&signature).unwrap(),
+            account.address().unwrap()
+        );
+        assert!(account
+            .sign_typed_data(&Password::from("wrong"), &typed_data)
+            .is_err());
+    }
 }
@@ -0,0 +1,295 @@
+//! Contains the Header and Block data structures. The header commits to the transactions, the
+//! receipts and the state left by executing them.
+
+use bigint;
+use bloom::Bloom;
+use convert;
+use ethereum_types::{H160, H256, H64, U256};
+use rlp::{Decodable, DecoderError, Encodable, RlpStream, UntrustedRlp};
+use tiny_keccak::keccak256;
+use trie_root::ordered_trie_root;
+use typed_transaction::TypedTransaction;
+
+#[derive(Debug, Clone, PartialEq)]
+/// The header of a block
+pub struct Header {
+    /// Hash of the parent header
+    pub parent_hash: H256,
+    /// Hash of the ommers list
+    pub ommers_hash: H256,
+    /// Beneficiary of the block, receives the transaction fees
+    pub beneficiary: H160,
+    /// Root of the state trie once the block has been applied
+    pub state_root: H256,
+    /// Root of the transactions trie
+    pub transactions_root: H256,
+    /// Root of the receipts trie
+    pub receipts_root: H256,
+    /// Union of the blooms of all the receipts
+    pub logs_bloom: Bloom,
+    /// Difficulty of the block. After the merge this carries the prevrandao value
+    pub difficulty: U256,
+    /// Number of the block
+    pub number: U256,
+    /// Maximum amount of gas that can be consumed by the block
+    pub gas_limit: U256,
+    /// Gas used by all the transactions of the block
+    pub gas_used: U256,
+    /// Unix timestamp of the block
+    pub timestamp: U256,
+    /// Arbitrary data, at most 32 bytes
+    pub extra_data: Vec<u8>,
+    /// Proof of work mix hash
+    pub mix_hash: H256,
+    /// Proof of work nonce
+    pub nonce: H64,
+    /// Base fee per gas, present from London on (EIP-1559)
+    pub base_fee_per_gas: Option<U256>,
+}
+
+impl Default for Header {
+    fn default() -> Header {
+        let empty_root = ordered_trie_root(Vec::<Vec<u8>>::new());
+        Header {
+            parent_hash: H256::zero(),
+            ommers_hash: empty_ommers_hash(),
+            beneficiary: H160::zero(),
+            state_root: empty_root,
+            transactions_root: empty_root,
+            receipts_root: empty_root,
+            logs_bloom: Bloom::default(),
+            difficulty: U256::zero(),
+            number: U256::zero(),
+            gas_limit: U256::zero(),
+            gas_used: U256::zero(),
+            timestamp: U256::zero(),
+            extra_data: vec![],
+            mix_hash: H256::zero(),
+            nonce: H64::zero(),
+            base_fee_per_gas: None,
+        }
+    }
+}
+
+impl Header {
+    /// Returns the hash that identifies the block
+    pub fn hash(&self) -> H256 {
+        H256::from(keccak256(&self.rlp_bytes()))
+    }
+}
+
+impl Encodable for Header {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        s.begin_list(if self.base_fee_per_gas.is_some() {
+            16
+        } else {
+            15
+        });
+        s.append(&convert::to_h256(self.parent_hash));
+        s.append(&convert::to_h256(self.ommers_hash));
+        s.append(&convert::to_address(self.beneficiary));
+        s.append(&convert::to_h256(self.state_root));
+        s.append(&convert::to_h256(self.transactions_root));
+        s.append(&convert::to_h256(self.receipts_root));
+        s.append(&self.logs_bloom);
+        s.append(&convert::to_u256(self.difficulty));
+        s.append(&convert::to_u256(self.number));
+        s.append(&convert::to_u256(self.gas_limit));
+        s.append(&convert::to_u256(self.gas_used));
+        s.append(&convert::to_u256(self.timestamp));
+        s.append(&self.extra_data);
+        s.append(&convert::to_h256(self.mix_hash));
+        s.append(&self.nonce.0.to_vec());
+        if let Some(base_fee_per_gas) = self.base_fee_per_gas {
+            s.append(&convert::to_u256(base_fee_per_gas));
+        }
+    }
+}
+
+impl Decodable for Header {
+    fn decode(rlp: &UntrustedRlp) -> Result<Self, DecoderError> {
+        let item_count = rlp.item_count();
+        if item_count != 15 && item_count != 16 {
+            return Err(DecoderError::RlpIncorrectListLen);
+        }
+        let nonce: Vec<u8> = rlp.val_at(14)?;
+        if nonce.len() != 8 {
+            return Err(DecoderError::Custom("header nonce must be 8 bytes"));
+        }
+        let base_fee_per_gas = if item_count == 16 {
+            Some(convert::from_u256(rlp.val_at(15)?))
+        } else {
+            None
+        };
+        Ok(Self {
+            parent_hash: convert::from_h256(rlp.val_at(0)?),
+            ommers_hash: convert::from_h256(rlp.val_at(1)?),
+            beneficiary: convert::from_address(rlp.val_at(2)?),
+            state_root: convert::from_h256(rlp.val_at(3)?),
+            transactions_root: convert::from_h256(rlp.val_at(4)?),
+            receipts_root: convert::from_h256(rlp.val_at(5)?),
+            logs_bloom: rlp.val_at(6)?,
+            difficulty: convert::from_u256(rlp.val_at(7)?),
+            number: convert::from_u256(rlp.val_at(8)?),
+            gas_limit: convert::from_u256(rlp.val_at(9)?),
+            gas_used: convert::from_u256(rlp.val_at(10)?),
+            timestamp: convert::from_u256(rlp.val_at(11)?),
+            extra_data: rlp.val_at(12)?,
+            mix_hash: convert::from_h256(rlp.val_at(13)?),
+            nonce: H64::from_slice(&nonce),
+            base_fee_per_gas,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A block without ommers
+pub struct Block {
+    /// Header of the block
+    pub header: Header,
+    /// Transactions of the block, in execution order
+    pub transactions: Vec<TypedTransaction>,
+}
+
+impl Block {
+    /// Creates and returns a new Block, setting the transactions root of `header`
+    pub fn new(mut header: Header, transactions: Vec<TypedTransaction>) -> Block {
+        header.transactions_root = transactions_root(&transactions);
+        Block {
+            header,
+            transactions,
+        }
+    }
+
+    /// Returns the hash of the header
+    pub fn hash(&self) -> H256 {
+        self.header.hash()
+    }
+
+    /// Decodes a block from its RLP encoding
+    pub fn decode(raw: &[u8]) -> Result<Block, DecoderError> {
+        UntrustedRlp::new(raw).as_val()
+    }
+
+    /// Returns the RLP encoding of the block
+    pub fn encode(&self) -> Vec<u8> {
+        self.rlp_bytes().to_vec()
+    }
+}
+
+/// Implements rlp::Encodable as [header, transactions, ommers]. Legacy transactions are
+/// embedded as lists and typed transactions as byte strings (EIP-2718).
+impl Encodable for Block {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        s.begin_list(3);
+        s.append(&self.header);
+        s.begin_list(self.transactions.len());
+        for transaction in &self.transactions {
+            match *transaction {
+                TypedTransaction::Legacy(_) => {
+                    s.append_raw(&transaction.encode(), 1);
+                }
+                _ => {
+                    s.append(&transaction.encode());
+                }
+            }
+        }
+        s.begin_list(0);
+    }
+}
+
+impl Decodable for Block {
+    fn decode(rlp: &UntrustedRlp) -> Result<Self, DecoderError> {
+        if rlp.item_count() != 3 {
+            return Err(DecoderError::RlpIncorrectListLen);
+        }
+        if rlp.at(2)?.item_count() != 0 {
+            return Err(DecoderError::Custom("ommers are not supported"));
+        }
+        let mut transactions = vec![];
+        for transaction in rlp.at(1)?.iter() {
+            let transaction = if transaction.is_list() {
+                TypedTransaction::decode(transaction.as_raw())?
+            } else {
+                let raw: Vec<u8> = transaction.as_val()?;
+                TypedTransaction::decode(&raw)?
+            };
+            transactions.push(transaction);
+        }
+        Ok(Self {
+            header: rlp.val_at(0)?,
+            transactions,
+        })
+    }
+}
+
+/// Returns the transactions root committed to by a block header
+pub fn transactions_root(transactions: &[TypedTransaction]) -> H256 {
+    ordered_trie_root(transactions.iter().map(TypedTransaction::encode))
+}
+
+/// Returns the hash of an empty ommers list, the ommers hash of every block without ommers
+pub fn empty_ommers_hash() -> H256 {
+    let stream = RlpStream::new_list(0);
+    H256::from(keccak256(&stream.out()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustc_serialize::hex::FromHex;
+    use transaction::Transaction;
+
+    fn h256(hex: &str) -> H256 {
+        H256::from_slice(&hex.from_hex().unwrap())
+    }
+
+    /// The Ethereum mainnet genesis header
+    fn genesis() -> Header {
+        Header {
+            state_root: h256("d7f8974fb5ac78d9ac099b9ad5018bedc2ce0a72dad1827a1709da30580f0544"),
+            difficulty: U256::from(17_179_869_184u64),
+            gas_limit: U256::from(5_000u64),
+            extra_data: "11bbe8db4e347b4e8c937c1c8370e4b5ed33adb3db69cbdb7a38e1e50b1b82fa"
+                .from_hex()
+                .unwrap(),
+            nonce: H64::from_low_u64_be(0x42),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn hashes_genesis_header() {
+        assert_eq!(
+            genesis().ommers_hash,
+            h256("1dcc4de8dec75d7aab85b567b6ccd41ad312451b948a7413f0a142fd40d49347")
+        );
+        assert_eq!(
+            genesis().hash(),
+            h256("d4e56740f876aef8c010b86a40d5f56745a118d0906a34e69aec8c0db1cb8fa3")
+        );
+    }
+
+    #[test]
+    fn round_trips_blocks() {
+        let mut header = genesis();
+        header.base_fee_per_gas = Some(U256::from(7u64));
+        let transactions: Vec<TypedTransaction> = vec![
+            Transaction {
+                nonce: U256::one(),
+                to: Some(H160::from_low_u64_be(2)),
+                ..Default::default()
+            }
+            .into(),
+            TypedTransaction::DynamicFee(Default::default()),
+        ];
+        let block = Block::new(header, transactions);
+        assert_eq!(
+            block.header.transactions_root,
+            transactions_root(&block.transactions)
+        );
+        let decoded = Block::decode(&block.encode()).unwrap();
+        assert_eq!(decoded, block);
+        assert_eq!(decoded.hash(), block.hash());
+    }
+}
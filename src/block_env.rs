@@ -0,0 +1,122 @@
+//! Contains the block context that is exposed to running contracts
+
+use block::Header;
+use ethereum_types::{H160, H256, U256};
+use std::collections::HashMap;
+
+/// How many of the most recent block hashes BLOCKHASH can look up
+pub const BLOCK_HASH_HISTORY: u64 = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// Protocol upgrades whose rules change how transactions execute
+pub enum Hardfork {
+    /// Access lists and cold/warm access costs (EIP-2929, EIP-2930)
+    Berlin,
+    /// Base fee and reduced refunds (EIP-1559, EIP-3529)
+    London,
+    /// Warm coinbase and withdrawals (EIP-3651, EIP-4895)
+    Shanghai,
+    /// Blob transactions and restricted SELFDESTRUCT (EIP-4844, EIP-6780)
+    Cancun,
+}
+
+impl Default for Hardfork {
+    fn default() -> Hardfork {
+        Hardfork::Cancun
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+/// Information about the block that transactions are being executed in
+pub struct BlockEnv {
+    /// Beneficiary of the block, receives the transaction fees
+    pub coinbase: H160,
+    /// Unix timestamp of the block
+    pub timestamp: U256,
+    /// Number of the block
+    pub number: U256,
+    /// Difficulty of the block. After the merge this carries the prevrandao value
+    pub difficulty: U256,
+    /// Maximum amount of gas that can be consumed by the block
+    pub gas_limit: U256,
+    /// Base fee per gas (EIP-1559)
+    pub base_fee: U256,
+    /// Base fee per unit of blob gas (EIP-4844)
+    pub blob_base_fee: U256,
+    /// Chain ID (EIP-155)
+    pub chain_id: U256,
+    /// Rules the block is executed under
+    pub hardfork: Hardfork,
+    /// Hashes of the previous blocks, indexed by block number
+    block_hashes: HashMap<U256, H256>,
+}
+
+impl BlockEnv {
+    /// Creates and returns a new BlockEnv for the given block number
+    pub fn new(number: U256) -> BlockEnv {
+        BlockEnv {
+            number,
+            ..Default::default()
+        }
+    }
+
+    /// Part of the Builder, takes the coinbase, timestamp, number, difficulty, gas limit and
+    /// base fee from a block header. The chain ID, hardfork and block hashes are kept.
+    pub fn with_header(mut self, header: &Header) -> BlockEnv {
+        self.coinbase = header.beneficiary;
+        self.timestamp = header.timestamp;
+        self.number = header.number;
+        self.difficulty = header.difficulty;
+        self.gas_limit = header.gas_limit;
+        self.base_fee = header.base_fee_per_gas.unwrap_or_default();
+        self
+    }
+
+    /// Part of the Builder, allows setting the hash of a previous block. The embedder is
+    /// expected to supply the last `BLOCK_HASH_HISTORY` hashes; older ones are ignored.
+    pub fn with_block_hash(mut self, number: U256, hash: H256) -> BlockEnv {
+        self.insert_block_hash(number, hash);
+        self
+    }
+
+    /// Records the hash of a previous block
+    pub fn insert_block_hash(&mut self, number: U256, hash: H256) {
+        if self.is_in_history(number) {
+            self.block_hashes.insert(number, hash);
+        }
+    }
+
+    /// Returns the hash of block `number`, or zero when it is not one of the last
+    /// `BLOCK_HASH_HISTORY` blocks
+    pub fn block_hash(&self, number: U256) -> H256 {
+        if !self.is_in_history(number) {
+            return H256::zero();
+        }
+        self.block_hashes.get(&number).cloned().unwrap_or_default()
+    }
+
+    fn is_in_history(&self, number: U256) -> bool {
+        number < self.number && self.number - number <= U256::from(BLOCK_HASH_HISTORY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn block_hash_within_history() {
+        let hash = H256::from_low_u64_be(7);
+        let env = BlockEnv::new(U256::from(300u64)).with_block_hash(U256::from(299u64), hash);
+        assert_eq!(env.block_hash(U256::from(299u64)), hash);
+    }
+
+    #[test]
+    fn block_hash_outside_history() {
+        let env = BlockEnv::new(U256::from(300u64))
+            .with_block_hash(U256::from(10u64), H256::from_low_u64_be(1))
+            .with_block_hash(U256::from(300u64), H256::from_low_u64_be(2));
+        assert!(env.block_hash(U256::from(10u64)).is_zero());
+        assert!(env.block_hash(U256::from(300u64)).is_zero());
+    }
+}
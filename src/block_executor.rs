@@ -0,0 +1,383 @@
+//! Contains the executor that applies the transactions of a block, in order, to the state
+//! held by the VM
+
+use block::{transactions_root, Block, Header};
+use block_env::{BlockEnv, Hardfork};
+use bloom::Bloom;
+use errors::{BlockError, ExecutorError, Result};
+use ethereum_types::{H256, U256};
+use executor::TransactionExecutor;
+use log_filter::LogIndex;
+use receipt::{receipts_root, Receipt};
+use typed_transaction::TypedTransaction;
+use vm::VM;
+
+#[derive(Debug, Clone, PartialEq)]
+/// The result of executing a block
+pub struct BlockOutcome {
+    /// Receipts of the transactions, in block order
+    pub receipts: Vec<Receipt>,
+    /// Total gas used by the transactions
+    pub gas_used: U256,
+    /// Union of the blooms of all the receipts
+    pub logs_bloom: Bloom,
+    /// Root of the receipts trie
+    pub receipts_root: H256,
+    /// Root of the state trie once the block has been applied
+    pub state_root: H256,
+}
+
+/// Applies whole blocks to the state held by a VM
+pub struct BlockExecutor<'a> {
+    vm: &'a mut VM,
+    block_reward: U256,
+    log_index: Option<&'a mut LogIndex>,
+}
+
+impl<'a> BlockExecutor<'a> {
+    /// Creates and returns a new BlockExecutor that pays no block reward
+    pub fn new(vm: &'a mut VM) -> BlockExecutor<'a> {
+        BlockExecutor {
+            vm,
+            block_reward: U256::zero(),
+            log_index: None,
+        }
+    }
+
+    /// Part of the Builder, sets the reward paid to the coinbase for every block
+    pub fn with_block_reward(mut self, block_reward: U256) -> BlockExecutor<'a> {
+        self.block_reward = block_reward;
+        self
+    }
+
+    /// Part of the Builder, adds the logs of every block that is applied to `log_index`. A
+    /// block replaces the logs indexed for its height and above, which belong to a chain that
+    /// has been reorganised away.
+    pub fn with_log_index(mut self, log_index: &'a mut LogIndex) -> BlockExecutor<'a> {
+        self.log_index = Some(log_index);
+        self
+    }
+
+    /// Executes the signed `transactions` in order in the block described by `env`. Every
+    /// transaction sees the state left by the previous ones. If any transaction is invalid or
+    /// the block runs out of gas, the whole block is rejected and the state is left untouched.
+    /// Otherwise the block reward is paid to the coinbase.
+    pub fn execute<T: Into<TypedTransaction>>(
+        &mut self,
+        env: BlockEnv,
+        transactions: Vec<T>,
+    ) -> Result<BlockOutcome> {
+        let transactions: Vec<TypedTransaction> =
+            transactions.into_iter().map(Into::into).collect();
+        let hashes: Vec<H256> = transactions.iter().map(TypedTransaction::hash).collect();
+        let number = env.number;
+        let outcome = self.atomically(env, |executor| executor.apply(transactions))?;
+        self.index_logs(number, &hashes, &outcome.receipts);
+        Ok(outcome)
+    }
+
+    /// Executes `transactions` on top of the block `parent_hash` and returns the resulting
+    /// block, with every root in its header filled in
+    pub fn produce(
+        &mut self,
+        env: BlockEnv,
+        parent_hash: H256,
+        transactions: Vec<TypedTransaction>,
+    ) -> Result<Block> {
+        let outcome = self.execute(env.clone(), transactions.clone())?;
+        let base_fee_per_gas = if env.hardfork >= Hardfork::London {
+            Some(env.base_fee)
+        } else {
+            None
+        };
+        let header = Header {
+            parent_hash,
+            beneficiary: env.coinbase,
+            state_root: outcome.state_root,
+            receipts_root: outcome.receipts_root,
+            logs_bloom: outcome.logs_bloom,
+            difficulty: env.difficulty,
+            number: env.number,
+            gas_limit: env.gas_limit,
+            gas_used: outcome.gas_used,
+            timestamp: env.timestamp,
+            base_fee_per_gas,
+            ..Default::default()
+        };
+        Ok(Block::new(header, transactions))
+    }
+
+    /// Executes `block` and checks that its header commits to the transactions, the receipts
+    /// and the state they produce. The block fields of `env` are taken from the header. An
+    /// invalid block is rejected with an error and leaves the state untouched.
+    pub fn verify(&mut self, block: &Block, env: BlockEnv) -> Result<BlockOutcome> {
+        let header = &block.header;
+        if transactions_root(&block.transactions) != header.transactions_root {
+            return Err(BlockError::TransactionsRootMismatch.into());
+        }
+        let outcome = self.atomically(env.with_header(header), |executor| {
+            let outcome = executor.apply(block.transactions.clone())?;
+            if outcome.gas_used != header.gas_used {
+                return Err(BlockError::GasUsedMismatch.into());
+            }
+            if outcome.logs_bloom != header.logs_bloom {
+                return Err(BlockError::LogsBloomMismatch.into());
+            }
+            if outcome.receipts_root != header.receipts_root {
+                return Err(BlockError::ReceiptsRootMismatch.into());
+            }
+            if outcome.state_root != header.state_root {
+                return Err(BlockError::StateRootMismatch.into());
+            }
+            Ok(outcome)
+        })?;
+        let hashes: Vec<H256> = block
+            .transactions
+            .iter()
+            .map(TypedTransaction::hash)
+            .collect();
+        self.index_logs(header.number, &hashes, &outcome.receipts);
+        Ok(outcome)
+    }
+
+    /// Replaces the logs indexed for block `number` and above with the logs of `receipts`
+    fn index_logs(&mut self, number: U256, hashes: &[H256], receipts: &[Receipt]) {
+        if let Some(ref mut log_index) = self.log_index {
+            match number.checked_sub(U256::one()) {
+                Some(parent) => log_index.truncate(parent),
+                None => **log_index = LogIndex::new(),
+            }
+            for (index, (hash, receipt)) in hashes.iter().zip(receipts).enumerate() {
+                log_index.insert_receipt(number, *hash, index, receipt);
+            }
+        }
+    }
+
+    /// Runs `f` in the block described by `env`, restoring the state and the previous block
+    /// if it fails
+    fn atomically<R, F>(&mut self, env: BlockEnv, f: F) -> Result<R>
+    where
+        F: FnOnce(&mut Self) -> Result<R>,
+    {
+        let checkpoint = self.vm.checkpoint();
+        let previous_env = self.vm.block_env().clone();
+        self.vm.set_block_env(env);
+        let result = f(self);
+        if result.is_ok() {
+            self.vm.commit(checkpoint);
+        } else {
+            self.vm.revert(checkpoint);
+            self.vm.set_block_env(previous_env);
+        }
+        result
+    }
+
+    fn apply(&mut self, transactions: Vec<TypedTransaction>) -> Result<BlockOutcome> {
+        let block_gas_limit = self.vm.block_env().gas_limit;
+        let mut gas_used = U256::zero();
+        let mut logs_bloom = Bloom::default();
+        let mut receipts = Vec::with_capacity(transactions.len());
+        for transaction in transactions {
+            if gas_used.saturating_add(transaction.gas_limit()) > block_gas_limit {
+                return Err(ExecutorError::BlockGasLimitExceeded.into());
+            }
+            let mut receipt = TransactionExecutor::new(self.vm).execute_signed(transaction)?;
+            gas_used = gas_used + receipt.gas_used;
+            receipt.cumulative_gas_used = gas_used;
+            logs_bloom.accrue_bloom(&receipt.logs_bloom);
+            receipts.push(receipt);
+        }
+
+        if !self.block_reward.is_zero() {
+            let coinbase = self.vm.block_env().coinbase;
+            let balance = self.vm.balance(coinbase);
+            self.vm.set_balance(coinbase, balance + self.block_reward);
+        }
+
+        Ok(BlockOutcome {
+            receipts_root: receipts_root(&receipts),
+            state_root: self.vm.state_root(),
+            receipts,
+            gas_used,
+            logs_bloom,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethereum_types::H160;
+    use log_filter::LogFilter;
+    use rustc_serialize::hex::FromHex;
+    use secp256k1::key::SecretKey;
+    use secp256k1::Secp256k1;
+    use transaction::Transaction;
+
+    const CHAIN_ID: u64 = 250;
+
+    fn secret_key() -> SecretKey {
+        SecretKey::from_slice(&Secp256k1::new(), &[0x46; 32]).unwrap()
+    }
+
+    /// The address of `secret_key`
+    fn signer() -> H160 {
+        H160::from_slice(
+            &"9d8a62f656a8d1615c1294fd71e9cfb3e4855a4f"
+                .from_hex()
+                .unwrap(),
+        )
+    }
+
+    fn coinbase() -> H160 {
+        H160::from_low_u64_be(3)
+    }
+
+    fn env(gas_limit: u64) -> BlockEnv {
+        let mut env = BlockEnv::new(U256::one());
+        env.coinbase = coinbase();
+        env.gas_limit = U256::from(gas_limit);
+        env.chain_id = U256::from(CHAIN_ID);
+        env
+    }
+
+    fn transfer(nonce: u64, value: u64) -> Transaction {
+        signed(Transaction {
+            nonce: U256::from(nonce),
+            gas_price: U256::from(1u64),
+            start_gas: U256::from(21_000u64),
+            to: Some(H160::from_low_u64_be(2)),
+            value: U256::from(value),
+            ..Default::default()
+        })
+    }
+
+    fn signed(mut transaction: Transaction) -> Transaction {
+        transaction.sign(&secret_key(), Some(CHAIN_ID)).unwrap();
+        transaction
+    }
+
+    fn funded_vm() -> VM {
+        let mut vm = VM::default();
+        vm.set_balance(signer(), U256::from(1_000_000u64));
+        vm
+    }
+
+    #[test]
+    fn executes_transactions_in_order() {
+        let mut vm = funded_vm();
+        let outcome = BlockExecutor::new(&mut vm)
+            .with_block_reward(U256::from(5u64))
+            .execute(env(100_000), vec![transfer(0, 10), transfer(1, 20)])
+            .unwrap();
+        assert_eq!(outcome.gas_used, U256::from(42_000u64));
+        assert_eq!(outcome.receipts.len(), 2);
+        assert_eq!(
+            outcome.receipts[0].cumulative_gas_used,
+            U256::from(21_000u64)
+        );
+        assert_eq!(
+            outcome.receipts[1].cumulative_gas_used,
+            U256::from(42_000u64)
+        );
+        assert_eq!(outcome.receipts_root, receipts_root(&outcome.receipts));
+        assert!(outcome.logs_bloom.is_empty());
+        assert_eq!(outcome.state_root, vm.state_root());
+        assert_eq!(vm.nonce(signer()), U256::from(2u64));
+        assert_eq!(vm.balance(H160::from_low_u64_be(2)), U256::from(30u64));
+        assert_eq!(vm.balance(coinbase()), U256::from(42_000u64 + 5));
+    }
+
+    #[test]
+    fn rejects_block_over_gas_limit() {
+        let mut vm = funded_vm();
+        let state_root = vm.state_root();
+        let result = BlockExecutor::new(&mut vm)
+            .execute(env(30_000), vec![transfer(0, 10), transfer(1, 20)]);
+        assert!(result.is_err());
+        assert_eq!(vm.state_root(), state_root);
+        assert_eq!(vm.nonce(signer()), U256::zero());
+    }
+
+    #[test]
+    fn rejects_block_with_invalid_transaction() {
+        let mut vm = funded_vm();
+        let result = BlockExecutor::new(&mut vm)
+            .execute(env(100_000), vec![transfer(0, 10), transfer(0, 20)]);
+        assert!(result.is_err());
+        assert_eq!(vm.balance(signer()), U256::from(1_000_000u64));
+    }
+
+    #[test]
+    fn indexes_logs_of_applied_blocks() {
+        // PUSH1 0x07 PUSH1 0x00 PUSH1 0x00 LOG1
+        let contract = H160::from_low_u64_be(0x42);
+        let mut vm = funded_vm();
+        vm.set_code(contract, vec![0x60, 0x07, 0x60, 0x00, 0x60, 0x00, 0xa1]);
+        let call = signed(Transaction {
+            nonce: U256::one(),
+            gas_price: U256::from(1u64),
+            start_gas: U256::from(50_000u64),
+            to: Some(contract),
+            ..Default::default()
+        });
+        let call_hash = TypedTransaction::from(call.clone()).hash();
+        let mut index = LogIndex::new();
+        BlockExecutor::new(&mut vm)
+            .with_log_index(&mut index)
+            .execute(env(100_000), vec![transfer(0, 10), call])
+            .unwrap();
+
+        let filter = LogFilter::new()
+            .with_address(contract)
+            .with_topics(0, vec![H256::from_low_u64_be(7)]);
+        let logs = index.query(&filter);
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].block_number, U256::one());
+        assert_eq!(logs[0].transaction_hash, call_hash);
+        assert_eq!(logs[0].transaction_index, 1);
+        assert!(index
+            .query(
+                &filter
+                    .clone()
+                    .with_topics(0, vec![H256::from_low_u64_be(8)])
+            )
+            .is_empty());
+
+        // Another block at the same height replaces the logs of the first one
+        BlockExecutor::new(&mut vm)
+            .with_log_index(&mut index)
+            .execute(env(100_000), vec![transfer(2, 10)])
+            .unwrap();
+        assert!(index.query(&filter).is_empty());
+    }
+
+    #[test]
+    fn verifies_produced_block() {
+        let transactions = vec![transfer(0, 10).into(), transfer(1, 20).into()];
+        let block = BlockExecutor::new(&mut funded_vm())
+            .produce(env(100_000), H256::zero(), transactions)
+            .unwrap();
+        assert_eq!(block.header.gas_used, U256::from(42_000u64));
+        assert_eq!(block.header.number, U256::one());
+
+        let mut vm = funded_vm();
+        let outcome = BlockExecutor::new(&mut vm).verify(&block, env(0)).unwrap();
+        assert_eq!(outcome.state_root, block.header.state_root);
+        assert_eq!(vm.nonce(signer()), U256::from(2u64));
+
+        let mut tampered = block.clone();
+        tampered.header.state_root = H256::zero();
+        let mut vm = funded_vm();
+        assert!(BlockExecutor::new(&mut vm)
+            .verify(&tampered, env(0))
+            .is_err());
+        assert_eq!(vm.nonce(signer()), U256::zero());
+
+        let mut tampered = block;
+        tampered.transactions.pop();
+        assert!(BlockExecutor::new(&mut funded_vm())
+            .verify(&tampered, env(0))
+            .is_err());
+    }
+}
@@ -0,0 +1,189 @@
+//! Contains the 2048-bit bloom filter used to summarise the logs of receipts and blocks
+
+use eth_log::Log;
+use ethereum_types::{H160, H256};
+use rlp::{Decodable, DecoderError, Encodable, RlpStream, UntrustedRlp};
+use std::fmt;
+use std::ops::{BitOr, BitOrAssign};
+use tiny_keccak::keccak256;
+
+/// Size of a bloom filter in bytes
+pub const BLOOM_SIZE: usize = 256;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+/// A 2048-bit bloom filter over the addresses and topics of logs
+pub struct Bloom(pub [u8; BLOOM_SIZE]);
+
+impl Bloom {
+    /// Creates and returns a Bloom that accrues every log in `logs`
+    pub fn from_logs(logs: &[Log]) -> Bloom {
+        let mut bloom = Bloom::default();
+        for log in logs {
+            bloom.accrue_log(log);
+        }
+        bloom
+    }
+
+    /// Adds the address and the topics of `log` to the filter
+    pub fn accrue_log(&mut self, log: &Log) {
+        self.accrue(&log.address[..]);
+        for topic in &log.topics {
+            self.accrue(&topic[..]);
+        }
+    }
+
+    /// Adds `input` to the filter by setting the three bits selected by its hash
+    pub fn accrue(&mut self, input: &[u8]) {
+        for (index, mask) in bloom_bits(input).iter() {
+            self.0[*index] |= *mask;
+        }
+    }
+
+    /// Adds every entry of `other` to the filter
+    pub fn accrue_bloom(&mut self, other: &Bloom) {
+        for (byte, other_byte) in self.0.iter_mut().zip(other.0.iter()) {
+            *byte |= *other_byte;
+        }
+    }
+
+    /// Returns a filter holding the entries of both filters, as used for the bloom of a block
+    pub fn union(&self, other: &Bloom) -> Bloom {
+        let mut bloom = *self;
+        bloom.accrue_bloom(other);
+        bloom
+    }
+
+    /// Returns true if `input` may have been added to the filter. False positives are
+    /// possible, false negatives are not.
+    pub fn contains_input(&self, input: &[u8]) -> bool {
+        bloom_bits(input)
+            .iter()
+            .all(|(index, mask)| self.0[*index] & *mask == *mask)
+    }
+
+    /// Returns true if every entry of `other` may be in the filter
+    pub fn contains_bloom(&self, other: &Bloom) -> bool {
+        self.0
+            .iter()
+            .zip(other.0.iter())
+            .all(|(byte, other_byte)| *byte & *other_byte == *other_byte)
+    }
+
+    /// Returns true if a log emitted by `address` may have been added to the filter
+    pub fn contains_address(&self, address: &H160) -> bool {
+        self.contains_input(&address.0)
+    }
+
+    /// Returns true if a log with `topic` may have been added to the filter
+    pub fn contains_topic(&self, topic: &H256) -> bool {
+        self.contains_input(&topic.0)
+    }
+
+    /// Returns true if no bits are set
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().all(|b| *b == 0)
+    }
+}
+
+impl BitOr for Bloom {
+    type Output = Bloom;
+
+    fn bitor(self, other: Bloom) -> Bloom {
+        self.union(&other)
+    }
+}
+
+impl BitOrAssign for Bloom {
+    fn bitor_assign(&mut self, other: Bloom) {
+        self.accrue_bloom(&other);
+    }
+}
+
+/// Byte indexes and bit masks of the three bits set for `input`. Each bit is picked by the
+/// low 11 bits of one of the first three pairs of bytes of the Keccak hash of `input`.
+fn bloom_bits(input: &[u8]) -> [(usize, u8); 3] {
+    let hash = keccak256(input);
+    let mut bits = [(0, 0); 3];
+    for (i, bit) in bits.iter_mut().enumerate() {
+        let position = ((hash[2 * i] as usize) << 8 | hash[2 * i + 1] as usize) & 2047;
+        *bit = (BLOOM_SIZE - 1 - position / 8, 1 << (position % 8));
+    }
+    bits
+}
+
+impl Default for Bloom {
+    fn default() -> Bloom {
+        Bloom([0; BLOOM_SIZE])
+    }
+}
+
+impl fmt::Debug for Bloom {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Bloom(")?;
+        for byte in self.0.iter() {
+            write!(f, "{:02x}", byte)?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl Encodable for Bloom {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        s.append(&self.0.to_vec());
+    }
+}
+
+impl Decodable for Bloom {
+    fn decode(rlp: &UntrustedRlp) -> Result<Self, DecoderError> {
+        let bytes: Vec<u8> = rlp.as_val()?;
+        if bytes.len() != BLOOM_SIZE {
+            return Err(DecoderError::Custom("bloom must be 256 bytes"));
+        }
+        let mut bloom = Bloom::default();
+        bloom.0.copy_from_slice(&bytes);
+        Ok(bloom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bigint;
+
+    fn log(address: u8, topic: u8) -> Log {
+        let mut log = Log::new(bigint::Address::from(&[address; 20][..]));
+        log.topics.push(bigint::H256::from(&[topic; 32][..]));
+        log
+    }
+
+    #[test]
+    fn sets_three_bits_per_input() {
+        let mut bloom = Bloom::default();
+        bloom.accrue(b"test");
+        let bits: u32 = bloom.0.iter().map(|b| b.count_ones()).sum();
+        assert_eq!(bits, 3);
+        assert!(bloom.contains_input(b"test"));
+        assert!(!bloom.contains_input(b"hallo"));
+    }
+
+    #[test]
+    fn contains_accrued_addresses_and_topics() {
+        let bloom = Bloom::from_logs(&[log(0x11, 0x22)]);
+        assert!(bloom.contains_address(&H160::from([0x11; 20])));
+        assert!(bloom.contains_topic(&H256::from([0x22; 32])));
+        assert!(!bloom.contains_address(&H160::from([0x33; 20])));
+        assert!(!bloom.contains_topic(&H256::from([0x44; 32])));
+    }
+
+    #[test]
+    fn union_contains_both_filters() {
+        let first = Bloom::from_logs(&[log(0x11, 0x22)]);
+        let second = Bloom::from_logs(&[log(0x33, 0x44)]);
+        let union = first | second;
+        assert!(union.contains_bloom(&first));
+        assert!(union.contains_bloom(&second));
+        assert!(!first.contains_bloom(&union));
+        assert!(union.contains_address(&H160::from([0x33; 20])));
+        assert_eq!(union, first.union(&second));
+    }
+}
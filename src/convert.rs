@@ -0,0 +1,68 @@
+//! Conversions between the `ethereum_types` values used by the public API and the `bigint`
+//! values used inside the VM
+
+use bigint;
+use bigint::{Address, M256, U256};
+use ethereum_types::{H160, H256};
+
+/// Converts a public API integer into a VM integer
+pub fn to_u256(value: ethereum_types::U256) -> U256 {
+    let mut bytes = [0u8; 32];
+    value.to_big_endian(&mut bytes);
+    U256::from(&bytes[..])
+}
+
+/// Converts a VM integer into a public API integer
+pub fn from_u256(value: U256) -> ethereum_types::U256 {
+    let mut bytes = [0u8; 32];
+    value.to_big_endian(&mut bytes);
+    ethereum_types::U256::from_big_endian(&bytes)
+}
+
+/// Converts a public API integer into a stack word
+pub fn to_m256(value: ethereum_types::U256) -> M256 {
+    to_u256(value).into()
+}
+
+/// Converts a public API address into a VM address
+pub fn to_address(address: H160) -> Address {
+    Address::from(&address.0[..])
+}
+
+/// Converts a VM address into a public API address
+pub fn from_address(address: Address) -> H160 {
+    H160::from_slice(&address[..])
+}
+
+/// Converts an address into a stack word, right aligned
+pub fn address_to_m256(address: H160) -> M256 {
+    M256::from(&address.0[..])
+}
+
+/// Takes the low 20 bytes of a stack word as an address
+pub fn m256_to_address(value: M256) -> H160 {
+    let value: U256 = value.into();
+    let mut bytes = [0u8; 32];
+    value.to_big_endian(&mut bytes);
+    H160::from_slice(&bytes[12..])
+}
+
+/// Converts a hash into a stack word
+pub fn h256_to_m256(hash: H256) -> M256 {
+    M256::from(&hash.0[..])
+}
+
+/// Converts a stack word into a hash, such as a storage key
+pub fn m256_to_h256(value: M256) -> H256 {
+    from_h256(bigint::H256::from(value))
+}
+
+/// Converts a public API hash into a VM hash
+pub fn to_h256(hash: H256) -> bigint::H256 {
+    bigint::H256::from(&hash.0[..])
+}
+
+/// Converts a VM hash into a public API hash
+pub fn from_h256(hash: bigint::H256) -> H256 {
+    H256::from_slice(&hash[..])
+}
@@ -0,0 +1,468 @@
+//! Contains the EIP-712 encoder, which hashes typed structured data such as permits and
+//! meta-transactions so it can be signed and verified
+
+use errors::{Eip712Error, Result};
+use ethereum_types::{H160, H256, U256};
+use keys;
+use rustc_serialize::hex::FromHex;
+use secp256k1::key::SecretKey;
+use serde_json;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::str::FromStr;
+use tiny_keccak::keccak256;
+
+/// Name of the type that describes the domain
+pub const DOMAIN_TYPE: &str = "EIP712Domain";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// A member of a struct type
+pub struct MemberType {
+    /// Name of the member
+    pub name: String,
+    /// Type of the member, such as `uint256`, `Person` or `address[]`
+    #[serde(rename = "type")]
+    pub kind: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+/// Typed structured data, in the JSON layout taken by `eth_signTypedData_v4`
+pub struct TypedData {
+    /// The struct types, including `EIP712Domain`
+    pub types: BTreeMap<String, Vec<MemberType>>,
+    /// Type of the message
+    pub primary_type: String,
+    /// Values of the domain, described by `EIP712Domain`
+    pub domain: Value,
+    /// Values of the message, described by the primary type
+    pub message: Value,
+}
+
+impl TypedData {
+    /// Parses typed data from its JSON
+    pub fn from_json(json: &str) -> Result<TypedData> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Returns the encoding of a struct type followed by the struct types it references, in
+    /// alphabetical order, such as `Mail(Person from,Person to)Person(string name)`
+    pub fn encode_type(&self, name: &str) -> Result<String> {
+        let mut dependencies = BTreeSet::new();
+        self.collect_dependencies(name, &mut dependencies)?;
+        dependencies.remove(name);
+        let mut encoded = String::new();
+        for dependency in Some(name)
+            .into_iter()
+            .chain(dependencies.iter().map(String::as_str))
+        {
+            let members: Vec<String> = self
+                .types
+                .get(dependency)
+                .ok_or_else(|| Eip712Error::UnknownType(dependency.to_string()))?
+                .iter()
+                .map(|member| format!("{} {}", member.kind, member.name))
+                .collect();
+            encoded.push_str(&format!("{}({})", dependency, members.join(",")));
+        }
+        Ok(encoded)
+    }
+
+    /// Returns the Keccak hash of the encoding of a struct type
+    pub fn type_hash(&self, name: &str) -> Result<H256> {
+        Ok(H256::from(keccak256(self.encode_type(name)?.as_bytes())))
+    }
+
+    /// Returns the hash of `value` as a struct of type `name`
+    pub fn hash_struct(&self, name: &str, value: &Value) -> Result<H256> {
+        Ok(H256::from(keccak256(&self.encode_data(name, value)?)))
+    }
+
+    /// Returns the type hash of a struct followed by the 32 byte encoding of every member
+    pub fn encode_data(&self, name: &str, value: &Value) -> Result<Vec<u8>> {
+        let members = self
+            .types
+            .get(name)
+            .ok_or_else(|| Eip712Error::UnknownType(name.to_string()))?;
+        let mut encoded = self.type_hash(name)?.0.to_vec();
+        for member in members {
+            let field = value
+                .get(&member.name)
+                .ok_or_else(|| Eip712Error::MissingField(member.name.clone()))?;
+            encoded.extend_from_slice(&self.encode_value(&member.kind, field)?.0);
+        }
+        Ok(encoded)
+    }
+
+    /// Returns the hash of the domain
+    pub fn domain_separator(&self) -> Result<H256> {
+        self.hash_struct(DOMAIN_TYPE, &self.domain)
+    }
+
+    /// Returns the digest that is signed: the Keccak hash of `0x19 0x01`, the domain separator
+    /// and the hash of the message
+    pub fn signing_hash(&self) -> Result<H256> {
+        let mut preimage = vec![0x19, 0x01];
+        preimage.extend_from_slice(&self.domain_separator()?.0);
+        preimage.extend_from_slice(&self.hash_struct(&self.primary_type, &self.message)?.0);
+        Ok(H256::from(keccak256(&preimage)))
+    }
+
+    /// Signs the typed data, returning the 65 byte r || s || v signature where v is 27 or 28
+    pub fn sign(&self, secret_key: &SecretKey) -> Result<[u8; 65]> {
+        Ok(keys::sign_hash_rsv(secret_key, &self.signing_hash()?.0)?)
+    }
+
+    /// Recovers the address that signed the typed data
+    pub fn recover_signer(&self, signature: &[u8]) -> Result<H160> {
+        Ok(keys::recover_rsv(&self.signing_hash()?.0, signature)?)
+    }
+
+    fn collect_dependencies(&self, kind: &str, found: &mut BTreeSet<String>) -> Result<()> {
+        let kind = element_type(kind).unwrap_or(kind);
+        if found.contains(kind) {
+            return Ok(());
+        }
+        let members = match self.types.get(kind) {
+            Some(members) => members,
+            None if is_atomic_or_dynamic(kind) => return Ok(()),
+            None => return Err(Eip712Error::UnknownType(kind.to_string()).into()),
+        };
+        found.insert(kind.to_string());
+        for member in members {
+            self.collect_dependencies(&member.kind, found)?;
+        }
+        Ok(())
+    }
+
+    /// Encodes a member value into 32 bytes. Structs, arrays, strings and bytes are hashed.
+    fn encode_value(&self, kind: &str, value: &Value) -> Result<H256> {
+        let invalid = || Eip712Error::InvalidValue(kind.to_string());
+        if let Some(element) = element_type(kind) {
+            let elements = value.as_array().ok_or_else(invalid)?;
+            let mut encoded = vec![];
+            for element_value in elements {
+                encoded.extend_from_slice(&self.encode_value(element, element_value)?.0);
+            }
+            return Ok(H256::from(keccak256(&encoded)));
+        }
+        if self.types.contains_key(kind) {
+            return self.hash_struct(kind, value);
+        }
+        match kind {
+            "string" => {
+                let string = value.as_str().ok_or_else(invalid)?;
+                Ok(H256::from(keccak256(string.as_bytes())))
+            }
+            "bytes" => {
+                let bytes = parse_hex(value).ok_or_else(invalid)?;
+                Ok(H256::from(keccak256(&bytes)))
+            }
+            "bool" => {
+                let flag = value.as_bool().ok_or_else(invalid)?;
+                Ok(H256::from_low_u64_be(flag as u64))
+            }
+            "address" => {
+                let address = value
+                    .as_str()
+                    .and_then(|address| keys::parse_address(address).ok())
+                    .ok_or_else(invalid)?;
+                Ok(H256::from(address))
+            }
+            _ if kind.starts_with("bytes") => {
+                let size: usize = kind[5..].parse().map_err(|_| invalid())?;
+                let bytes = parse_hex(value).ok_or_else(invalid)?;
+                if size == 0 || size > 32 || bytes.len() != size {
+                    return Err(invalid().into());
+                }
+                let mut encoded = H256::zero();
+                encoded.0[..size].copy_from_slice(&bytes);
+                Ok(encoded)
+            }
+            _ if kind.starts_with("uint") || kind.starts_with("int") => {
+                let number = parse_integer(value).ok_or_else(invalid)?;
+                check_integer_width(kind, number)?;
+                let mut encoded = H256::zero();
+                number.to_big_endian(&mut encoded.0);
+                Ok(encoded)
+            }
+            _ => Err(Eip712Error::UnknownType(kind.to_string()).into()),
+        }
+    }
+}
+
+/// Returns the type of the elements of an array type, such as `Person` for `Person[]`
+fn element_type(kind: &str) -> Option<&str> {
+    if kind.ends_with(']') {
+        kind.rfind('[').map(|open| &kind[..open])
+    } else {
+        None
+    }
+}
+
+fn is_atomic_or_dynamic(kind: &str) -> bool {
+    kind == "string"
+        || kind == "bool"
+        || kind == "address"
+        || kind.starts_with("bytes")
+        || kind.starts_with("uint")
+        || kind.starts_with("int")
+}
+
+/// Parses a 0x prefixed hex string into bytes
+fn parse_hex(value: &Value) -> Option<Vec<u8>> {
+    let hex = value.as_str()?;
+    let hex = if hex.starts_with("0x") {
+        &hex[2..]
+    } else {
+        hex
+    };
+    hex.from_hex().ok()
+}
+
+/// Parses a JSON number, or a decimal or 0x prefixed hex string. Negative numbers are
+/// returned in two's complement.
+fn parse_integer(value: &Value) -> Option<U256> {
+    if let Some(number) = value.as_u64() {
+        return Some(U256::from(number));
+    }
+    if let Some(number) = value.as_i64() {
+        return Some(negate(U256::from(number.wrapping_neg() as u64)));
+    }
+    let string = value.as_str()?;
+    let (negative, digits) = if string.starts_with('-') {
+        (true, &string[1..])
+    } else {
+        (false, string)
+    };
+    let number = if digits.starts_with("0x") {
+        U256::from_str(&digits[2..]).ok()?
+    } else {
+        U256::from_dec_str(digits).ok()?
+    };
+    Some(if negative { negate(number) } else { number })
+}
+
+/// Checks that a two's complement `number` fits in the bits declared by an `intN` or `uintN`
+/// type
+fn check_integer_width(kind: &str, number: U256) -> Result<()> {
+    let (signed, size) = if kind.starts_with("uint") {
+        (false, &kind[4..])
+    } else {
+        (true, &kind[3..])
+    };
+    let bits: usize = size
+        .parse()
+        .map_err(|_| Eip712Error::UnknownType(kind.to_string()))?;
+    if bits == 0 || bits > 256 || bits % 8 != 0 {
+        return Err(Eip712Error::UnknownType(kind.to_string()).into());
+    }
+    if bits == 256 {
+        return Ok(());
+    }
+    let fits = if !signed {
+        number.bits() <= bits
+    } else if number.bit(255) {
+        negate(number) <= U256::one() << (bits - 1)
+    } else {
+        number.bits() < bits
+    };
+    if fits {
+        Ok(())
+    } else {
+        Err(Eip712Error::IntegerOutOfRange(kind.to_string()).into())
+    }
+}
+
+fn negate(number: U256) -> U256 {
+    (!number).overflowing_add(U256::one()).0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secp256k1;
+
+    fn h256(hex: &str) -> H256 {
+        H256::from_slice(&hex.from_hex().unwrap())
+    }
+
+    /// The example of the EIP-712 specification
+    fn mail() -> TypedData {
+        TypedData::from_json(
+            r#"{
+                "types": {
+                    "EIP712Domain": [
+                        { "name": "name", "type": "string" },
+                        { "name": "version", "type": "string" },
+                        { "name": "chainId", "type": "uint256" },
+                        { "name": "verifyingContract", "type": "address" }
+                    ],
+                    "Person": [
+                        { "name": "name", "type": "string" },
+                        { "name": "wallet", "type": "address" }
+                    ],
+                    "Mail": [
+                        { "name": "from", "type": "Person" },
+                        { "name": "to", "type": "Person" },
+                        { "name": "contents", "type": "string" }
+                    ]
+                },
+                "primaryType": "Mail",
+                "domain": {
+                    "name": "Ether Mail",
+                    "version": "1",
+                    "chainId": 1,
+                    "verifyingContract": "0xCcCCccccCCCCcCCCCCCcCcCccCcCCCcCcccccccC"
+                },
+                "message": {
+                    "from": {
+                        "name": "Cow",
+                        "wallet": "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826"
+                    },
+                    "to": {
+                        "name": "Bob",
+                        "wallet": "0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB"
+                    },
+                    "contents": "Hello, Bob!"
+                }
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn hashes_specification_example() {
+        let mail = mail();
+        assert_eq!(
+            mail.encode_type("Mail").unwrap(),
+            "Mail(Person from,Person to,string contents)Person(string name,address wallet)"
+        );
+        assert_eq!(
+            mail.type_hash("Mail").unwrap(),
+            h256("a0cedeb2dc280ba39b857546d74f5549c3a1d7bdc2dd96bf881f76108e23dac2")
+        );
+        assert_eq!(
+            mail.hash_struct("Mail", &mail.message).unwrap(),
+            h256("c52c0ee5d84264471806290a3f2c4cecfc5490626bf912d01f240d7a274b371e")
+        );
+        assert_eq!(
+            mail.domain_separator().unwrap(),
+            h256("f2cee375fa42b42143804025fc449deafd50cc031ca257e0b194a650a912090f")
+        );
+        assert_eq!(
+            mail.signing_hash().unwrap(),
+            h256("be609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2")
+        );
+    }
+
+    #[test]
+    fn signs_specification_example() {
+        let context = secp256k1::Secp256k1::with_caps(secp256k1::ContextFlag::Full);
+        let secret_key = SecretKey::from_slice(&context, &keccak256(b"cow")).unwrap();
+        let mail = mail();
+        let signature = mail.sign(&secret_key).unwrap();
+        assert_eq!(
+            H256::from_slice(&signature[..32]),
+            h256("4355c47d63924e8a72e509b65029052eb6c299d53a04e167c5775fd466751c9d")
+        );
+        assert_eq!(
+            H256::from_slice(&signature[32..64]),
+            h256("07299936d304c153f6443dfa05f40ff007d72911b6f72307f996231605b91562")
+        );
+        assert_eq!(signature[64], 28);
+        assert_eq!(
+            mail.recover_signer(&signature).unwrap(),
+            keys::parse_address("0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826").unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_types_that_are_not_structs() {
+        let mail = mail();
+        for name in &["uint256", "string", "Mail[]", "Unknown"] {
+            match mail
+                .encode_type(name)
+                .unwrap_err()
+                .downcast_ref::<Eip712Error>()
+            {
+                Some(Eip712Error::UnknownType(_)) => (),
+                other => panic!("expected an unknown type for {}, got {:?}", name, other),
+            }
+            assert!(mail.type_hash(name).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_integers_wider_than_their_type() {
+        let typed_data = TypedData {
+            types: BTreeMap::new(),
+            primary_type: "Mail".to_string(),
+            domain: Value::Null,
+            message: Value::Null,
+        };
+        let encode = |kind: &str, value: &str| {
+            typed_data.encode_value(kind, &serde_json::from_str(value).unwrap())
+        };
+        assert!(encode("uint8", "255").is_ok());
+        assert!(encode("uint8", "256").is_err());
+        assert!(encode("uint8", "-1").is_err());
+        assert!(encode("uint16", "\"0x10000\"").is_err());
+        assert!(encode("int8", "127").is_ok());
+        assert!(encode("int8", "-128").is_ok());
+        assert!(encode("int8", "128").is_err());
+        assert!(encode("int8", "-129").is_err());
+        assert!(encode(
+            "uint256",
+            "\"0xffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff\""
+        )
+        .is_ok());
+        assert!(encode("uint7", "1").is_err());
+        assert!(encode("uint", "1").is_err());
+        assert!(encode("int", "1").is_err());
+    }
+
+    #[test]
+    fn encodes_arrays_integers_and_bytes() {
+        let mut types = BTreeMap::new();
+        types.insert(
+            "Group".to_string(),
+            serde_json::from_str(
+                r#"[
+                    { "name": "name", "type": "string" },
+                    { "name": "ids", "type": "uint256[]" },
+                    { "name": "delta", "type": "int8" },
+                    { "name": "data", "type": "bytes" },
+                    { "name": "flag", "type": "bool" },
+                    { "name": "tag", "type": "bytes4" }
+                ]"#,
+            )
+            .unwrap(),
+        );
+        let typed_data = TypedData {
+            types,
+            primary_type: "Group".to_string(),
+            domain: Value::Null,
+            message: Value::Null,
+        };
+        let group: Value = serde_json::from_str(
+            r#"{
+                "name": "devs",
+                "ids": [1, "0x20"],
+                "delta": -5,
+                "data": "0xbeef",
+                "flag": true,
+                "tag": "0x01020304"
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            typed_data.hash_struct("Group", &group).unwrap(),
+            h256("3c93beeecc92b1285df6f50edd66d21691b093cd0fa3d7d85a3db5928fe38a81")
+        );
+        let mut missing = group.clone();
+        missing.as_object_mut().unwrap().remove("tag");
+        assert!(typed_data.hash_struct("Group", &missing).is_err());
+        assert!(typed_data.domain_separator().is_err());
+    }
+}
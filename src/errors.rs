@@ -1,4 +1,5 @@
 //! This module contains errors related to the Fantom VM itself
+use ethereum_types::{H160, U256};
 use failure::Error;
 
 /// Convenience wrapper around T and a VMError
@@ -17,6 +18,30 @@ pub enum VMError {
     NoSender,
     #[fail(display = "No code in account")]
     NoCodeInAccount,
+    #[fail(display = "stack underflow")]
+    StackUnderflow,
+    #[fail(display = "stack overflow")]
+    StackOverflow,
+    #[fail(display = "out of gas")]
+    OutOfGas,
+    #[fail(display = "insufficient balance")]
+    InsufficientBalance,
+    #[fail(display = "contract address already in use")]
+    AddressCollision,
+    #[fail(display = "contract code exceeds the maximum size")]
+    CodeSizeLimit,
+    #[fail(display = "contract code starts with the reserved 0xEF byte")]
+    InvalidCodePrefix,
+    #[fail(display = "state modification in a static call")]
+    StaticStateChange,
+    #[fail(display = "jump to an invalid destination")]
+    InvalidJump,
+    #[fail(display = "return data read out of bounds")]
+    ReturnDataOutOfBounds,
+    #[fail(display = "init code exceeds the maximum size")]
+    InitCodeSizeLimit,
+    #[fail(display = "execution reverted")]
+    Revert,
 }
 
 #[derive(Debug, Clone, Fail)]
@@ -31,3 +56,115 @@ pub enum StorageError {
     #[fail(display = "already committed")]
     AlreadyCommitted,
 }
+
+#[derive(Debug, Clone, Fail)]
+/// Errors that make a transaction invalid, so it cannot be executed
+pub enum ExecutorError {
+    #[fail(display = "invalid nonce: expected {}, found {}", expected, found)]
+    InvalidNonce { expected: U256, found: U256 },
+    #[fail(display = "gas limit is lower than the intrinsic gas")]
+    IntrinsicGasTooLow,
+    #[fail(display = "gas limit is too high")]
+    GasLimitTooHigh,
+    #[fail(display = "insufficient balance to pay for gas and value")]
+    InsufficientBalance,
+    #[fail(display = "max priority fee per gas is higher than max fee per gas")]
+    PriorityFeeTooHigh,
+    #[fail(display = "max fee per gas is lower than the block base fee")]
+    FeeCapTooLow,
+    #[fail(display = "max fee per blob gas is lower than the block blob base fee")]
+    BlobFeeCapTooLow,
+    #[fail(display = "blob transaction carries no blobs")]
+    NoBlobs,
+    #[fail(display = "transaction gas limit exceeds the gas left in the block")]
+    BlockGasLimitExceeded,
+    #[fail(display = "init code exceeds the maximum size")]
+    InitCodeTooLarge,
+}
+
+#[derive(Debug, Clone, Fail)]
+/// Errors that make a block invalid
+pub enum BlockError {
+    #[fail(display = "transactions root does not match the header")]
+    TransactionsRootMismatch,
+    #[fail(display = "gas used does not match the header")]
+    GasUsedMismatch,
+    #[fail(display = "logs bloom does not match the header")]
+    LogsBloomMismatch,
+    #[fail(display = "receipts root does not match the header")]
+    ReceiptsRootMismatch,
+    #[fail(display = "state root does not match the header")]
+    StateRootMismatch,
+}
+
+#[derive(Debug, Clone, Fail)]
+/// Errors related to parsing addresses
+pub enum AddressError {
+    #[fail(display = "address must be 20 hex encoded bytes")]
+    InvalidAddress,
+    #[fail(display = "address does not match its EIP-55 checksum")]
+    InvalidChecksum,
+}
+
+#[derive(Debug, Clone, Fail)]
+/// Errors related to mnemonics and hierarchical deterministic keys
+pub enum HdWalletError {
+    #[fail(display = "invalid mnemonic")]
+    InvalidMnemonic,
+    #[fail(display = "mnemonics have 12, 15, 18, 21 or 24 words, not {}", _0)]
+    InvalidWordCount(usize),
+    #[fail(display = "invalid derivation path {}", _0)]
+    InvalidDerivationPath(String),
+    #[fail(display = "derived key is invalid")]
+    InvalidChildKey,
+}
+
+#[derive(Debug, Clone, Fail)]
+/// Errors related to Web3 Secret Storage key files
+pub enum KeystoreError {
+    #[fail(display = "unsupported key derivation function {}", _0)]
+    UnsupportedKdf(String),
+    #[fail(display = "key file has a missing or invalid {}", _0)]
+    InvalidField(&'static str),
+    #[fail(
+        display = "scrypt parameters n = {}, r = {}, p = {} exceed the allowed cost",
+        n, r, p
+    )]
+    ScryptCostTooHigh { n: usize, r: usize, p: usize },
+    #[fail(display = "unsupported cipher {}", _0)]
+    UnsupportedCipher(String),
+    #[fail(display = "invalid password")]
+    InvalidPassword,
+    #[fail(display = "private key must be 32 hex encoded bytes")]
+    InvalidPrivateKey,
+    #[fail(display = "no key file for account {:?}", _0)]
+    UnknownAccount(H160),
+    #[fail(display = "account {:?} already exists", _0)]
+    AccountExists(H160),
+    #[fail(display = "account {:?} is locked", _0)]
+    AccountLocked(H160),
+}
+
+#[derive(Debug, Clone, Fail)]
+/// Errors related to transaction signatures
+pub enum TransactionError {
+    #[fail(display = "invalid signature")]
+    InvalidSignature,
+    #[fail(display = "signature s value is too high")]
+    HighS,
+    #[fail(display = "transaction is for chain {}, not {}", found, expected)]
+    InvalidChainId { expected: U256, found: u64 },
+}
+
+#[derive(Debug, Clone, Fail)]
+/// Errors related to EIP-712 typed structured data
+pub enum Eip712Error {
+    #[fail(display = "unknown type {}", _0)]
+    UnknownType(String),
+    #[fail(display = "missing value for member {}", _0)]
+    MissingField(String),
+    #[fail(display = "invalid value for type {}", _0)]
+    InvalidValue(String),
+    #[fail(display = "value out of range for type {}", _0)]
+    IntegerOutOfRange(String),
+}
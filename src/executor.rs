@@ -0,0 +1,673 @@
+//! Contains the executor that applies transactions to the account state held by the VM
+
+use access_set::AccessSet;
+use block_env::Hardfork;
+use errors::{ExecutorError, Result, TransactionError};
+use ethereum_types::{H160, U256};
+use gas_prices::{self, INITCODE_WORD_GAS};
+use receipt::Receipt;
+use std::cmp;
+use transaction::{contract_address, Transaction};
+use typed_transaction::TypedTransaction;
+use vm::{self, VM};
+
+/// Gas paid by every transaction
+pub const TX_GAS: usize = 21_000;
+/// Additional gas paid by contract creation transactions
+pub const TX_CREATE_GAS: usize = 32_000;
+/// Gas paid for every zero byte of transaction data
+pub const TX_DATA_ZERO_GAS: usize = 4;
+/// Gas paid for every non-zero byte of transaction data
+pub const TX_DATA_NON_ZERO_GAS: usize = 16;
+/// Gas paid for every address in the access list (EIP-2930)
+pub const TX_ACCESS_LIST_ADDRESS_GAS: usize = 2_400;
+/// Gas paid for every storage key in the access list (EIP-2930)
+pub const TX_ACCESS_LIST_STORAGE_KEY_GAS: usize = 1_900;
+/// At most `gas_used / MAX_REFUND_QUOTIENT` is given back as a refund (EIP-3529)
+pub const MAX_REFUND_QUOTIENT: usize = 5;
+/// Refund quotient used before London
+pub const LEGACY_REFUND_QUOTIENT: usize = 2;
+/// Gas paid for every byte of deployed contract code
+pub const CODE_DEPOSIT_GAS: usize = 200;
+/// Maximum size of deployed contract code (EIP-170)
+pub const MAX_CODE_SIZE: usize = 24_576;
+/// Maximum size of init code, from Shanghai on (EIP-3860)
+pub const MAX_INITCODE_SIZE: usize = 2 * MAX_CODE_SIZE;
+/// Precompiled contracts live at addresses 1 through PRECOMPILE_COUNT
+pub const PRECOMPILE_COUNT: u64 = 10;
+
+/// Applies transactions, one at a time, to the state held by a VM
+pub struct TransactionExecutor<'a> {
+    vm: &'a mut VM,
+}
+
+impl<'a> TransactionExecutor<'a> {
+    /// Creates and returns a new TransactionExecutor
+    pub fn new(vm: &'a mut VM) -> TransactionExecutor<'a> {
+        TransactionExecutor { vm }
+    }
+
+    /// Executes `transaction` on behalf of `sender`. Invalid transactions are rejected with an
+    /// error and leave the state untouched. Otherwise the gas is bought at the effective gas
+    /// price, the transaction runs, unused gas and refunds go back to the sender and the
+    /// priority fee is paid to the coinbase. The base fee and the blob fee are burned. A
+    /// failed execution is reverted but still pays for its gas.
+    pub fn execute<T: Into<TypedTransaction>>(
+        &mut self,
+        transaction: T,
+        sender: H160,
+    ) -> Result<Receipt> {
+        let transaction = transaction.into();
+        let gas_limit = self.validate(&transaction, sender)?;
+        let base_fee = self.vm.block_env().base_fee;
+        let gas_price = transaction.effective_gas_price(base_fee);
+        let blob_fee = transaction.blob_gas() * self.vm.block_env().blob_base_fee;
+
+        let balance = self.vm.balance(sender);
+        self.vm.set_balance(
+            sender,
+            balance - U256::from(gas_limit) * gas_price - blob_fee,
+        );
+        let nonce = self.vm.nonce(sender);
+        self.vm.set_nonce(sender, nonce + U256::one());
+        let target = transaction
+            .to()
+            .unwrap_or_else(|| contract_address(sender, transaction.nonce()));
+        let coinbase = self.vm.block_env().coinbase;
+        self.vm
+            .start_transaction(initial_access_set(&transaction, sender, target, coinbase));
+
+        let hardfork = self.vm.block_env().hardfork;
+        let execution_gas = gas_limit - intrinsic_gas(&transaction, hardfork);
+        let checkpoint = self.vm.checkpoint();
+        let context = call_context(&transaction, gas_price);
+        let (result, created_address) = match transaction.to() {
+            Some(to) => (self.call(context, sender, to, execution_gas), None),
+            None => {
+                let result = self.create(context, sender, target, execution_gas);
+                (result, Some(target))
+            }
+        };
+        let reverted = vm::is_revert(&result);
+        let status = match result {
+            Ok(()) => {
+                self.vm.commit(checkpoint);
+                true
+            }
+            Err(_) => {
+                self.vm.revert(checkpoint);
+                false
+            }
+        };
+
+        // A REVERT keeps the gas left, any other failure consumes all of it
+        let gas_left = if status || reverted {
+            self.vm.gas_left()
+        } else {
+            0
+        };
+        let gas_used = gas_limit - gas_left;
+        let refund = if status {
+            let quotient = if self.vm.block_env().hardfork < Hardfork::London {
+                LEGACY_REFUND_QUOTIENT
+            } else {
+                MAX_REFUND_QUOTIENT
+            };
+            cmp::min(self.vm.gas_refund(), gas_used / quotient)
+        } else {
+            0
+        };
+        let gas_used = gas_used - refund;
+
+        let balance = self.vm.balance(sender);
+        self.vm.set_balance(
+            sender,
+            balance + U256::from(gas_limit - gas_used) * gas_price,
+        );
+        let coinbase = self.vm.block_env().coinbase;
+        let coinbase_balance = self.vm.balance(coinbase);
+        self.vm.set_balance(
+            coinbase,
+            coinbase_balance + U256::from(gas_used) * (gas_price - base_fee),
+        );
+        self.vm.commit_storage();
+        self.vm.destroy_accounts();
+
+        let mut receipt = Receipt::new(
+            transaction.transaction_type(),
+            status,
+            U256::from(gas_used),
+            self.vm.take_logs(),
+        );
+        receipt.contract_address = created_address;
+        Ok(receipt)
+    }
+
+    /// Recovers the sender from the signature and executes the transaction. Transactions
+    /// signed for another chain are rejected.
+    pub fn execute_signed<T: Into<TypedTransaction>>(&mut self, transaction: T) -> Result<Receipt> {
+        let transaction = transaction.into();
+        if let Some(chain_id) = transaction.chain_id() {
+            let expected = self.vm.block_env().chain_id;
+            if U256::from(chain_id) != expected {
+                return Err(TransactionError::InvalidChainId {
+                    expected,
+                    found: chain_id,
+                }
+                .into());
+            }
+        }
+        let sender = transaction.recover_sender()?;
+        self.execute(transaction, sender)
+    }
+
+    /// Checks the transaction can be executed, returning its gas limit
+    fn validate(&self, transaction: &TypedTransaction, sender: H160) -> Result<usize> {
+        let nonce = self.vm.nonce(sender);
+        if transaction.nonce() != nonce {
+            return Err(ExecutorError::InvalidNonce {
+                expected: nonce,
+                found: transaction.nonce(),
+            }
+            .into());
+        }
+        if transaction.gas_limit() > U256::from(u64::max_value()) {
+            return Err(ExecutorError::GasLimitTooHigh.into());
+        }
+        let gas_limit = transaction.gas_limit().as_u64() as usize;
+        let hardfork = self.vm.block_env().hardfork;
+        if transaction.to().is_none()
+            && hardfork >= Hardfork::Shanghai
+            && transaction.data().len() > MAX_INITCODE_SIZE
+        {
+            return Err(ExecutorError::InitCodeTooLarge.into());
+        }
+        if gas_limit < intrinsic_gas(transaction, hardfork) {
+            return Err(ExecutorError::IntrinsicGasTooLow.into());
+        }
+        if transaction.max_priority_fee_per_gas() > transaction.max_fee_per_gas() {
+            return Err(ExecutorError::PriorityFeeTooHigh.into());
+        }
+        let block_env = self.vm.block_env();
+        if transaction.max_fee_per_gas() < block_env.base_fee {
+            return Err(ExecutorError::FeeCapTooLow.into());
+        }
+        if let TypedTransaction::Blob(ref blob) = *transaction {
+            if blob.blob_versioned_hashes.is_empty() {
+                return Err(ExecutorError::NoBlobs.into());
+            }
+            if blob.max_fee_per_blob_gas < block_env.blob_base_fee {
+                return Err(ExecutorError::BlobFeeCapTooLow.into());
+            }
+        }
+        let upfront_cost = transaction
+            .gas_limit()
+            .checked_mul(transaction.max_fee_per_gas())
+            .and_then(|cost| cost.checked_add(transaction.value()))
+            .and_then(|cost| {
+                transaction
+                    .blob_gas()
+                    .checked_mul(transaction.max_fee_per_blob_gas())
+                    .and_then(|blob_cost| cost.checked_add(blob_cost))
+            })
+            .ok_or(ExecutorError::InsufficientBalance)?;
+        if self.vm.balance(sender) < upfront_cost {
+            return Err(ExecutorError::InsufficientBalance.into());
+        }
+        Ok(gas_limit)
+    }
+
+    /// Transfers the value and runs the code deployed at `to`
+    fn call(&mut self, transaction: Transaction, sender: H160, to: H160, gas: usize) -> Result<()> {
+        self.vm.transfer(sender, to, transaction.value)?;
+        self.vm.set_transaction(transaction, sender);
+        self.vm.set_gas_limit(gas);
+        self.vm.execute()
+    }
+
+    /// Runs the transaction data as init code and deploys the code it returns at `address`
+    fn create(
+        &mut self,
+        transaction: Transaction,
+        sender: H160,
+        address: H160,
+        gas: usize,
+    ) -> Result<()> {
+        self.vm.create_account(sender, address, transaction.value)?;
+        self.vm.set_transaction(transaction, sender);
+        self.vm.set_gas_limit(gas);
+        self.vm.execute()?;
+        let code = self.vm.return_data().to_vec();
+        self.vm.deploy_code(address, code)
+    }
+}
+
+/// Builds the addresses and storage keys that are warm when the transaction starts: its
+/// access list, the sender, the recipient or created contract, the coinbase (EIP-3651) and
+/// the precompiles
+fn initial_access_set(
+    transaction: &TypedTransaction,
+    sender: H160,
+    target: H160,
+    coinbase: H160,
+) -> AccessSet {
+    let mut access_set = AccessSet::from_access_list(transaction.access_list());
+    access_set.insert_address(sender);
+    access_set.insert_address(target);
+    access_set.insert_address(coinbase);
+    for precompile in 1..=PRECOMPILE_COUNT {
+        access_set.insert_address(H160::from_low_u64_be(precompile));
+    }
+    access_set
+}
+
+/// Builds the legacy transaction the VM sees while running `transaction`, which exposes the
+/// effective gas price through GASPRICE
+fn call_context(transaction: &TypedTransaction, gas_price: U256) -> Transaction {
+    Transaction {
+        nonce: transaction.nonce(),
+        gas_price,
+        start_gas: transaction.gas_limit(),
+        to: transaction.to(),
+        value: transaction.value(),
+        data: transaction.data().to_vec(),
+        ..Default::default()
+    }
+}
+
+/// Gas charged before any code runs under `hardfork`: the base cost, the transaction data,
+/// the contract creation cost, which includes the init code words from Shanghai on (EIP-3860),
+/// and the access list
+pub fn intrinsic_gas(transaction: &TypedTransaction, hardfork: Hardfork) -> usize {
+    let data_gas: usize = transaction
+        .data()
+        .iter()
+        .map(|b| {
+            if *b == 0 {
+                TX_DATA_ZERO_GAS
+            } else {
+                TX_DATA_NON_ZERO_GAS
+            }
+        })
+        .sum();
+    let create_gas = if transaction.to().is_some() {
+        0
+    } else if hardfork >= Hardfork::Shanghai {
+        TX_CREATE_GAS + gas_prices::word_count(transaction.data().len()) * INITCODE_WORD_GAS
+    } else {
+        TX_CREATE_GAS
+    };
+    let access_list_gas: usize = transaction
+        .access_list()
+        .iter()
+        .map(|item| {
+            TX_ACCESS_LIST_ADDRESS_GAS + TX_ACCESS_LIST_STORAGE_KEY_GAS * item.storage_keys.len()
+        })
+        .sum();
+    TX_GAS + data_gas + create_gas + access_list_gas
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use block_env::BlockEnv;
+    use ethereum_types::H256;
+    use rustc_serialize::hex::FromHex;
+    use typed_transaction::{AccessListItem, AccessListTransaction, DynamicFeeTransaction};
+
+    fn sender() -> H160 {
+        H160::from_low_u64_be(1)
+    }
+
+    fn recipient() -> H160 {
+        H160::from_low_u64_be(2)
+    }
+
+    fn coinbase() -> H160 {
+        H160::from_low_u64_be(3)
+    }
+
+    /// Address that signed the EIP-155 transaction
+    fn signer() -> H160 {
+        H160::from_slice(
+            &"9d8a62f656a8d1615c1294fd71e9cfb3e4855a4f"
+                .from_hex()
+                .unwrap(),
+        )
+    }
+
+    fn funded_vm() -> VM {
+        let mut env = BlockEnv::default();
+        env.coinbase = coinbase();
+        let mut vm = VM::default().with_block_env(env);
+        vm.set_balance(sender(), U256::from(1_000_000_000u64));
+        vm
+    }
+
+    fn transfer_transaction() -> Transaction {
+        Transaction {
+            gas_price: U256::from(2u64),
+            start_gas: U256::from(21_000u64),
+            to: Some(recipient()),
+            value: U256::from(1_000u64),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn intrinsic_gas_counts_data_bytes() {
+        let transaction = Transaction {
+            to: Some(recipient()),
+            data: vec![0, 1, 0, 2],
+            ..Default::default()
+        };
+        assert_eq!(
+            intrinsic_gas(&transaction.into(), Hardfork::Cancun),
+            21_000 + 2 * 4 + 2 * 16
+        );
+    }
+
+    fn access_list() -> Vec<AccessListItem> {
+        vec![AccessListItem {
+            address: recipient(),
+            storage_keys: vec![H256::zero(), H256::from_low_u64_be(1)],
+        }]
+    }
+
+    #[test]
+    fn intrinsic_gas_counts_access_list() {
+        let transaction = AccessListTransaction {
+            to: Some(recipient()),
+            access_list: access_list(),
+            ..Default::default()
+        };
+        assert_eq!(
+            intrinsic_gas(&transaction.into(), Hardfork::Cancun),
+            21_000 + 2_400 + 2 * 1_900
+        );
+    }
+
+    fn dynamic_fee_transaction() -> DynamicFeeTransaction {
+        DynamicFeeTransaction {
+            max_priority_fee_per_gas: U256::from(2u64),
+            max_fee_per_gas: U256::from(100u64),
+            gas_limit: U256::from(21_000u64),
+            to: Some(recipient()),
+            value: U256::from(1_000u64),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn burns_base_fee_and_pays_priority_fee() {
+        let vm = funded_vm();
+        let mut env = vm.block_env().clone();
+        env.base_fee = U256::from(10u64);
+        let mut vm = vm.with_block_env(env);
+        let receipt = TransactionExecutor::new(&mut vm)
+            .execute(dynamic_fee_transaction(), sender())
+            .unwrap();
+        assert!(receipt.status);
+        assert_eq!(vm.balance(coinbase()), U256::from(21_000u64 * 2));
+        assert_eq!(
+            vm.balance(sender()),
+            U256::from(1_000_000_000u64 - 1_000 - 21_000 * 12)
+        );
+    }
+
+    #[test]
+    fn rejects_fee_cap_below_base_fee() {
+        let mut env = BlockEnv::default();
+        env.base_fee = U256::from(101u64);
+        let mut vm = funded_vm().with_block_env(env);
+        assert!(TransactionExecutor::new(&mut vm)
+            .execute(dynamic_fee_transaction(), sender())
+            .is_err());
+
+        let mut transaction = dynamic_fee_transaction();
+        transaction.max_priority_fee_per_gas = U256::from(200u64);
+        let mut vm = funded_vm();
+        assert!(TransactionExecutor::new(&mut vm)
+            .execute(transaction, sender())
+            .is_err());
+        assert_eq!(vm.nonce(sender()), U256::zero());
+    }
+
+    #[test]
+    fn warms_access_list() {
+        let mut vm = funded_vm();
+        let mut transaction = dynamic_fee_transaction();
+        transaction.gas_limit = U256::from(30_000u64);
+        transaction.access_list = access_list();
+        let receipt = TransactionExecutor::new(&mut vm)
+            .execute(transaction, sender())
+            .unwrap();
+        assert_eq!(receipt.gas_used, U256::from(21_000u64 + 2_400 + 2 * 1_900));
+        assert!(vm.access_set().contains_address(recipient()));
+        assert!(vm
+            .access_set()
+            .contains_storage_key(recipient(), H256::from_low_u64_be(1)));
+        assert!(!vm
+            .access_set()
+            .contains_storage_key(recipient(), H256::from_low_u64_be(2)));
+    }
+
+    #[test]
+    fn reverted_transaction_keeps_gas_left() {
+        let mut vm = funded_vm();
+        // PUSH1 0x00 PUSH1 0x00 REVERT
+        vm.set_code(recipient(), vec![0x60, 0x00, 0x60, 0x00, 0xfd]);
+        let mut transaction = transfer_transaction();
+        transaction.start_gas = U256::from(30_000u64);
+        let receipt = TransactionExecutor::new(&mut vm)
+            .execute(transaction, sender())
+            .unwrap();
+        assert!(!receipt.status);
+        assert_eq!(receipt.gas_used, U256::from(21_000u64 + 6));
+        assert!(vm.balance(recipient()).is_zero());
+    }
+
+    #[test]
+    fn executes_value_transfer() {
+        let mut vm = funded_vm();
+        let receipt = TransactionExecutor::new(&mut vm)
+            .execute(transfer_transaction(), sender())
+            .unwrap();
+        assert!(receipt.status);
+        assert_eq!(receipt.gas_used, U256::from(21_000u64));
+        assert_eq!(vm.balance(recipient()), U256::from(1_000u64));
+        assert_eq!(vm.balance(coinbase()), U256::from(42_000u64));
+        assert_eq!(
+            vm.balance(sender()),
+            U256::from(1_000_000_000u64 - 1_000 - 42_000)
+        );
+        assert_eq!(vm.nonce(sender()), U256::one());
+    }
+
+    /// The signed transaction from the EIP-155 specification, with its sender funded
+    fn signed_transaction(chain_id: u64) -> (VM, Transaction) {
+        let raw = "f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a76400008025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83"
+            .from_hex()
+            .unwrap();
+        let transaction = Transaction::from_raw(&raw).unwrap();
+        let mut env = BlockEnv::default();
+        env.chain_id = U256::from(chain_id);
+        let mut vm = VM::default().with_block_env(env);
+        vm.set_balance(signer(), U256::from(2_000_000_000_000_000_000u64));
+        vm.set_nonce(signer(), U256::from(9u64));
+        (vm, transaction)
+    }
+
+    #[test]
+    fn executes_signed_transaction() {
+        let (mut vm, transaction) = signed_transaction(1);
+        let receipt = TransactionExecutor::new(&mut vm)
+            .execute_signed(transaction)
+            .unwrap();
+        assert!(receipt.status);
+        assert_eq!(vm.nonce(signer()), U256::from(10u64));
+    }
+
+    #[test]
+    fn rejects_signed_transaction_for_other_chain() {
+        let (mut vm, transaction) = signed_transaction(250);
+        assert!(TransactionExecutor::new(&mut vm)
+            .execute_signed(transaction)
+            .is_err());
+        assert_eq!(vm.nonce(signer()), U256::from(9u64));
+    }
+
+    #[test]
+    fn reports_chain_ids_wider_than_64_bits() {
+        let (vm, transaction) = signed_transaction(1);
+        let mut env = vm.block_env().clone();
+        env.chain_id = (U256::one() << 64) + U256::one();
+        let mut vm = vm.with_block_env(env.clone());
+        let err = TransactionExecutor::new(&mut vm)
+            .execute_signed(transaction)
+            .unwrap_err();
+        match err.downcast_ref::<TransactionError>() {
+            Some(TransactionError::InvalidChainId { expected, found }) => {
+                assert_eq!(*expected, env.chain_id);
+                assert_eq!(*found, 1);
+            }
+            other => panic!("expected invalid chain id, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_nonce() {
+        let mut vm = funded_vm();
+        let mut transaction = transfer_transaction();
+        transaction.nonce = U256::from(5u64);
+        assert!(TransactionExecutor::new(&mut vm)
+            .execute(transaction, sender())
+            .is_err());
+        assert_eq!(vm.nonce(sender()), U256::zero());
+        assert_eq!(vm.balance(sender()), U256::from(1_000_000_000u64));
+    }
+
+    #[test]
+    fn rejects_insufficient_balance() {
+        let mut vm = funded_vm();
+        let mut transaction = transfer_transaction();
+        transaction.value = U256::from(1_000_000_000u64);
+        assert!(TransactionExecutor::new(&mut vm)
+            .execute(transaction, sender())
+            .is_err());
+    }
+
+    fn create_transaction(init_code: Vec<u8>) -> Transaction {
+        Transaction {
+            gas_price: U256::one(),
+            start_gas: U256::from(100_000u64),
+            data: init_code,
+            ..Default::default()
+        }
+    }
+
+    /// Init code that writes `runtime` into memory with MSTORE8 and returns it
+    fn init_code_returning(runtime: &[u8]) -> Vec<u8> {
+        let mut code = vec![];
+        for (i, byte) in runtime.iter().enumerate() {
+            code.extend_from_slice(&[0x60, *byte, 0x60, i as u8, 0x53]);
+        }
+        code.extend_from_slice(&[0x60, runtime.len() as u8, 0x60, 0x00, 0xf3]);
+        code
+    }
+
+    #[test]
+    fn deploys_returned_code() {
+        let mut vm = funded_vm();
+        let runtime = vec![0x60, 0x2a, 0x60, 0x00, 0x55];
+        let receipt = TransactionExecutor::new(&mut vm)
+            .execute(create_transaction(init_code_returning(&runtime)), sender())
+            .unwrap();
+        let address = contract_address(sender(), U256::zero());
+        assert!(receipt.status);
+        assert_eq!(receipt.contract_address, Some(address));
+        assert_eq!(vm.code(address), runtime);
+        assert_eq!(vm.nonce(address), U256::one());
+        // intrinsic with one init code word, 12 PUSH1, one word of memory and code deposit
+        assert_eq!(receipt.gas_used, U256::from(53_444u64 + 2 + 36 + 3 + 1_000));
+    }
+
+    #[test]
+    fn charges_init_code_from_shanghai() {
+        let transaction: TypedTransaction = create_transaction(vec![0; 33]).into();
+        assert_eq!(
+            intrinsic_gas(&transaction, Hardfork::London),
+            53_000 + 33 * 4
+        );
+        assert_eq!(
+            intrinsic_gas(&transaction, Hardfork::Shanghai),
+            53_000 + 33 * 4 + 2 * 2
+        );
+
+        let mut transaction = create_transaction(vec![0; MAX_INITCODE_SIZE + 1]);
+        transaction.start_gas = U256::from(1_000_000u64);
+        let mut vm = funded_vm();
+        let err = TransactionExecutor::new(&mut vm)
+            .execute(transaction.clone(), sender())
+            .unwrap_err();
+        match err.downcast_ref::<ExecutorError>() {
+            Some(ExecutorError::InitCodeTooLarge) => (),
+            other => panic!("expected too large init code, got {:?}", other),
+        }
+        let mut env = vm.block_env().clone();
+        env.hardfork = Hardfork::London;
+        let mut vm = vm.with_block_env(env);
+        assert!(TransactionExecutor::new(&mut vm)
+            .execute(transaction, sender())
+            .is_ok());
+    }
+
+    #[test]
+    fn rejects_code_starting_with_ef() {
+        let mut vm = funded_vm();
+        let receipt = TransactionExecutor::new(&mut vm)
+            .execute(create_transaction(init_code_returning(&[0xef])), sender())
+            .unwrap();
+        assert!(!receipt.status);
+        assert_eq!(receipt.gas_used, U256::from(100_000u64));
+        assert!(vm.code(contract_address(sender(), U256::zero())).is_empty());
+    }
+
+    #[test]
+    fn access_list_warms_storage_keys() {
+        let mut vm = funded_vm();
+        // PUSH1 0x01 SLOAD PUSH1 0x02 SLOAD
+        vm.set_code(recipient(), vec![0x60, 0x01, 0x54, 0x60, 0x02, 0x54]);
+        let mut transaction = dynamic_fee_transaction();
+        transaction.gas_limit = U256::from(100_000u64);
+        transaction.access_list = access_list();
+        let receipt = TransactionExecutor::new(&mut vm)
+            .execute(transaction, sender())
+            .unwrap();
+        assert!(receipt.status);
+        // access list, two PUSH1, a warm and a cold SLOAD
+        assert_eq!(
+            receipt.gas_used,
+            U256::from(21_000u64 + 2_400 + 2 * 1_900 + 6 + 100 + 2_100)
+        );
+    }
+
+    #[test]
+    fn runs_recipient_code() {
+        let mut vm = funded_vm();
+        // PUSH1 0x2a PUSH1 0x00 SSTORE
+        vm.set_code(recipient(), vec![0x60, 0x2a, 0x60, 0x00, 0x55]);
+        let mut transaction = transfer_transaction();
+        transaction.start_gas = U256::from(100_000u64);
+        let receipt = TransactionExecutor::new(&mut vm)
+            .execute(transaction, sender())
+            .unwrap();
+        assert!(receipt.status);
+        // two PUSH1, a cold slot and setting it from zero
+        assert_eq!(receipt.gas_used, U256::from(21_006u64 + 2_100 + 20_000));
+        assert_eq!(
+            vm.storage_at(recipient(), U256::zero()),
+            U256::from(0x2au64)
+        );
+    }
+}
@@ -1,7 +1,47 @@
 //! Contains the Gas cost for all the various Opcodes
 
+use bigint::M256;
+use block_env::Hardfork;
 use opcodes::Opcode;
 
+/// Cost of the first access to an account in a transaction (EIP-2929)
+pub const COLD_ACCOUNT_ACCESS_COST: usize = 2_600;
+/// Cost of the first access to a storage slot in a transaction (EIP-2929)
+pub const COLD_SLOAD_COST: usize = 2_100;
+/// Cost of accessing an account or storage slot that is already warm (EIP-2929)
+pub const WARM_STORAGE_READ_COST: usize = 100;
+/// Cost of setting a storage slot from zero to non-zero
+pub const SSTORE_SET_GAS: usize = 20_000;
+/// Cost of changing a non-zero storage slot, not counting the cold access
+pub const SSTORE_RESET_GAS: usize = 2_900;
+/// Refund for clearing a storage slot (EIP-3529)
+pub const SSTORE_CLEARS_SCHEDULE: usize = 4_800;
+/// Refund for clearing a storage slot before London (EIP-2200)
+pub const LEGACY_SSTORE_CLEARS_SCHEDULE: usize = 15_000;
+/// SSTORE fails unless more than this much gas is left (EIP-2200)
+pub const SSTORE_SENTRY_GAS: usize = 2_300;
+/// Extra cost of a call that transfers value
+pub const CALL_VALUE_TRANSFER_GAS: usize = 9_000;
+/// Extra cost of a call that transfers value to an empty account
+pub const CALL_NEW_ACCOUNT_GAS: usize = 25_000;
+/// Gas given to the callee for free when a call transfers value
+pub const CALL_STIPEND: usize = 2_300;
+/// Gas paid for every byte of the exponent of EXP (EIP-160)
+pub const EXP_BYTE_GAS: usize = 50;
+/// Gas paid for every byte of log data
+pub const LOG_DATA_GAS: usize = 8;
+/// Cost of SELFDESTRUCT, not counting the beneficiary access
+pub const SELFDESTRUCT_GAS: usize = 5_000;
+/// Refund for destroying an account, removed in London (EIP-3529)
+pub const SELFDESTRUCT_REFUND: usize = 24_000;
+
+/// Gas paid for every word of memory
+pub const MEMORY_GAS: usize = 3;
+/// Divisor of the quadratic part of the memory cost
+pub const QUAD_COEFF_DIV: usize = 512;
+/// Gas paid for every word of init code (EIP-3860)
+pub const INITCODE_WORD_GAS: usize = 2;
+
 /// Gets the cost for a specific Opcode. They are grouped by cost.
 pub fn get_cost(op: Opcode) -> Option<usize> {
     match op {
@@ -21,10 +61,13 @@ pub fn get_cost(op: Opcode) -> Option<usize> {
         | Opcode::NUMBER
         | Opcode::DIFFICULTY
         | Opcode::GASLIMIT
+        | Opcode::CHAINID
+        | Opcode::BASEFEE
         | Opcode::POP
         | Opcode::PC
         | Opcode::MSIZE
-        | Opcode::GAS => Some(2),
+        | Opcode::GAS
+        | Opcode::PUSH0 => Some(2),
 
         Opcode::LT
         | Opcode::GT
@@ -55,17 +98,67 @@ pub fn get_cost(op: Opcode) -> Option<usize> {
         Opcode::BLOCKHASH => Some(20),
         Opcode::SHA3 => Some(30),
 
-        Opcode::BALANCE => Some(400),
         Opcode::LOG(0) => Some(375),
         Opcode::LOG(1) => Some(750),
         Opcode::LOG(2) => Some(1125),
         Opcode::LOG(3) => Some(1500),
         Opcode::LOG(4) => Some(1875),
+        Opcode::CREATE => Some(32_000),
 
         _ => None,
     }
 }
 
+/// Gets the number of 32 byte words needed to hold `bytes` bytes
+pub fn word_count(bytes: usize) -> usize {
+    (bytes + 31) / 32
+}
+
+/// Gets the total cost of a memory of `words` words. Expanding the memory costs the
+/// difference between the new and the old total.
+pub fn memory_gas(words: usize) -> usize {
+    MEMORY_GAS * words + words * words / QUAD_COEFF_DIV
+}
+
+/// Gets the cost and the refund change of an SSTORE that sets a warm storage slot to `new`,
+/// given its value at the start of the transaction and its current value (EIP-2200). The
+/// refund for clearing a slot was lowered in London (EIP-3529).
+pub fn sstore_gas(original: M256, current: M256, new: M256, hardfork: Hardfork) -> (usize, isize) {
+    let zero = M256::zero();
+    let clears = if hardfork < Hardfork::London {
+        LEGACY_SSTORE_CLEARS_SCHEDULE as isize
+    } else {
+        SSTORE_CLEARS_SCHEDULE as isize
+    };
+    if current == new {
+        return (WARM_STORAGE_READ_COST, 0);
+    }
+    if original == current {
+        if original == zero {
+            return (SSTORE_SET_GAS, 0);
+        }
+        let refund = if new == zero { clears } else { 0 };
+        return (SSTORE_RESET_GAS, refund);
+    }
+    let mut refund = 0;
+    if original != zero {
+        if current == zero {
+            refund -= clears;
+        } else if new == zero {
+            refund += clears;
+        }
+    }
+    if original == new {
+        let restored = if original == zero {
+            SSTORE_SET_GAS
+        } else {
+            SSTORE_RESET_GAS
+        };
+        refund += (restored - WARM_STORAGE_READ_COST) as isize;
+    }
+    (WARM_STORAGE_READ_COST, refund)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -74,4 +167,37 @@ mod tests {
         let cost = get_cost(Opcode::ADD);
         assert_eq!(cost.unwrap(), 3);
     }
+
+    #[test]
+    fn memory_gas_grows_quadratically() {
+        assert_eq!(word_count(0), 0);
+        assert_eq!(word_count(33), 2);
+        assert_eq!(memory_gas(1), 3);
+        assert_eq!(memory_gas(32), 98);
+        assert_eq!(memory_gas(1024), 3 * 1024 + 2048);
+    }
+
+    #[test]
+    fn sstore_gas_follows_eip_3529() {
+        let (zero, one, two) = (M256::zero(), M256::from(1), M256::from(2));
+        let london = Hardfork::London;
+        assert_eq!(sstore_gas(zero, zero, zero, london), (100, 0));
+        assert_eq!(sstore_gas(zero, zero, one, london), (20_000, 0));
+        assert_eq!(sstore_gas(one, one, two, london), (2_900, 0));
+        assert_eq!(sstore_gas(one, one, zero, london), (2_900, 4_800));
+        assert_eq!(sstore_gas(one, zero, one, london), (100, -4_800 + 2_800));
+        assert_eq!(sstore_gas(zero, one, zero, london), (100, 19_900));
+        assert_eq!(sstore_gas(one, two, zero, london), (100, 4_800));
+    }
+
+    #[test]
+    fn sstore_gas_refunds_more_before_london() {
+        let (zero, one, two) = (M256::zero(), M256::from(1), M256::from(2));
+        let berlin = Hardfork::Berlin;
+        assert_eq!(sstore_gas(zero, zero, one, berlin), (20_000, 0));
+        assert_eq!(sstore_gas(one, one, zero, berlin), (2_900, 15_000));
+        assert_eq!(sstore_gas(one, zero, one, berlin), (100, -15_000 + 2_800));
+        assert_eq!(sstore_gas(zero, one, zero, berlin), (100, 19_900));
+        assert_eq!(sstore_gas(one, two, zero, berlin), (100, 15_000));
+    }
 }
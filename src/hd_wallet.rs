@@ -0,0 +1,187 @@
+//! Contains BIP-39 mnemonics and BIP-32 hierarchical deterministic keys, derived along the
+//! BIP-44 path that Ethereum wallets use
+
+use bip39::{Language, Mnemonic, MnemonicType, Seed};
+use errors::{HdWalletError, Result};
+use hmac::{Hmac, Mac};
+use secp256k1;
+use secp256k1::key::{PublicKey, SecretKey};
+use sha2::Sha512;
+
+/// BIP-44 path of the Ethereum accounts, without the address index
+pub const ETHEREUM_DERIVATION_PATH: &str = "m/44'/60'/0'/0";
+/// Indexes at or above this one derive hardened children
+pub const HARDENED_OFFSET: u32 = 0x8000_0000;
+
+/// Generates a random English mnemonic with 12, 15, 18, 21 or 24 words
+pub fn generate_mnemonic(word_count: usize) -> Result<String> {
+    let mnemonic_type = MnemonicType::for_word_count(word_count)
+        .map_err(|_| HdWalletError::InvalidWordCount(word_count))?;
+    Ok(Mnemonic::new(mnemonic_type, Language::English)
+        .phrase()
+        .to_string())
+}
+
+/// Checks the words and the checksum of an English mnemonic
+pub fn validate_mnemonic(phrase: &str) -> Result<()> {
+    Mnemonic::validate(phrase, Language::English).map_err(|_| HdWalletError::InvalidMnemonic)?;
+    Ok(())
+}
+
+/// Returns the 64 byte seed of a mnemonic, protected by an optional BIP-39 passphrase
+pub fn mnemonic_to_seed(phrase: &str, passphrase: &str) -> Result<Vec<u8>> {
+    let mnemonic = Mnemonic::from_phrase(phrase, Language::English)
+        .map_err(|_| HdWalletError::InvalidMnemonic)?;
+    Ok(Seed::new(&mnemonic, passphrase).as_bytes().to_vec())
+}
+
+/// Returns the path of the Ethereum account with the given index, `m/44'/60'/0'/0/index`
+pub fn ethereum_path(index: u32) -> String {
+    format!("{}/{}", ETHEREUM_DERIVATION_PATH, index)
+}
+
+/// Derives the secret keys of the first `count` Ethereum accounts of a mnemonic, such as the
+/// development accounts of a test network
+pub fn derive_accounts(phrase: &str, passphrase: &str, count: u32) -> Result<Vec<SecretKey>> {
+    let seed = mnemonic_to_seed(phrase, passphrase)?;
+    let parent = ExtendedKey::master(&seed)?.derive_path(ETHEREUM_DERIVATION_PATH)?;
+    (0..count)
+        .map(|index| parent.derive_child(index).map(|key| key.secret_key))
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A BIP-32 extended private key
+pub struct ExtendedKey {
+    /// The private key
+    pub secret_key: SecretKey,
+    /// The chain code that, with the key, derives the children
+    pub chain_code: [u8; 32],
+}
+
+impl ExtendedKey {
+    /// Creates and returns the master key of a seed
+    pub fn master(seed: &[u8]) -> Result<ExtendedKey> {
+        ExtendedKey::from_hmac(b"Bitcoin seed", seed)
+    }
+
+    /// Derives the child with the given index. Indexes from `HARDENED_OFFSET` on derive
+    /// hardened children.
+    pub fn derive_child(&self, index: u32) -> Result<ExtendedKey> {
+        let context = secp256k1::Secp256k1::with_caps(secp256k1::ContextFlag::Full);
+        let mut data = Vec::with_capacity(37);
+        if index >= HARDENED_OFFSET {
+            data.push(0);
+            data.extend_from_slice(&self.secret_key[0..self.secret_key.len()]);
+        } else {
+            let public_key = PublicKey::from_secret_key(&context, &self.secret_key)?;
+            data.extend_from_slice(&public_key.serialize_vec(&context, true));
+        }
+        data.extend_from_slice(&[
+            (index >> 24) as u8,
+            (index >> 16) as u8,
+            (index >> 8) as u8,
+            index as u8,
+        ]);
+        let mut child = ExtendedKey::from_hmac(&self.chain_code, &data)?;
+        child
+            .secret_key
+            .add_assign(&context, &self.secret_key)
+            .map_err(|_| HdWalletError::InvalidChildKey)?;
+        Ok(child)
+    }
+
+    /// Derives the key at `path`, such as `m/44'/60'/0'/0/0`. Hardened steps are marked with
+    /// `'` or `h`.
+    pub fn derive_path(&self, path: &str) -> Result<ExtendedKey> {
+        let mut steps = path.split('/');
+        if steps.next() != Some("m") {
+            return Err(HdWalletError::InvalidDerivationPath(path.to_string()).into());
+        }
+        let mut key = self.clone();
+        for step in steps {
+            let (number, offset) = if step.ends_with('\'') || step.ends_with('h') {
+                (&step[..step.len() - 1], HARDENED_OFFSET)
+            } else {
+                (step, 0)
+            };
+            let index = match number.parse::<u32>() {
+                Ok(index) if index < HARDENED_OFFSET => index + offset,
+                _ => return Err(HdWalletError::InvalidDerivationPath(path.to_string()).into()),
+            };
+            key = key.derive_child(index)?;
+        }
+        Ok(key)
+    }
+
+    /// Splits HMAC-SHA512(key, data) into a private key and a chain code
+    fn from_hmac(key: &[u8], data: &[u8]) -> Result<ExtendedKey> {
+        let mut mac =
+            Hmac::<Sha512>::new_varkey(key).map_err(|_| HdWalletError::InvalidChildKey)?;
+        mac.input(data);
+        let output = mac.result().code();
+        let context = secp256k1::Secp256k1::with_caps(secp256k1::ContextFlag::Full);
+        let secret_key = SecretKey::from_slice(&context, &output[..32])
+            .map_err(|_| HdWalletError::InvalidChildKey)?;
+        let mut chain_code = [0u8; 32];
+        chain_code.copy_from_slice(&output[32..]);
+        Ok(ExtendedKey {
+            secret_key,
+            chain_code,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use keys;
+    use rustc_serialize::hex::{FromHex, ToHex};
+
+    const HARDHAT_MNEMONIC: &str = "test test test test test test test test test test test junk";
+
+    #[test]
+    fn derives_bip32_test_vector() {
+        let seed = "000102030405060708090a0b0c0d0e0f".from_hex().unwrap();
+        let master = ExtendedKey::master(&seed).unwrap();
+        assert_eq!(
+            master.secret_key[0..32].to_hex(),
+            "e8f32e723decf4051aefac8e2c93c9c5b214313817cdb01a1494b917c8436b35"
+        );
+        let child = master.derive_path("m/0'/1").unwrap();
+        assert_eq!(
+            child.secret_key[0..32].to_hex(),
+            "3c6cb8d0f6a264c91ea8b5030fadaa8e538b020f0a387421a12de9319dc93368"
+        );
+        assert!(master.derive_path("0/1").is_err());
+        assert!(master.derive_path("m/x").is_err());
+    }
+
+    #[test]
+    fn derives_hardhat_accounts() {
+        validate_mnemonic(HARDHAT_MNEMONIC).unwrap();
+        let secret_keys = derive_accounts(HARDHAT_MNEMONIC, "", 2).unwrap();
+        assert_eq!(
+            secret_keys[0][0..32].to_hex(),
+            "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80"
+        );
+        let context = secp256k1::Secp256k1::with_caps(secp256k1::ContextFlag::Full);
+        let public_key = PublicKey::from_secret_key(&context, &secret_keys[1]).unwrap();
+        assert_eq!(
+            keys::to_checksum_address(&keys::public_key_to_address(&public_key)),
+            "0x70997970C51812dc3A010C7d01b50e0d17dc79C8"
+        );
+    }
+
+    #[test]
+    fn generates_and_validates_mnemonics() {
+        let phrase = generate_mnemonic(24).unwrap();
+        assert_eq!(phrase.split(' ').count(), 24);
+        validate_mnemonic(&phrase).unwrap();
+        assert!(generate_mnemonic(13).is_err());
+        assert!(
+            validate_mnemonic("test test test test test test test test test test test test")
+                .is_err()
+        );
+    }
+}
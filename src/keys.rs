@@ -1,12 +1,34 @@
+use errors::AddressError;
+use ethereum_types::{H160, U256};
 use rand::rngs::OsRng;
 use rand::RngCore;
 use rpassword::read_password;
+use rustc_serialize::hex::{FromHex, ToHex};
 use secp256k1;
 use secp256k1::key::{PublicKey, SecretKey};
 use secp256k1::Error;
+use secp256k1::{Message, RecoverableSignature, RecoveryId};
 use std::string::ToString;
 use std::{fmt, fs::File};
 use std::{io, io::BufRead, io::BufReader, io::Write};
+use tiny_keccak::keccak256;
+
+/// Order of the secp256k1 curve
+pub const SECP256K1_N: U256 = U256([
+    0xBFD2_5E8C_D036_4141,
+    0xBAAE_DCE6_AF48_A03B,
+    0xFFFF_FFFF_FFFF_FFFE,
+    0xFFFF_FFFF_FFFF_FFFF,
+]);
+
+/// Half the order of the secp256k1 curve. Signatures with a higher `s` value are malleable and
+/// are rejected (EIP-2).
+pub const SECP256K1_HALF_N: U256 = U256([
+    0xDFE9_2F46_681B_20A0,
+    0x5D57_6E73_57A4_501D,
+    0xFFFF_FFFF_FFFF_FFFF,
+    0x7FFF_FFFF_FFFF_FFFF,
+]);
 
 #[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 /// Wrapper type around a String to represent a password
@@ -64,6 +86,175 @@ pub fn generate_random_keypair() -> Result<(PublicKey, SecretKey), Error> {
     Ok((public_key, private_key))
 }
 
+/// Derives the address of a public key: the last 20 bytes of the keccak hash of the
+/// uncompressed key, without its 0x04 prefix.
+pub fn public_key_to_address(public_key: &PublicKey) -> H160 {
+    let context = secp256k1::Secp256k1::with_caps(secp256k1::ContextFlag::Full);
+    let serialized = public_key.serialize_vec(&context, false);
+    let hash = keccak256(&serialized[1..]);
+    H160::from_slice(&hash[12..])
+}
+
+/// Formats an address as hex with the EIP-55 mixed-case checksum, such as
+/// `0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed`
+pub fn to_checksum_address(address: &H160) -> String {
+    let hex = address.0.to_hex();
+    let hash = keccak256(hex.as_bytes());
+    let mut checksummed = String::with_capacity(42);
+    checksummed.push_str("0x");
+    for (i, c) in hex.chars().enumerate() {
+        let nibble = (hash[i / 2] >> (if i % 2 == 0 { 4 } else { 0 })) & 0x0f;
+        if nibble >= 8 {
+            checksummed.extend(c.to_uppercase());
+        } else {
+            checksummed.push(c);
+        }
+    }
+    checksummed
+}
+
+/// Parses a hex address, with or without its 0x prefix. Addresses in a single case are
+/// accepted as is, mixed-case addresses must match their EIP-55 checksum.
+pub fn parse_address(address: &str) -> Result<H160, AddressError> {
+    let hex = if address.starts_with("0x") || address.starts_with("0X") {
+        &address[2..]
+    } else {
+        address
+    };
+    let bytes = match hex.from_hex() {
+        Ok(ref bytes) if bytes.len() == 20 => H160::from_slice(bytes),
+        _ => return Err(AddressError::InvalidAddress),
+    };
+    let is_single_case = hex == hex.to_lowercase() || hex == hex.to_uppercase();
+    if !is_single_case && to_checksum_address(&bytes)[2..] != *hex {
+        return Err(AddressError::InvalidChecksum);
+    }
+    Ok(bytes)
+}
+
+/// Signs `hash` with `secret_key`, returning the 64 byte r || s signature and its recovery id
+pub fn sign_hash(secret_key: &SecretKey, hash: &[u8; 32]) -> Result<([u8; 64], i32), Error> {
+    let context = secp256k1::Secp256k1::with_caps(secp256k1::ContextFlag::Full);
+    let message = Message::from_slice(hash)?;
+    let signature = context.sign_recoverable(&message, secret_key)?;
+    let (recovery_id, signature) = signature.serialize_compact(&context);
+    Ok((signature, recovery_id.to_i32()))
+}
+
+/// Recovers the public key that signed `hash`, given the 64 byte r || s signature and its
+/// recovery id.
+pub fn recover_public_key(
+    hash: &[u8; 32],
+    signature: &[u8; 64],
+    recovery_id: i32,
+) -> Result<PublicKey, Error> {
+    let context = secp256k1::Secp256k1::with_caps(secp256k1::ContextFlag::Full);
+    let message = Message::from_slice(hash)?;
+    let recovery_id = RecoveryId::from_i32(recovery_id)?;
+    let signature = RecoverableSignature::from_compact(&context, signature, recovery_id)?;
+    context.recover(&message, &signature)
+}
+
+/// Hashes a message the way `personal_sign` does: the Keccak hash of
+/// `"\x19Ethereum Signed Message:\n"`, the decimal length of the message and the message
+/// itself (EIP-191 version 0x45)
+pub fn hash_message(message: &[u8]) -> [u8; 32] {
+    let mut preimage = format!("\x19Ethereum Signed Message:\n{}", message.len()).into_bytes();
+    preimage.extend_from_slice(message);
+    keccak256(&preimage)
+}
+
+/// Signs `hash` with `secret_key`, returning the 65 byte r || s || v signature where v is 27
+/// or 28
+pub fn sign_hash_rsv(secret_key: &SecretKey, hash: &[u8; 32]) -> Result<[u8; 65], Error> {
+    let (signature, recovery_id) = sign_hash(secret_key, hash)?;
+    let mut output = [0u8; 65];
+    output[..64].copy_from_slice(&signature);
+    output[64] = 27 + recovery_id as u8;
+    Ok(output)
+}
+
+/// Recovers the address that produced the 65 byte r || s || v `signature` over `hash`. The
+/// recovery id may be given as 27 or 28, or as 0 or 1.
+pub fn recover_rsv(hash: &[u8; 32], signature: &[u8]) -> Result<H160, Error> {
+    if signature.len() != 65 {
+        return Err(Error::InvalidSignature);
+    }
+    let recovery_id = match signature[64] {
+        v @ 27..=28 => v - 27,
+        v @ 0..=1 => v,
+        _ => return Err(Error::InvalidSignature),
+    };
+    let mut compact = [0u8; 64];
+    compact.copy_from_slice(&signature[..64]);
+    let public_key = recover_public_key(hash, &compact, i32::from(recovery_id))?;
+    Ok(public_key_to_address(&public_key))
+}
+
+/// Signs a message with the EIP-191 prefix, returning the 65 byte r || s || v signature where
+/// v is 27 or 28
+pub fn sign_message(secret_key: &SecretKey, message: &[u8]) -> Result<[u8; 65], Error> {
+    sign_hash_rsv(secret_key, &hash_message(message))
+}
+
+/// Recovers the address that signed a message with `sign_message`
+pub fn recover_message_signer(message: &[u8], signature: &[u8]) -> Result<H160, Error> {
+    recover_rsv(&hash_message(message), signature)
+}
+
+/// Source of the passphrase that protects an account, so accounts can be created without a
+/// terminal
+pub trait PassphraseProvider {
+    /// Returns the passphrase, or a description of why it could not be obtained
+    fn passphrase(&self) -> Result<Password, String>;
+}
+
+/// A password supplies itself
+impl PassphraseProvider for Password {
+    fn passphrase(&self) -> Result<Password, String> {
+        Ok(self.clone())
+    }
+}
+
+/// Any callback returning a password is a provider
+impl<F> PassphraseProvider for F
+where
+    F: Fn() -> Result<Password, String>,
+{
+    fn passphrase(&self) -> Result<Password, String> {
+        self()
+    }
+}
+
+/// Prompts for the passphrase on the terminal with `get_passphrase`
+pub struct Prompt;
+
+impl PassphraseProvider for Prompt {
+    fn passphrase(&self) -> Result<Password, String> {
+        get_passphrase()
+    }
+}
+
+/// Reads the passphrase from the environment variable with the given name
+pub struct EnvVar(pub String);
+
+impl PassphraseProvider for EnvVar {
+    fn passphrase(&self) -> Result<Password, String> {
+        std::env::var(&self.0)
+            .map(Password::from)
+            .map_err(|_| format!("Environment variable {} is not set.", self.0))
+    }
+}
+
+/// Reads the passphrase from the first line of a file with `get_passphrase_file`
+pub struct PasswordFile(pub String);
+
+impl PassphraseProvider for PasswordFile {
+    fn passphrase(&self) -> Result<Password, String> {
+        get_passphrase_file(self.0.clone())
+    }
+}
+
 /// Prompts the user for a passphrase. They will have to enter this to do anything with
 /// their account.
 pub fn get_passphrase() -> Result<Password, String> {
@@ -119,11 +310,95 @@ pub fn passwords_from_files(files: &[String]) -> Result<Vec<Password>, String> {
 
 mod tests {
     #[allow(unused_imports)]
-    use super::generate_random_keypair;
+    use super::*;
 
     #[test]
     fn test_generate_random_keypair() {
         let test_keypair = generate_random_keypair();
         assert!(test_keypair.is_ok());
     }
+
+    #[test]
+    fn passphrase_providers() {
+        let password = Password::from("secret");
+        assert_eq!(password.passphrase(), Ok(password.clone()));
+
+        let callback = || -> Result<Password, String> { Ok(Password::from("from callback")) };
+        assert_eq!(callback.passphrase(), Ok(Password::from("from callback")));
+
+        // Names are unique to this process so concurrent test runs don't share them
+        let name = format!("EVM_RS_TEST_PASSPHRASE_{}", std::process::id());
+        std::env::set_var(&name, "from env");
+        let from_env = EnvVar(name.clone()).passphrase();
+        std::env::remove_var(&name);
+        assert_eq!(from_env, Ok(Password::from("from env")));
+        assert!(EnvVar(name).passphrase().is_err());
+
+        let path =
+            std::env::temp_dir().join(format!("evm_rs_test_passphrase_{}", std::process::id()));
+        std::fs::write(&path, "from file\nsecond\n").unwrap();
+        let from_file = PasswordFile(path.to_string_lossy().into_owned()).passphrase();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(from_file, Ok(Password::from("from file")));
+    }
+
+    #[test]
+    fn checksum_addresses() {
+        for expected in &[
+            "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+            "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359",
+            "0xdbF03B407c01E7cD3CBea99509d93f8DDDC8C6FB",
+            "0xD1220A0cf47c7B9Be7A2E6BA89F429762e7b9aDb",
+        ] {
+            let address = parse_address(expected).unwrap();
+            assert_eq!(to_checksum_address(&address), *expected);
+            assert_eq!(parse_address(&expected.to_lowercase()).unwrap(), address);
+        }
+        assert!(parse_address("52908400098527886E0F7030069857D2E4169EE7").is_ok());
+        assert!(parse_address("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAeD").is_err());
+        assert!(parse_address("0x5aaeb6053f3e94c9b9a09f33669435e7ef1bea").is_err());
+    }
+
+    #[test]
+    fn derives_address_from_public_key() {
+        let context = secp256k1::Secp256k1::with_caps(secp256k1::ContextFlag::Full);
+        let secret = "7a28b5ba57c53603b0b07b56bba752f7784bf506fa95edc395f5cf6c7514fe9d"
+            .from_hex()
+            .unwrap();
+        let secret_key = SecretKey::from_slice(&context, &secret).unwrap();
+        let public_key = PublicKey::from_secret_key(&context, &secret_key).unwrap();
+        assert_eq!(
+            to_checksum_address(&public_key_to_address(&public_key)),
+            "0x008AeEda4D805471dF9b2A5B0f38A0C3bCBA786b"
+        );
+    }
+
+    #[test]
+    fn signs_and_recovers_messages() {
+        let context = secp256k1::Secp256k1::with_caps(secp256k1::ContextFlag::Full);
+        let secret = "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80"
+            .from_hex()
+            .unwrap();
+        let secret_key = SecretKey::from_slice(&context, &secret).unwrap();
+        assert_eq!(
+            hash_message(b"hello world").to_hex(),
+            "d9eba16ed0ecae432b71fe008c98cc872bb4cc214d3220a36f365326cf807d68"
+        );
+        let signature = sign_message(&secret_key, b"hello world").unwrap();
+        assert_eq!(
+            signature[..].to_hex(),
+            "a461f509887bd19e312c0c58467ce8ff8e300d3c1a90b608a760c5b80318eaf1\
+             5fe57c96f9175d6cd4daad4663763baa7e78836e067d0163e9a2ccf2ff753f5b1b"
+        );
+        let signer = parse_address("0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266").unwrap();
+        assert_eq!(
+            recover_message_signer(b"hello world", &signature).unwrap(),
+            signer
+        );
+        assert_ne!(
+            recover_message_signer(b"hello", &signature).unwrap(),
+            signer
+        );
+        assert!(recover_message_signer(b"hello world", &signature[..64]).is_err());
+    }
 }
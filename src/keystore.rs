@@ -0,0 +1,379 @@
+//! Contains the KeyStore, which manages a directory of Web3 Secret Storage key files and keeps
+//! unlocked keys in memory
+
+use account::{Account, Kdf};
+use errors::{KeystoreError, Result};
+use ethereum_types::H160;
+use keys;
+use keys::Password;
+use secp256k1::key::SecretKey;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Prefix of the names of the key files managed by a KeyStore
+const KEY_FILE_PREFIX: &str = "UTC--";
+
+/// A secret key kept in memory after its account has been unlocked
+struct UnlockedKey {
+    secret_key: SecretKey,
+    expires: Option<Instant>,
+}
+
+/// Manages the key files in a directory, such as a geth `keystore` directory
+pub struct KeyStore {
+    directory: PathBuf,
+    kdf: Kdf,
+    key_files: HashMap<H160, PathBuf>,
+    unlocked: HashMap<H160, UnlockedKey>,
+}
+
+impl KeyStore {
+    /// Opens the key store in `directory`, creating the directory if needed, and scans it for
+    /// key files
+    pub fn open<P: Into<PathBuf>>(directory: P) -> Result<KeyStore> {
+        let mut key_store = KeyStore {
+            directory: directory.into(),
+            kdf: Kdf::default(),
+            key_files: HashMap::new(),
+            unlocked: HashMap::new(),
+        };
+        fs::create_dir_all(&key_store.directory)?;
+        key_store.refresh()?;
+        Ok(key_store)
+    }
+
+    /// Part of the Builder, sets the KDF used to encrypt new and re-encrypted keys
+    pub fn with_kdf(mut self, kdf: Kdf) -> KeyStore {
+        self.kdf = kdf;
+        self
+    }
+
+    /// Rescans the directory for key files. Files that are not named `UTC--...` or that cannot
+    /// be parsed are ignored.
+    pub fn refresh(&mut self) -> Result<()> {
+        self.key_files.clear();
+        for entry in fs::read_dir(&self.directory)? {
+            let path = entry?.path();
+            let is_key_file = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .map_or(false, |name| name.starts_with(KEY_FILE_PREFIX));
+            if !is_key_file || !path.is_file() {
+                continue;
+            }
+            if let Ok(address) = Account::load(&path).and_then(|account| account.address()) {
+                self.key_files.insert(address, path);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the addresses of the accounts in the key store, in ascending order
+    pub fn accounts(&self) -> Vec<H160> {
+        let mut addresses: Vec<H160> = self.key_files.keys().cloned().collect();
+        addresses.sort();
+        addresses
+    }
+
+    /// Returns true if the key store holds a key file for `address`
+    pub fn contains(&self, address: &H160) -> bool {
+        self.key_files.contains_key(address)
+    }
+
+    /// Loads the account of `address` from its key file
+    pub fn account(&self, address: &H160) -> Result<Account> {
+        Account::load(self.key_file(address)?)
+    }
+
+    /// Creates a new account with a random key, encrypted with `password`
+    pub fn create(&mut self, password: &Password) -> Result<H160> {
+        let (_, secret_key) = keys::generate_random_keypair()?;
+        self.import(&secret_key, password)
+    }
+
+    /// Imports a raw secret key, encrypting it with `password`
+    pub fn import(&mut self, secret_key: &SecretKey, password: &Password) -> Result<H160> {
+        let account =
+            Account::from_secret_key(secret_key, password, self.kdf, self.directory.clone())?;
+        self.insert(account)
+    }
+
+    /// Imports the JSON of a key file exported by `export` or by another wallet. Nothing is
+    /// written unless it is a version 3 key file with a supported cipher and KDF.
+    pub fn import_json(&mut self, json: &str) -> Result<H160> {
+        let account: Account = serde_json::from_str(json)?;
+        account.validate()?;
+        self.insert(account)
+    }
+
+    /// Returns the JSON of the key file of `address`
+    pub fn export(&self, address: &H160) -> Result<String> {
+        Ok(fs::read_to_string(self.key_file(address)?)?)
+    }
+
+    /// Deletes the key file of `address`, once `password` has been checked against it
+    pub fn delete(&mut self, address: &H160, password: &Password) -> Result<()> {
+        self.account(address)?.decrypt(password)?;
+        fs::remove_file(self.key_file(address)?)?;
+        self.key_files.remove(address);
+        self.unlocked.remove(address);
+        Ok(())
+    }
+
+    /// Re-encrypts the key of `address` with `new_password`. The key file keeps its name and
+    /// the account keeps its ID.
+    pub fn change_password(
+        &mut self,
+        address: &H160,
+        old_password: &Password,
+        new_password: &Password,
+    ) -> Result<()> {
+        let account = self.account(address)?;
+        let secret_key = account.decrypt(old_password)?;
+        let path = self.key_file(address)?;
+        let account =
+            Account::from_secret_key(&secret_key, new_password, self.kdf, self.directory.clone())?
+                .with_id(account.get_id());
+        self.save(&account, &path)?;
+        Ok(())
+    }
+
+    /// Decrypts the key of `address` and keeps it in memory until `timeout` elapses, or until
+    /// the account is locked again when there is no timeout
+    pub fn unlock(
+        &mut self,
+        address: &H160,
+        password: &Password,
+        timeout: Option<Duration>,
+    ) -> Result<()> {
+        let secret_key = self.account(address)?.decrypt(password)?;
+        self.unlocked.insert(
+            *address,
+            UnlockedKey {
+                secret_key,
+                expires: timeout.map(|timeout| Instant::now() + timeout),
+            },
+        );
+        Ok(())
+    }
+
+    /// Unlocks `address` with the first password, read from `password_files` one per line,
+    /// that decrypts its key
+    pub fn unlock_with_password_files(
+        &mut self,
+        address: &H160,
+        password_files: &[String],
+        timeout: Option<Duration>,
+    ) -> Result<()> {
+        let passwords = keys::passwords_from_files(password_files).map_err(failure::err_msg)?;
+        for password in passwords {
+            match self.unlock(address, &password, timeout) {
+                Err(e) => match e.downcast_ref::<KeystoreError>() {
+                    Some(KeystoreError::InvalidPassword) => continue,
+                    _ => return Err(e),
+                },
+                Ok(()) => return Ok(()),
+            }
+        }
+        Err(KeystoreError::InvalidPassword.into())
+    }
+
+    /// Drops the unlocked key of `address` from memory
+    pub fn lock(&mut self, address: &H160) {
+        self.unlocked.remove(address);
+    }
+
+    /// Returns true if the key of `address` is unlocked and its timeout has not elapsed
+    pub fn is_unlocked(&mut self, address: &H160) -> bool {
+        self.secret_key(address).is_ok()
+    }
+
+    /// Returns the unlocked key of `address`. Keys whose timeout has elapsed are dropped.
+    pub fn secret_key(&mut self, address: &H160) -> Result<SecretKey> {
+        let expired = match self.unlocked.get(address) {
+            Some(unlocked) => unlocked
+                .expires
+                .map_or(false, |expires| Instant::now() >= expires),
+            None => return Err(KeystoreError::AccountLocked(*address).into()),
+        };
+        if expired {
+            self.unlocked.remove(address);
+            return Err(KeystoreError::AccountLocked(*address).into());
+        }
+        Ok(self.unlocked[address].secret_key)
+    }
+
+    fn key_file(&self, address: &H160) -> Result<PathBuf> {
+        self.key_files
+            .get(address)
+            .cloned()
+            .ok_or_else(|| KeystoreError::UnknownAccount(*address).into())
+    }
+
+    fn insert(&mut self, account: Account) -> Result<H160> {
+        let address = account.address()?;
+        if self.contains(&address) {
+            return Err(KeystoreError::AccountExists(address).into());
+        }
+        let path = self.directory.join(account.get_account_filename());
+        let path = self.save(&account, &path)?;
+        self.key_files.insert(address, path);
+        Ok(address)
+    }
+
+    fn save(&self, account: &Account, path: &Path) -> Result<PathBuf> {
+        let directory = path.parent().unwrap_or(&self.directory);
+        let filename = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or(KeystoreError::InvalidField("file name"))?;
+        account.save(&directory.to_string_lossy(), filename)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustc_serialize::hex::FromHex;
+    use secp256k1;
+    use std::io::Write;
+    use uuid;
+
+    fn temp_directory() -> PathBuf {
+        std::env::temp_dir().join(uuid::Uuid::new_v4().to_string())
+    }
+
+    fn open(directory: &Path) -> KeyStore {
+        KeyStore::open(directory)
+            .unwrap()
+            .with_kdf(Kdf::Pbkdf2 { iterations: 2 })
+    }
+
+    fn secret_key() -> SecretKey {
+        let secp = secp256k1::Secp256k1::with_caps(secp256k1::ContextFlag::Full);
+        let secret = "7a28b5ba57c53603b0b07b56bba752f7784bf506fa95edc395f5cf6c7514fe9d"
+            .from_hex()
+            .unwrap();
+        SecretKey::from_slice(&secp, &secret).unwrap()
+    }
+
+    fn address() -> H160 {
+        H160::from_slice(
+            &"008aeeda4d805471df9b2a5b0f38a0c3bcba786b"
+                .from_hex()
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn scans_imports_and_deletes() {
+        let directory = temp_directory();
+        let password = Password::from("testpassword");
+        let mut key_store = open(&directory);
+        assert!(key_store.accounts().is_empty());
+        let created = key_store.create(&password).unwrap();
+        assert_eq!(
+            key_store.import(&secret_key(), &password).unwrap(),
+            address()
+        );
+        assert!(key_store.import(&secret_key(), &password).is_err());
+        fs::write(directory.join("README"), "not a key file").unwrap();
+
+        let mut key_store = open(&directory);
+        let mut expected = vec![created, address()];
+        expected.sort();
+        assert_eq!(key_store.accounts(), expected);
+
+        assert!(key_store
+            .delete(&address(), &Password::from("wrong"))
+            .is_err());
+        key_store.delete(&address(), &password).unwrap();
+        assert_eq!(key_store.accounts(), vec![created]);
+        assert_eq!(open(&directory).accounts(), vec![created]);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn exports_and_changes_password() {
+        let (first, second) = (temp_directory(), temp_directory());
+        let password = Password::from("testpassword");
+        let mut key_store = open(&first);
+        key_store.import(&secret_key(), &password).unwrap();
+        let json = key_store.export(&address()).unwrap();
+
+        let mut other = open(&second);
+        assert_eq!(other.import_json(&json).unwrap(), address());
+        let id = other.account(&address()).unwrap().get_id();
+        let new_password = Password::from("new password");
+        other
+            .change_password(&address(), &password, &new_password)
+            .unwrap();
+        let account = other.account(&address()).unwrap();
+        assert_eq!(account.get_id(), id);
+        assert!(account.decrypt(&password).is_err());
+        assert_eq!(account.decrypt(&new_password).unwrap(), secret_key());
+        assert_eq!(other.accounts(), vec![address()]);
+        fs::remove_dir_all(&first).unwrap();
+        fs::remove_dir_all(&second).unwrap();
+    }
+
+    #[test]
+    fn rejects_invalid_json_without_writing() {
+        let (first, second) = (temp_directory(), temp_directory());
+        let mut key_store = open(&first);
+        key_store
+            .import(&secret_key(), &Password::from("testpassword"))
+            .unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&key_store.export(&address()).unwrap()).unwrap();
+
+        let mut other = open(&second);
+        let mut old_version = json.clone();
+        old_version["version"] = serde_json::Value::from(2);
+        let mut unknown_kdf = json.clone();
+        unknown_kdf["crypto"]["kdf"] = serde_json::Value::from("argon2");
+        let mut missing_salt = json.clone();
+        missing_salt["crypto"]["kdfparams"]["salt"] = serde_json::Value::Null;
+        for invalid in &[old_version, unknown_kdf, missing_salt] {
+            assert!(other.import_json(&invalid.to_string()).is_err());
+        }
+        assert!(other.accounts().is_empty());
+        assert_eq!(fs::read_dir(&second).unwrap().count(), 0);
+        fs::remove_dir_all(&first).unwrap();
+        fs::remove_dir_all(&second).unwrap();
+    }
+
+    #[test]
+    fn caches_unlocked_keys() {
+        let directory = temp_directory();
+        let password = Password::from("testpassword");
+        let mut key_store = open(&directory);
+        key_store.import(&secret_key(), &password).unwrap();
+        assert!(!key_store.is_unlocked(&address()));
+
+        key_store.unlock(&address(), &password, None).unwrap();
+        assert_eq!(key_store.secret_key(&address()).unwrap(), secret_key());
+        key_store.lock(&address());
+        assert!(key_store.secret_key(&address()).is_err());
+
+        key_store
+            .unlock(&address(), &password, Some(Duration::from_secs(0)))
+            .unwrap();
+        assert!(!key_store.is_unlocked(&address()));
+
+        let password_file = directory.join("passwords");
+        let mut file = fs::File::create(&password_file).unwrap();
+        writeln!(file, "wrong\ntestpassword").unwrap();
+        key_store
+            .unlock_with_password_files(
+                &address(),
+                &[password_file.to_string_lossy().into_owned()],
+                Some(Duration::from_secs(60)),
+            )
+            .unwrap();
+        assert!(key_store.is_unlocked(&address()));
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
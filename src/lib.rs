@@ -1,5 +1,6 @@
 #![feature(fixed_size_array)]
 extern crate bigint;
+extern crate bip39;
 extern crate chrono;
 extern crate env_logger;
 extern crate ethereum_types;
@@ -7,6 +8,7 @@ extern crate ethereum_types;
 extern crate failure;
 extern crate hmac;
 extern crate libvm;
+#[macro_use]
 extern crate log;
 extern crate openssl;
 extern crate pbkdf2;
@@ -14,22 +16,38 @@ extern crate rand;
 extern crate rlp;
 extern crate rpassword;
 extern crate rustc_serialize;
+extern crate scrypt;
 #[macro_use]
 extern crate serde_derive;
 extern crate secp256k1;
+extern crate serde_json;
 extern crate sha2;
 extern crate sha3;
 extern crate tiny_keccak;
 extern crate trie;
 extern crate uuid;
 
-mod account;
+pub mod access_set;
+pub mod account;
+pub mod block;
+pub mod block_env;
+pub mod block_executor;
+pub mod bloom;
+mod convert;
+pub mod eip712;
 mod errors;
 pub mod eth_log;
+pub mod executor;
 mod gas_prices;
-mod keys;
+pub mod hd_wallet;
+pub mod keys;
+pub mod keystore;
+pub mod log_filter;
 mod memory;
 mod opcodes;
+pub mod receipt;
 mod storage;
 pub mod transaction;
+mod trie_root;
+pub mod typed_transaction;
 pub mod vm;
@@ -0,0 +1,287 @@
+//! Contains the filters used to query logs by block range, address and topics, and an
+//! in-memory index of the logs emitted by executed transactions
+
+use bloom::Bloom;
+use convert;
+use eth_log::Log;
+use ethereum_types::{H160, H256, U256};
+use receipt::Receipt;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+/// Selects logs the way `eth_getLogs` does. Empty address and topic sets match anything.
+pub struct LogFilter {
+    /// First block to include, or None to start at the earliest block
+    pub from_block: Option<U256>,
+    /// Last block to include, or None to stop at the latest block
+    pub to_block: Option<U256>,
+    /// Addresses that may have emitted the log
+    pub addresses: Vec<H160>,
+    /// Accepted values for each topic position. None is a wildcard for that position.
+    pub topics: Vec<Option<Vec<H256>>>,
+}
+
+impl LogFilter {
+    /// Creates and returns a LogFilter that matches every log
+    pub fn new() -> LogFilter {
+        LogFilter::default()
+    }
+
+    /// Part of the Builder, restricts the filter to blocks `from_block` through `to_block`
+    pub fn with_block_range(
+        mut self,
+        from_block: Option<U256>,
+        to_block: Option<U256>,
+    ) -> LogFilter {
+        self.from_block = from_block;
+        self.to_block = to_block;
+        self
+    }
+
+    /// Part of the Builder, adds an address the log may have been emitted by
+    pub fn with_address(mut self, address: H160) -> LogFilter {
+        self.addresses.push(address);
+        self
+    }
+
+    /// Part of the Builder, sets the accepted values of the topic at `position`. Positions
+    /// before it that have not been set are wildcards.
+    pub fn with_topics(mut self, position: usize, topics: Vec<H256>) -> LogFilter {
+        if self.topics.len() <= position {
+            self.topics.resize(position + 1, None);
+        }
+        self.topics[position] = Some(topics);
+        self
+    }
+
+    /// Returns true if block `number` is within the block range
+    pub fn matches_block(&self, number: U256) -> bool {
+        self.from_block.map_or(true, |from| number >= from)
+            && self.to_block.map_or(true, |to| number <= to)
+    }
+
+    /// Returns true if the address and the topics of `log` match the filter
+    pub fn matches(&self, log: &Log) -> bool {
+        if !self.addresses.is_empty()
+            && !self.addresses.contains(&convert::from_address(log.address))
+        {
+            return false;
+        }
+        if self.topics.len() > log.topics.len() {
+            return false;
+        }
+        self.topics
+            .iter()
+            .zip(log.topics.iter())
+            .all(|(accepted, topic)| match *accepted {
+                Some(ref accepted) if !accepted.is_empty() => {
+                    accepted.contains(&convert::from_h256(*topic))
+                }
+                _ => true,
+            })
+    }
+
+    /// Returns false if no log summarised by `bloom` can match, so the logs can be skipped
+    pub fn matches_bloom(&self, bloom: &Bloom) -> bool {
+        if !self.addresses.is_empty() && !self.addresses.iter().any(|a| bloom.contains_address(a)) {
+            return false;
+        }
+        self.topics.iter().all(|accepted| match *accepted {
+            Some(ref accepted) if !accepted.is_empty() => {
+                accepted.iter().any(|t| bloom.contains_topic(t))
+            }
+            _ => true,
+        })
+    }
+
+    /// Returns the logs of `receipt` that match the filter
+    pub fn filter_receipt<'a>(&self, receipt: &'a Receipt) -> Vec<&'a Log> {
+        if !self.matches_bloom(&receipt.logs_bloom) {
+            return vec![];
+        }
+        receipt
+            .logs
+            .iter()
+            .filter(|log| self.matches(log))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A log together with where it was emitted
+pub struct IndexedLog {
+    /// Number of the block the log was emitted in
+    pub block_number: U256,
+    /// Hash of the transaction that emitted the log
+    pub transaction_hash: H256,
+    /// Position of the transaction in its block
+    pub transaction_index: usize,
+    /// Position of the log in its block
+    pub log_index: usize,
+    /// The log itself
+    pub log: Log,
+}
+
+#[derive(Debug, Default, Clone)]
+struct IndexedBlock {
+    bloom: Bloom,
+    logs: Vec<IndexedLog>,
+}
+
+#[derive(Debug, Default, Clone)]
+/// Keeps the logs of executed transactions in memory, grouped by block, so they can be
+/// queried with a LogFilter
+pub struct LogIndex {
+    blocks: BTreeMap<U256, IndexedBlock>,
+}
+
+impl LogIndex {
+    /// Creates and returns an empty LogIndex
+    pub fn new() -> LogIndex {
+        LogIndex::default()
+    }
+
+    /// Adds the logs emitted by a transaction, such as the ones returned by `VM::logs`
+    pub fn insert_logs(
+        &mut self,
+        block_number: U256,
+        transaction_hash: H256,
+        transaction_index: usize,
+        logs: &[Log],
+    ) {
+        let block = self
+            .blocks
+            .entry(block_number)
+            .or_insert_with(Default::default);
+        for log in logs {
+            block.bloom.accrue_log(log);
+            let log_index = block.logs.len();
+            block.logs.push(IndexedLog {
+                block_number,
+                transaction_hash,
+                transaction_index,
+                log_index,
+                log: log.clone(),
+            });
+        }
+    }
+
+    /// Adds the logs of a transaction receipt
+    pub fn insert_receipt(
+        &mut self,
+        block_number: U256,
+        transaction_hash: H256,
+        transaction_index: usize,
+        receipt: &Receipt,
+    ) {
+        self.insert_logs(
+            block_number,
+            transaction_hash,
+            transaction_index,
+            &receipt.logs,
+        );
+    }
+
+    /// Returns the logs that match `filter`, in the order they were emitted. Blocks whose
+    /// bloom cannot match are skipped.
+    pub fn query(&self, filter: &LogFilter) -> Vec<&IndexedLog> {
+        let from = filter.from_block.unwrap_or_else(U256::zero);
+        let to = filter.to_block.unwrap_or_else(U256::max_value);
+        if from > to {
+            return vec![];
+        }
+        self.blocks
+            .range(from..=to)
+            .filter(|(_, block)| filter.matches_bloom(&block.bloom))
+            .flat_map(|(_, block)| block.logs.iter())
+            .filter(|indexed| filter.matches(&indexed.log))
+            .collect()
+    }
+
+    /// Drops the logs of blocks after `number`, for instance after a reorganisation
+    pub fn truncate(&mut self, number: U256) {
+        if number < U256::max_value() {
+            self.blocks.split_off(&(number + U256::one()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bigint;
+
+    fn address(byte: u8) -> H160 {
+        H160::from([byte; 20])
+    }
+
+    fn topic(byte: u8) -> H256 {
+        H256::from([byte; 32])
+    }
+
+    fn log(address: u8, topics: &[u8]) -> Log {
+        let mut log = Log::new(bigint::Address::from(&[address; 20][..]));
+        for t in topics {
+            log.topics.push(bigint::H256::from(&[*t; 32][..]));
+        }
+        log
+    }
+
+    #[test]
+    fn matches_addresses_and_positional_topics() {
+        let transfer = log(0x11, &[0xaa, 0xbb, 0xcc]);
+        assert!(LogFilter::new().matches(&transfer));
+        assert!(LogFilter::new()
+            .with_address(address(0x11))
+            .matches(&transfer));
+        assert!(!LogFilter::new()
+            .with_address(address(0x12))
+            .matches(&transfer));
+        assert!(LogFilter::new()
+            .with_topics(1, vec![topic(0x01), topic(0xbb)])
+            .matches(&transfer));
+        assert!(!LogFilter::new()
+            .with_topics(0, vec![topic(0xbb)])
+            .matches(&transfer));
+        assert!(!LogFilter::new()
+            .with_topics(3, vec![topic(0xdd)])
+            .matches(&transfer));
+    }
+
+    #[test]
+    fn skips_receipts_by_bloom() {
+        let receipt = Receipt::new(0, true, U256::from(21_000u64), vec![log(0x11, &[0xaa])]);
+        let filter = LogFilter::new().with_address(address(0x11));
+        assert!(filter.matches_bloom(&receipt.logs_bloom));
+        assert_eq!(filter.filter_receipt(&receipt).len(), 1);
+        let filter = LogFilter::new().with_topics(0, vec![topic(0xbb)]);
+        assert!(!filter.matches_bloom(&receipt.logs_bloom));
+        assert!(filter.filter_receipt(&receipt).is_empty());
+    }
+
+    #[test]
+    fn queries_index_by_block_range() {
+        let mut index = LogIndex::new();
+        index.insert_logs(U256::from(1u64), topic(1), 0, &[log(0x11, &[0xaa])]);
+        index.insert_logs(
+            U256::from(2u64),
+            topic(2),
+            0,
+            &[log(0x11, &[0xaa]), log(0x22, &[0xaa])],
+        );
+        index.insert_logs(U256::from(3u64), topic(3), 1, &[log(0x11, &[0xbb])]);
+
+        let filter = LogFilter::new()
+            .with_block_range(Some(U256::from(2u64)), None)
+            .with_topics(0, vec![topic(0xaa)]);
+        let logs = index.query(&filter);
+        assert_eq!(logs.len(), 2);
+        assert_eq!(logs[1].log_index, 1);
+        assert_eq!(logs[1].log.address, bigint::Address::from(&[0x22; 20][..]));
+
+        let filter = LogFilter::new().with_address(address(0x11));
+        assert_eq!(index.query(&filter).len(), 3);
+        index.truncate(U256::from(2u64));
+        assert_eq!(index.query(&filter).len(), 2);
+    }
+}
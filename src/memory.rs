@@ -30,6 +30,8 @@ pub trait Memory {
     fn copy_from_memory(&self, start: U256, len: U256) -> Vec<u8>;
 
     fn copy_into_memory(&mut self, values: &[u8], start: U256, value_start: U256, len: U256);
+    // Grows the memory to at least `size` bytes, filling it with zeros
+    fn resize(&mut self, size: usize);
 }
 
 /// Simple implementation of memory using Rust Vecs
@@ -68,9 +70,10 @@ impl Memory for SimpleMemory {
         self.memory[index..index + 32].to_vec().as_slice().into()
     }
 
-    /// Reads a single byte at the provided index
+    /// Reads a single byte at the provided index. Memory that has not been written yet reads
+    /// as zero.
     fn read_byte(&self, index: M256) -> u8 {
-        self.memory[index.as_usize()]
+        self.memory.get(index.as_usize()).cloned().unwrap_or(0)
     }
 
     fn read_slice(&self, init_off_u: U256, init_size_u: U256) -> &[u8] {
@@ -136,6 +139,14 @@ impl Memory for SimpleMemory {
             i = i + U256::from(1u64);
         }
     }
+
+    /// Grows the memory to at least `size` bytes. Memory never shrinks.
+    fn resize(&mut self, size: usize) {
+        if size > self.memory.len() {
+            self.memory.resize(size, 0);
+            self.expansions += 1;
+        }
+    }
 }
 
 #[cfg(test)]
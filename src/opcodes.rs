@@ -5,7 +5,7 @@ type Gas = u32;
 
 /// Opcodes supported by the Ethereum VM. https://github.com/trailofbits/evm-opcodes is a good
 /// reference for them.
-#[derive(PartialEq, Hash, Debug)]
+#[derive(Clone, Copy, PartialEq, Hash, Debug)]
 pub enum Opcode {
     STOP,
     ADD,
@@ -46,12 +46,15 @@ pub enum Opcode {
     EXTCODECOPY,
     RETURNDATASIZE,
     RETURNDATACOPY,
+    EXTCODEHASH,
     BLOCKHASH,
     COINBASE,
     TIMESTAMP,
     NUMBER,
     DIFFICULTY,
     GASLIMIT,
+    CHAINID,
+    BASEFEE,
     SLOAD,
     SSTORE,
     JUMP,
@@ -64,6 +67,7 @@ pub enum Opcode {
     MSIZE,
     GAS,
     JUMPDEST,
+    PUSH0,
     PUSH(u64),
     DUP(u64),
     SWAP(u64),
@@ -75,6 +79,8 @@ pub enum Opcode {
     CALLCODE,
     RETURN,
     DELEGATECALL,
+    STATICCALL,
+    REVERT,
 }
 
 impl Instruction for Opcode {
@@ -137,6 +143,7 @@ impl<'a> From<&'a u8> for Opcode {
             0x3c => Opcode::EXTCODECOPY,
             0x3d => Opcode::RETURNDATASIZE,
             0x3e => Opcode::RETURNDATACOPY,
+            0x3f => Opcode::EXTCODEHASH,
 
             // Block data functions
             0x40 => Opcode::BLOCKHASH,
@@ -145,6 +152,8 @@ impl<'a> From<&'a u8> for Opcode {
             0x43 => Opcode::NUMBER,
             0x44 => Opcode::DIFFICULTY,
             0x45 => Opcode::GASLIMIT,
+            0x46 => Opcode::CHAINID,
+            0x48 => Opcode::BASEFEE,
 
             // Stack, memory, storage, and flow operations
             0x50 => Opcode::POP,
@@ -161,6 +170,7 @@ impl<'a> From<&'a u8> for Opcode {
             0x5b => Opcode::JUMPDEST,
 
             // Push operations
+            0x5f => Opcode::PUSH0,
             0x60 => Opcode::PUSH(1),
             0x61 => Opcode::PUSH(2),
             0x62 => Opcode::PUSH(3),
@@ -243,6 +253,8 @@ impl<'a> From<&'a u8> for Opcode {
             0xf2 => Opcode::CALLCODE,
             0xf3 => Opcode::RETURN,
             0xf4 => Opcode::DELEGATECALL,
+            0xfa => Opcode::STATICCALL,
+            0xfd => Opcode::REVERT,
             0xfe => Opcode::INVALID,
             0xff => Opcode::SUICIDE,
             _ => Opcode::INVALID,
@@ -292,12 +304,15 @@ impl Into<u8> for Opcode {
             Opcode::EXTCODECOPY => 0x3c,
             Opcode::RETURNDATASIZE => 0x3d,
             Opcode::RETURNDATACOPY => 0x3e,
+            Opcode::EXTCODEHASH => 0x3f,
             Opcode::BLOCKHASH => 0x40,
             Opcode::COINBASE => 0x41,
             Opcode::TIMESTAMP => 0x42,
             Opcode::NUMBER => 0x43,
             Opcode::DIFFICULTY => 0x44,
             Opcode::GASLIMIT => 0x45,
+            Opcode::CHAINID => 0x46,
+            Opcode::BASEFEE => 0x48,
             Opcode::POP => 0x50,
             Opcode::MLOAD => 0x51,
             Opcode::MSTORE => 0x52,
@@ -310,6 +325,7 @@ impl Into<u8> for Opcode {
             Opcode::MSIZE => 0x59,
             Opcode::GAS => 0x5a,
             Opcode::JUMPDEST => 0x5b,
+            Opcode::PUSH0 => 0x5f,
             Opcode::PUSH(1) => 0x60,
             Opcode::PUSH(2) => 0x61,
             Opcode::PUSH(3) => 0x62,
@@ -384,6 +400,8 @@ impl Into<u8> for Opcode {
             Opcode::CALLCODE => 0xf2,
             Opcode::RETURN => 0xf3,
             Opcode::DELEGATECALL => 0xf4,
+            Opcode::STATICCALL => 0xfa,
+            Opcode::REVERT => 0xfd,
             Opcode::SUICIDE => 0xff,
             _ => 0xfe,
         }
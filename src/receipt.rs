@@ -0,0 +1,163 @@
+//! Contains the Receipt data structure
+
+use bigint;
+use bloom::Bloom;
+use convert;
+use eth_log::Log;
+use ethereum_types::{H160, H256, U256};
+use rlp::{Decodable, DecoderError, Encodable, RlpStream, UntrustedRlp};
+use trie_root::ordered_trie_root;
+
+/// The outcome of executing a transaction
+#[derive(Debug, Clone, PartialEq)]
+pub struct Receipt {
+    /// Envelope type of the transaction, 0 for legacy transactions
+    pub transaction_type: u8,
+    /// Whether the transaction executed successfully
+    pub status: bool,
+    /// Gas used by the transaction, after refunds
+    pub gas_used: U256,
+    /// Gas used by the transaction and the ones before it in the block
+    pub cumulative_gas_used: U256,
+    /// Bloom filter over the addresses and topics of the logs
+    pub logs_bloom: Bloom,
+    /// Logs emitted by the transaction
+    pub logs: Vec<Log>,
+    /// Address of the new contract, if the transaction created one
+    pub contract_address: Option<H160>,
+}
+
+impl Receipt {
+    /// Creates and returns a new Receipt for a transaction that is alone in its block
+    pub fn new(transaction_type: u8, status: bool, gas_used: U256, logs: Vec<Log>) -> Receipt {
+        Receipt {
+            transaction_type,
+            status,
+            gas_used,
+            cumulative_gas_used: gas_used,
+            logs_bloom: Bloom::from_logs(&logs),
+            logs,
+            contract_address: None,
+        }
+    }
+
+    /// Decodes a receipt as stored in the receipts trie. Receipts of legacy transactions are
+    /// bare RLP lists, the others are prefixed with the transaction type.
+    pub fn decode(raw: &[u8]) -> Result<Receipt, DecoderError> {
+        match raw.split_first() {
+            Some((first, _)) if *first >= 0xc0 => UntrustedRlp::new(raw).as_val(),
+            Some((first, payload)) => {
+                let mut receipt: Receipt = UntrustedRlp::new(payload).as_val()?;
+                receipt.transaction_type = *first;
+                Ok(receipt)
+            }
+            None => Err(DecoderError::RlpIsTooShort),
+        }
+    }
+
+    /// Returns the encoding of the receipt as stored in the receipts trie
+    pub fn encode(&self) -> Vec<u8> {
+        let payload = self.rlp_bytes();
+        if self.transaction_type == 0 {
+            return payload.to_vec();
+        }
+        let mut raw = vec![self.transaction_type];
+        raw.extend_from_slice(&payload);
+        raw
+    }
+}
+
+/// Implements rlp::Encodable using the post-Byzantium receipt layout. Only the consensus
+/// fields are encoded.
+impl Encodable for Receipt {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        s.begin_list(4);
+        s.append(&bigint::U256::from(self.status as u64));
+        s.append(&convert::to_u256(self.cumulative_gas_used));
+        s.append(&self.logs_bloom);
+        s.append_list(&self.logs);
+    }
+}
+
+/// Implements rlp::Decodable using the post-Byzantium receipt layout. The gas used by the
+/// transaction alone is not part of the encoding and is set to the cumulative gas used.
+impl Decodable for Receipt {
+    fn decode(rlp: &UntrustedRlp) -> Result<Self, DecoderError> {
+        if rlp.item_count() != 4 {
+            return Err(DecoderError::RlpIncorrectListLen);
+        }
+        let status = convert::from_u256(rlp.val_at(0)?);
+        if status > U256::one() {
+            return Err(DecoderError::Custom("invalid receipt status"));
+        }
+        let cumulative_gas_used = convert::from_u256(rlp.val_at(1)?);
+        Ok(Self {
+            transaction_type: 0,
+            status: status == U256::one(),
+            gas_used: cumulative_gas_used,
+            cumulative_gas_used,
+            logs_bloom: rlp.val_at(2)?,
+            logs: rlp.list_at(3)?,
+            contract_address: None,
+        })
+    }
+}
+
+/// Returns the receipts root committed to by a block header
+pub fn receipts_root(receipts: &[Receipt]) -> H256 {
+    ordered_trie_root(receipts.iter().map(Receipt::encode))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustc_serialize::hex::FromHex;
+
+    fn h256(hex: &str) -> H256 {
+        H256::from_slice(&hex.from_hex().unwrap())
+    }
+
+    fn log() -> Log {
+        let mut log = Log::new(bigint::Address::from(&[0x11u8; 20][..]));
+        log.topics.push(bigint::H256::from(&[0x22u8; 32][..]));
+        log.data = vec![1, 2, 3];
+        log
+    }
+
+    #[test]
+    fn round_trips_receipts() {
+        let mut legacy = Receipt::new(0, true, U256::from(21_000u64), vec![log()]);
+        legacy.cumulative_gas_used = U256::from(42_000u64);
+        let decoded = Receipt::decode(&legacy.encode()).unwrap();
+        assert_eq!(decoded.cumulative_gas_used, legacy.cumulative_gas_used);
+        assert_eq!(decoded.logs, legacy.logs);
+        assert_eq!(decoded.logs_bloom, legacy.logs_bloom);
+        assert!(decoded.status);
+
+        let typed = Receipt::new(2, false, U256::from(30_000u64), vec![]);
+        let raw = typed.encode();
+        assert_eq!(raw[0], 2);
+        let decoded = Receipt::decode(&raw).unwrap();
+        assert_eq!(decoded.transaction_type, 2);
+        assert!(!decoded.status);
+    }
+
+    #[test]
+    fn computes_receipts_root() {
+        assert_eq!(
+            receipts_root(&[]),
+            h256("56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421")
+        );
+        let first = Receipt::new(0, true, U256::from(21_000u64), vec![]);
+        assert_eq!(
+            receipts_root(&[first.clone()]),
+            h256("056b23fbba480696b65fe5a59b8f2148a1299103c4f57df839233af2cf4ca2d2")
+        );
+        let mut second = Receipt::new(2, true, U256::from(21_000u64), vec![]);
+        second.cumulative_gas_used = U256::from(42_000u64);
+        assert_eq!(
+            receipts_root(&[first, second]),
+            h256("d080a066ff223b1c759709fa9cd8d9105952cb7a5b231beafe683f964e2ab0d4")
+        );
+    }
+}
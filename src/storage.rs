@@ -2,6 +2,7 @@
 
 use bigint::{Address, M256, U256};
 use errors::StorageError;
+use std::collections::hash_map::Iter;
 use std::collections::HashMap;
 
 // Convenience wrapper
@@ -29,6 +30,11 @@ impl Storage {
         }
     }
 
+    /// Returns the address that owns this storage.
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
     /// Commit a value into the storage.
     fn commit(&mut self, index: U256, value: M256) -> Result<(), StorageError> {
         if self.storage.contains_key(&index) {
@@ -47,15 +53,17 @@ impl Storage {
         }
     }
 
-    /// Write a value into the storage.
+    /// Write a value into the storage, replacing any previous value.
     pub fn write(&mut self, index: U256, value: M256) -> Result<(), StorageError> {
-        if self.storage.contains_key(&index) {
-            return Err(StorageError::RequireError);
-        }
         self.storage.insert(index, value);
         Ok(())
     }
 
+    /// Iterate over the written items in storage.
+    pub fn iter(&self) -> Iter<U256, M256> {
+        self.storage.iter()
+    }
+
     /// Return the number of changed/full items in storage.
     pub fn len(&self) -> usize {
         self.storage.len()
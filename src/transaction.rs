@@ -1,6 +1,13 @@
-use ethereum_types::{H160, U256};
+use bigint;
+use convert;
+use errors::{Result, TransactionError};
+use ethereum_types::{H160, H256, U256};
+use keys;
+use rlp::{Decodable, DecoderError, Encodable, RlpStream, UntrustedRlp};
+use secp256k1::key::SecretKey;
 use std::fmt::Display;
 use std::fmt::Formatter;
+use tiny_keccak::keccak256;
 
 #[derive(Debug, Default, Deserialize, Clone, PartialEq, Serialize, Hash, Eq, PartialOrd, Ord)]
 pub struct Transaction {
@@ -25,8 +32,326 @@ pub struct Transaction {
     pub s: U256,
 }
 
+impl Transaction {
+    /// Decodes a raw RLP encoded transaction, as produced by wallets
+    pub fn from_raw(raw: &[u8]) -> std::result::Result<Transaction, DecoderError> {
+        UntrustedRlp::new(raw).as_val()
+    }
+
+    /// Returns the RLP encoding of the signed transaction
+    pub fn to_raw(&self) -> Vec<u8> {
+        self.rlp_bytes().into_vec()
+    }
+
+    /// Returns the hash of the signed transaction, which identifies it
+    pub fn hash(&self) -> H256 {
+        H256::from(keccak256(&self.to_raw()))
+    }
+
+    /// Returns the hash that is signed by the sender. When `chain_id` is given the hash follows
+    /// EIP-155 and commits to the chain, otherwise it is the pre-EIP-155 hash.
+    pub fn signing_hash(&self, chain_id: Option<u64>) -> H256 {
+        let mut stream = match chain_id {
+            Some(_) => RlpStream::new_list(9),
+            None => RlpStream::new_list(6),
+        };
+        self.rlp_append_unsigned(&mut stream);
+        if let Some(chain_id) = chain_id {
+            stream.append(&bigint::U256::from(chain_id));
+            stream.append(&bigint::U256::zero());
+            stream.append(&bigint::U256::zero());
+        }
+        H256::from(keccak256(&stream.out()))
+    }
+
+    /// Returns the chain the transaction was signed for, or None for transactions signed
+    /// before EIP-155
+    pub fn chain_id(&self) -> Option<u64> {
+        if self.v < U256::from(35u64) || self.v > U256::from(u64::max_value()) {
+            return None;
+        }
+        Some((self.v.low_u64() - 35) / 2)
+    }
+
+    /// Signs the transaction with `secret_key`, filling in `v`, `r` and `s`. When `chain_id` is
+    /// given the signature follows EIP-155, otherwise `v` is 27 or 28.
+    pub fn sign(&mut self, secret_key: &SecretKey, chain_id: Option<u64>) -> Result<()> {
+        let (r, s, recovery_id) = sign_hash(secret_key, &self.signing_hash(chain_id))?;
+        let offset = match chain_id {
+            Some(chain_id) => U256::from(chain_id) * U256::from(2u64) + U256::from(35u64),
+            None => U256::from(27u64),
+        };
+        self.v = offset + U256::from(recovery_id as u64);
+        self.r = r;
+        self.s = s;
+        Ok(())
+    }
+
+    /// Recovers the address that signed the transaction with `recover_signer`
+    pub fn recover_sender(&self) -> Result<H160> {
+        let recovery_id = self.recovery_id()?;
+        recover_signer(
+            &self.signing_hash(self.chain_id()),
+            self.r,
+            self.s,
+            recovery_id,
+        )
+    }
+
+    /// Extracts the recovery id from `v`, undoing the EIP-155 chain id encoding
+    fn recovery_id(&self) -> Result<i32> {
+        let offset = match self.chain_id() {
+            Some(chain_id) => U256::from(chain_id) * U256::from(2u64) + U256::from(35u64),
+            None => U256::from(27u64),
+        };
+        if self.v < offset || self.v - offset > U256::one() {
+            return Err(TransactionError::InvalidSignature.into());
+        }
+        Ok((self.v - offset).low_u64() as i32)
+    }
+
+    /// Appends the fields that are covered by the signature
+    fn rlp_append_unsigned(&self, s: &mut RlpStream) {
+        s.append(&convert::to_u256(self.nonce));
+        s.append(&convert::to_u256(self.gas_price));
+        s.append(&convert::to_u256(self.start_gas));
+        match self.to {
+            Some(to) => s.append(&convert::to_address(to)),
+            None => s.append_empty_data(),
+        };
+        s.append(&convert::to_u256(self.value));
+        s.append(&self.data);
+    }
+}
+
 impl Display for Transaction {
     fn fmt(&self, f: &mut Formatter) -> core::fmt::Result {
         write!(f, "{:#?}", self)
     }
 }
+
+/// Implements rlp::Encodable using the legacy transaction layout
+impl Encodable for Transaction {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        s.begin_list(9);
+        self.rlp_append_unsigned(s);
+        s.append(&convert::to_u256(self.v));
+        s.append(&convert::to_u256(self.r));
+        s.append(&convert::to_u256(self.s));
+    }
+}
+
+/// Implements rlp::Decodable using the legacy transaction layout
+impl Decodable for Transaction {
+    fn decode(rlp: &UntrustedRlp) -> std::result::Result<Self, DecoderError> {
+        if rlp.item_count() != 9 {
+            return Err(DecoderError::RlpIncorrectListLen);
+        }
+        let to = rlp.at(3)?;
+        let to = if to.is_empty() {
+            None
+        } else {
+            Some(convert::from_address(to.as_val()?))
+        };
+        Ok(Self {
+            nonce: convert::from_u256(rlp.val_at(0)?),
+            gas_price: convert::from_u256(rlp.val_at(1)?),
+            start_gas: convert::from_u256(rlp.val_at(2)?),
+            to,
+            value: convert::from_u256(rlp.val_at(4)?),
+            data: rlp.val_at(5)?,
+            v: convert::from_u256(rlp.val_at(6)?),
+            r: convert::from_u256(rlp.val_at(7)?),
+            s: convert::from_u256(rlp.val_at(8)?),
+        })
+    }
+}
+
+/// Signs a transaction hash, returning the `r` and `s` values and the recovery id
+pub fn sign_hash(secret_key: &SecretKey, hash: &H256) -> Result<(U256, U256, i32)> {
+    let (signature, recovery_id) = keys::sign_hash(secret_key, &hash.0)?;
+    Ok((
+        U256::from_big_endian(&signature[..32]),
+        U256::from_big_endian(&signature[32..]),
+        recovery_id,
+    ))
+}
+
+/// Recovers the address that signed a transaction hash. Fails if the signature is malformed
+/// or uses a high `s` value.
+pub fn recover_signer(hash: &H256, r: U256, s: U256, recovery_id: i32) -> Result<H160> {
+    if r.is_zero() || r >= keys::SECP256K1_N || s.is_zero() {
+        return Err(TransactionError::InvalidSignature.into());
+    }
+    if s > keys::SECP256K1_HALF_N {
+        return Err(TransactionError::HighS.into());
+    }
+    let mut signature = [0u8; 64];
+    r.to_big_endian(&mut signature[..32]);
+    s.to_big_endian(&mut signature[32..]);
+    let public_key = keys::recover_public_key(&hash.0, &signature, recovery_id)?;
+    Ok(keys::public_key_to_address(&public_key))
+}
+
+/// Returns the address of the contract created by `sender` when its nonce is `nonce`
+pub fn contract_address(sender: H160, nonce: U256) -> H160 {
+    let mut stream = RlpStream::new_list(2);
+    stream.append(&convert::to_address(sender));
+    stream.append(&convert::to_u256(nonce));
+    let hash = keccak256(&stream.out());
+    H160::from_slice(&hash[12..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustc_serialize::hex::FromHex;
+    use secp256k1;
+
+    fn h160(hex: &str) -> H160 {
+        H160::from_slice(&hex.from_hex().unwrap())
+    }
+
+    fn h256(hex: &str) -> H256 {
+        H256::from_slice(&hex.from_hex().unwrap())
+    }
+
+    // Signed transaction from the EIP-155 specification
+    const EIP155_RAW: &str = "f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a76400008025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83";
+
+    /// Key that signed the EIP-155 transaction
+    fn secret_key() -> SecretKey {
+        let context = secp256k1::Secp256k1::with_caps(secp256k1::ContextFlag::Full);
+        SecretKey::from_slice(&context, &[0x46; 32]).unwrap()
+    }
+
+    /// The address of `secret_key`
+    fn signer() -> H160 {
+        h160("9d8a62f656a8d1615c1294fd71e9cfb3e4855a4f")
+    }
+
+    #[test]
+    fn decodes_raw_transaction() {
+        let raw = EIP155_RAW.from_hex().unwrap();
+        let transaction = Transaction::from_raw(&raw).unwrap();
+        assert_eq!(transaction.nonce, U256::from(9u64));
+        assert_eq!(transaction.gas_price, U256::from(20_000_000_000u64));
+        assert_eq!(transaction.start_gas, U256::from(21_000u64));
+        assert_eq!(
+            transaction.to,
+            Some(h160("3535353535353535353535353535353535353535"))
+        );
+        assert_eq!(transaction.value, U256::from(1_000_000_000_000_000_000u64));
+        assert!(transaction.data.is_empty());
+        assert_eq!(transaction.v, U256::from(37u64));
+        assert_eq!(transaction.to_raw(), raw);
+    }
+
+    #[test]
+    fn hashes_transaction() {
+        let raw = EIP155_RAW.from_hex().unwrap();
+        let transaction = Transaction::from_raw(&raw).unwrap();
+        assert_eq!(
+            transaction.signing_hash(Some(1)),
+            h256("daf5a779ae972f972197303d7b574746c7ef83eadac0f2791ad23db92e4c8e53")
+        );
+        assert_eq!(
+            transaction.hash(),
+            h256("33469b22e9f636356c4160a87eb19df52b7412e8eac32a4a55ffe88ea8350788")
+        );
+    }
+
+    #[test]
+    fn recovers_sender() {
+        let raw = EIP155_RAW.from_hex().unwrap();
+        let transaction = Transaction::from_raw(&raw).unwrap();
+        assert_eq!(transaction.chain_id(), Some(1));
+        assert_eq!(transaction.recover_sender().unwrap(), signer());
+    }
+
+    #[test]
+    fn tampered_transaction_recovers_other_sender() {
+        let raw = EIP155_RAW.from_hex().unwrap();
+        let mut transaction = Transaction::from_raw(&raw).unwrap();
+        transaction.value = transaction.value + U256::one();
+        let sender = transaction.recover_sender();
+        assert!(sender.is_err() || sender.unwrap() != signer());
+    }
+
+    #[test]
+    fn rejects_high_s() {
+        let raw = EIP155_RAW.from_hex().unwrap();
+        let mut transaction = Transaction::from_raw(&raw).unwrap();
+        transaction.s = keys::SECP256K1_N - transaction.s;
+        transaction.v = U256::from(38u64);
+        assert!(transaction.recover_sender().is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_v() {
+        let raw = EIP155_RAW.from_hex().unwrap();
+        let mut transaction = Transaction::from_raw(&raw).unwrap();
+        transaction.v = U256::from(29u64);
+        assert!(transaction.recover_sender().is_err());
+    }
+
+    #[test]
+    fn signs_transaction() {
+        let mut transaction = Transaction {
+            nonce: U256::from(9u64),
+            gas_price: U256::from(20_000_000_000u64),
+            start_gas: U256::from(21_000u64),
+            to: Some(h160("3535353535353535353535353535353535353535")),
+            value: U256::from(1_000_000_000_000_000_000u64),
+            ..Default::default()
+        };
+        transaction.sign(&secret_key(), Some(1)).unwrap();
+        assert_eq!(transaction.to_raw(), EIP155_RAW.from_hex().unwrap());
+    }
+
+    #[test]
+    fn signs_transaction_without_chain_id() {
+        let mut transaction = Transaction {
+            start_gas: U256::from(21_000u64),
+            to: Some(h160("3535353535353535353535353535353535353535")),
+            ..Default::default()
+        };
+        transaction.sign(&secret_key(), None).unwrap();
+        assert!(transaction.v == U256::from(27u64) || transaction.v == U256::from(28u64));
+        assert_eq!(transaction.chain_id(), None);
+        assert_eq!(transaction.recover_sender().unwrap(), signer());
+    }
+
+    #[test]
+    fn rejects_truncated_transaction() {
+        let mut stream = RlpStream::new_list(2);
+        stream.append(&bigint::U256::one());
+        stream.append(&bigint::U256::one());
+        assert!(Transaction::from_raw(&stream.out()).is_err());
+    }
+
+    #[test]
+    fn round_trips_contract_creation() {
+        let transaction = Transaction {
+            nonce: U256::from(3u64),
+            start_gas: U256::from(100_000u64),
+            data: vec![0x60, 0x00],
+            ..Default::default()
+        };
+        let decoded = Transaction::from_raw(&transaction.to_raw()).unwrap();
+        assert_eq!(decoded, transaction);
+    }
+
+    #[test]
+    fn derives_contract_address() {
+        let sender = h160("6ac7ea33f8831ea9dcc53393aaa88b25a785dbf0");
+        assert_eq!(
+            contract_address(sender, U256::zero()),
+            h160("cd234a471b72ba2f1ccf0a70fcaba648a5eecd8d")
+        );
+        assert_eq!(
+            contract_address(sender, U256::one()),
+            h160("343c43a37d37dff08ae8c4a11544c718abb4fcf8")
+        );
+    }
+}
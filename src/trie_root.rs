@@ -0,0 +1,58 @@
+//! Contains helpers to compute the Merkle Patricia trie roots committed to by block headers
+
+use bigint;
+use convert;
+use ethereum_types::H256;
+use rlp::RlpStream;
+use std::collections::HashMap;
+use tiny_keccak::keccak256;
+use trie;
+
+/// Returns the root of the trie that maps the RLP encoded index of every item to the item,
+/// as used for the transactions and receipts roots
+pub fn ordered_trie_root<I>(items: I) -> H256
+where
+    I: IntoIterator<Item = Vec<u8>>,
+{
+    let map: HashMap<Vec<u8>, Vec<u8>> = items
+        .into_iter()
+        .enumerate()
+        .map(|(index, item)| {
+            let mut key = RlpStream::new();
+            key.append(&bigint::U256::from(index as u64));
+            (key.out(), item)
+        })
+        .collect();
+    convert::from_h256(trie::build(&map).0)
+}
+
+/// Returns the root of the trie that maps the Keccak hash of every key to its value, as used
+/// for the state and storage tries
+pub fn secure_trie_root<I>(items: I) -> H256
+where
+    I: IntoIterator<Item = (Vec<u8>, Vec<u8>)>,
+{
+    let map: HashMap<Vec<u8>, Vec<u8>> = items
+        .into_iter()
+        .map(|(key, value)| (keccak256(&key).to_vec(), value))
+        .collect();
+    convert::from_h256(trie::build(&map).0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustc_serialize::hex::FromHex;
+
+    #[test]
+    fn empty_trie_root() {
+        assert_eq!(
+            ordered_trie_root(Vec::<Vec<u8>>::new()),
+            H256::from_slice(
+                &"56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421"
+                    .from_hex()
+                    .unwrap()
+            )
+        );
+    }
+}
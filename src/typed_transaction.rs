@@ -0,0 +1,735 @@
+//! Contains the typed transaction envelope (EIP-2718) and the transaction types that use it:
+//! access list transactions (EIP-2930), dynamic fee transactions (EIP-1559) and blob
+//! transactions (EIP-4844). Legacy transactions are wrapped unchanged.
+
+use bigint;
+use convert;
+use errors::Result;
+use ethereum_types::{H160, H256, U256};
+use rlp::{Decodable, DecoderError, Encodable, RlpStream, UntrustedRlp};
+use secp256k1::key::SecretKey;
+use std::cmp;
+use tiny_keccak::keccak256;
+use transaction::{recover_signer, sign_hash, Transaction};
+
+/// Envelope type of access list transactions (EIP-2930)
+pub const ACCESS_LIST_TX_TYPE: u8 = 0x01;
+/// Envelope type of dynamic fee transactions (EIP-1559)
+pub const DYNAMIC_FEE_TX_TYPE: u8 = 0x02;
+/// Envelope type of blob transactions (EIP-4844)
+pub const BLOB_TX_TYPE: u8 = 0x03;
+/// Blob gas consumed by every blob referenced by a transaction
+pub const GAS_PER_BLOB: u64 = 131_072;
+
+#[derive(Debug, Default, Deserialize, Clone, PartialEq, Serialize, Hash, Eq)]
+/// An address and the storage keys of it that a transaction declares it will access
+pub struct AccessListItem {
+    /// Accessed address
+    pub address: H160,
+    /// Accessed storage keys of `address`
+    pub storage_keys: Vec<H256>,
+}
+
+impl Encodable for AccessListItem {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        s.begin_list(2);
+        s.append(&convert::to_address(self.address));
+        s.begin_list(self.storage_keys.len());
+        for key in &self.storage_keys {
+            s.append(&convert::to_h256(*key));
+        }
+    }
+}
+
+impl Decodable for AccessListItem {
+    fn decode(rlp: &UntrustedRlp) -> std::result::Result<Self, DecoderError> {
+        if rlp.item_count() != 2 {
+            return Err(DecoderError::RlpIncorrectListLen);
+        }
+        let storage_keys: Vec<bigint::H256> = rlp.list_at(1)?;
+        Ok(Self {
+            address: convert::from_address(rlp.val_at(0)?),
+            storage_keys: storage_keys.into_iter().map(convert::from_h256).collect(),
+        })
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Clone, PartialEq, Serialize, Hash, Eq)]
+/// A transaction with an access list (EIP-2930)
+pub struct AccessListTransaction {
+    /// Chain the transaction is valid on
+    pub chain_id: u64,
+    /// Nonce
+    pub nonce: U256,
+    /// Gas Price
+    pub gas_price: U256,
+    /// Gas Limit
+    pub gas_limit: U256,
+    /// Recipient
+    /// If None, then this is a contract creation
+    pub to: Option<H160>,
+    /// Transferred value
+    pub value: U256,
+    /// Data
+    pub data: Vec<u8>,
+    /// Addresses and storage keys that are warm from the start of the transaction
+    pub access_list: Vec<AccessListItem>,
+    /// Parity of the y coordinate of the signature point
+    pub y_parity: bool,
+    /// The R field of the signature.
+    pub r: U256,
+    /// The S field of the signature.
+    pub s: U256,
+}
+
+impl AccessListTransaction {
+    /// Appends the fields that are covered by the signature
+    fn rlp_append_unsigned(&self, s: &mut RlpStream) {
+        s.append(&bigint::U256::from(self.chain_id));
+        s.append(&convert::to_u256(self.nonce));
+        s.append(&convert::to_u256(self.gas_price));
+        s.append(&convert::to_u256(self.gas_limit));
+        append_to(s, self.to);
+        s.append(&convert::to_u256(self.value));
+        s.append(&self.data);
+        append_access_list(s, &self.access_list);
+    }
+}
+
+impl Encodable for AccessListTransaction {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        s.begin_list(11);
+        self.rlp_append_unsigned(s);
+        append_signature(s, self.y_parity, self.r, self.s);
+    }
+}
+
+impl Decodable for AccessListTransaction {
+    fn decode(rlp: &UntrustedRlp) -> std::result::Result<Self, DecoderError> {
+        if rlp.item_count() != 11 {
+            return Err(DecoderError::RlpIncorrectListLen);
+        }
+        Ok(Self {
+            chain_id: decode_chain_id(rlp)?,
+            nonce: convert::from_u256(rlp.val_at(1)?),
+            gas_price: convert::from_u256(rlp.val_at(2)?),
+            gas_limit: convert::from_u256(rlp.val_at(3)?),
+            to: decode_to(&rlp.at(4)?)?,
+            value: convert::from_u256(rlp.val_at(5)?),
+            data: rlp.val_at(6)?,
+            access_list: rlp.list_at(7)?,
+            y_parity: decode_y_parity(&rlp.at(8)?)?,
+            r: convert::from_u256(rlp.val_at(9)?),
+            s: convert::from_u256(rlp.val_at(10)?),
+        })
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Clone, PartialEq, Serialize, Hash, Eq)]
+/// A transaction paying a priority fee on top of the block base fee (EIP-1559)
+pub struct DynamicFeeTransaction {
+    /// Chain the transaction is valid on
+    pub chain_id: u64,
+    /// Nonce
+    pub nonce: U256,
+    /// Maximum fee per gas paid to the block producer
+    pub max_priority_fee_per_gas: U256,
+    /// Maximum total fee per gas, including the base fee
+    pub max_fee_per_gas: U256,
+    /// Gas Limit
+    pub gas_limit: U256,
+    /// Recipient
+    /// If None, then this is a contract creation
+    pub to: Option<H160>,
+    /// Transferred value
+    pub value: U256,
+    /// Data
+    pub data: Vec<u8>,
+    /// Addresses and storage keys that are warm from the start of the transaction
+    pub access_list: Vec<AccessListItem>,
+    /// Parity of the y coordinate of the signature point
+    pub y_parity: bool,
+    /// The R field of the signature.
+    pub r: U256,
+    /// The S field of the signature.
+    pub s: U256,
+}
+
+impl DynamicFeeTransaction {
+    /// Appends the fields that are covered by the signature
+    fn rlp_append_unsigned(&self, s: &mut RlpStream) {
+        s.append(&bigint::U256::from(self.chain_id));
+        s.append(&convert::to_u256(self.nonce));
+        s.append(&convert::to_u256(self.max_priority_fee_per_gas));
+        s.append(&convert::to_u256(self.max_fee_per_gas));
+        s.append(&convert::to_u256(self.gas_limit));
+        append_to(s, self.to);
+        s.append(&convert::to_u256(self.value));
+        s.append(&self.data);
+        append_access_list(s, &self.access_list);
+    }
+}
+
+impl Encodable for DynamicFeeTransaction {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        s.begin_list(12);
+        self.rlp_append_unsigned(s);
+        append_signature(s, self.y_parity, self.r, self.s);
+    }
+}
+
+impl Decodable for DynamicFeeTransaction {
+    fn decode(rlp: &UntrustedRlp) -> std::result::Result<Self, DecoderError> {
+        if rlp.item_count() != 12 {
+            return Err(DecoderError::RlpIncorrectListLen);
+        }
+        Ok(Self {
+            chain_id: decode_chain_id(rlp)?,
+            nonce: convert::from_u256(rlp.val_at(1)?),
+            max_priority_fee_per_gas: convert::from_u256(rlp.val_at(2)?),
+            max_fee_per_gas: convert::from_u256(rlp.val_at(3)?),
+            gas_limit: convert::from_u256(rlp.val_at(4)?),
+            to: decode_to(&rlp.at(5)?)?,
+            value: convert::from_u256(rlp.val_at(6)?),
+            data: rlp.val_at(7)?,
+            access_list: rlp.list_at(8)?,
+            y_parity: decode_y_parity(&rlp.at(9)?)?,
+            r: convert::from_u256(rlp.val_at(10)?),
+            s: convert::from_u256(rlp.val_at(11)?),
+        })
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Clone, PartialEq, Serialize, Hash, Eq)]
+/// A dynamic fee transaction that carries blobs (EIP-4844). Only the versioned hashes of the
+/// blobs are part of the transaction; blob transactions cannot create contracts.
+pub struct BlobTransaction {
+    /// Chain the transaction is valid on
+    pub chain_id: u64,
+    /// Nonce
+    pub nonce: U256,
+    /// Maximum fee per gas paid to the block producer
+    pub max_priority_fee_per_gas: U256,
+    /// Maximum total fee per gas, including the base fee
+    pub max_fee_per_gas: U256,
+    /// Gas Limit
+    pub gas_limit: U256,
+    /// Recipient
+    pub to: H160,
+    /// Transferred value
+    pub value: U256,
+    /// Data
+    pub data: Vec<u8>,
+    /// Addresses and storage keys that are warm from the start of the transaction
+    pub access_list: Vec<AccessListItem>,
+    /// Maximum fee per unit of blob gas
+    pub max_fee_per_blob_gas: U256,
+    /// Versioned hashes of the blobs carried by the transaction
+    pub blob_versioned_hashes: Vec<H256>,
+    /// Parity of the y coordinate of the signature point
+    pub y_parity: bool,
+    /// The R field of the signature.
+    pub r: U256,
+    /// The S field of the signature.
+    pub s: U256,
+}
+
+impl BlobTransaction {
+    /// Appends the fields that are covered by the signature
+    fn rlp_append_unsigned(&self, s: &mut RlpStream) {
+        s.append(&bigint::U256::from(self.chain_id));
+        s.append(&convert::to_u256(self.nonce));
+        s.append(&convert::to_u256(self.max_priority_fee_per_gas));
+        s.append(&convert::to_u256(self.max_fee_per_gas));
+        s.append(&convert::to_u256(self.gas_limit));
+        s.append(&convert::to_address(self.to));
+        s.append(&convert::to_u256(self.value));
+        s.append(&self.data);
+        append_access_list(s, &self.access_list);
+        s.append(&convert::to_u256(self.max_fee_per_blob_gas));
+        s.begin_list(self.blob_versioned_hashes.len());
+        for hash in &self.blob_versioned_hashes {
+            s.append(&convert::to_h256(*hash));
+        }
+    }
+}
+
+impl Encodable for BlobTransaction {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        s.begin_list(14);
+        self.rlp_append_unsigned(s);
+        append_signature(s, self.y_parity, self.r, self.s);
+    }
+}
+
+impl Decodable for BlobTransaction {
+    fn decode(rlp: &UntrustedRlp) -> std::result::Result<Self, DecoderError> {
+        if rlp.item_count() != 14 {
+            return Err(DecoderError::RlpIncorrectListLen);
+        }
+        let blob_versioned_hashes: Vec<bigint::H256> = rlp.list_at(10)?;
+        Ok(Self {
+            chain_id: decode_chain_id(rlp)?,
+            nonce: convert::from_u256(rlp.val_at(1)?),
+            max_priority_fee_per_gas: convert::from_u256(rlp.val_at(2)?),
+            max_fee_per_gas: convert::from_u256(rlp.val_at(3)?),
+            gas_limit: convert::from_u256(rlp.val_at(4)?),
+            to: convert::from_address(rlp.val_at(5)?),
+            value: convert::from_u256(rlp.val_at(6)?),
+            data: rlp.val_at(7)?,
+            access_list: rlp.list_at(8)?,
+            max_fee_per_blob_gas: convert::from_u256(rlp.val_at(9)?),
+            blob_versioned_hashes: blob_versioned_hashes
+                .into_iter()
+                .map(convert::from_h256)
+                .collect(),
+            y_parity: decode_y_parity(&rlp.at(11)?)?,
+            r: convert::from_u256(rlp.val_at(12)?),
+            s: convert::from_u256(rlp.val_at(13)?),
+        })
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq, Serialize, Hash, Eq)]
+/// Any transaction that can be included in a block
+pub enum TypedTransaction {
+    /// A legacy transaction, encoded as a bare RLP list
+    Legacy(Transaction),
+    /// An access list transaction (EIP-2930)
+    AccessList(AccessListTransaction),
+    /// A dynamic fee transaction (EIP-1559)
+    DynamicFee(DynamicFeeTransaction),
+    /// A blob transaction (EIP-4844)
+    Blob(BlobTransaction),
+}
+
+impl TypedTransaction {
+    /// Decodes a raw transaction. Legacy transactions are bare RLP lists, typed transactions
+    /// are their type byte followed by the RLP encoding of their fields.
+    pub fn decode(raw: &[u8]) -> std::result::Result<TypedTransaction, DecoderError> {
+        let (transaction_type, payload) = match raw.split_first() {
+            Some((first, _)) if *first >= 0xc0 => {
+                return Transaction::from_raw(raw).map(TypedTransaction::Legacy);
+            }
+            Some((first, payload)) => (*first, UntrustedRlp::new(payload)),
+            None => return Err(DecoderError::RlpIsTooShort),
+        };
+        match transaction_type {
+            ACCESS_LIST_TX_TYPE => payload.as_val().map(TypedTransaction::AccessList),
+            DYNAMIC_FEE_TX_TYPE => payload.as_val().map(TypedTransaction::DynamicFee),
+            BLOB_TX_TYPE => payload.as_val().map(TypedTransaction::Blob),
+            _ => Err(DecoderError::Custom("unknown transaction type")),
+        }
+    }
+
+    /// Returns the encoding of the signed transaction, as included in blocks
+    pub fn encode(&self) -> Vec<u8> {
+        let payload = match *self {
+            TypedTransaction::Legacy(ref transaction) => return transaction.to_raw(),
+            TypedTransaction::AccessList(ref transaction) => transaction.rlp_bytes(),
+            TypedTransaction::DynamicFee(ref transaction) => transaction.rlp_bytes(),
+            TypedTransaction::Blob(ref transaction) => transaction.rlp_bytes(),
+        };
+        let mut raw = vec![self.transaction_type()];
+        raw.extend_from_slice(&payload);
+        raw
+    }
+
+    /// Returns the envelope type of the transaction, 0 for legacy transactions
+    pub fn transaction_type(&self) -> u8 {
+        match *self {
+            TypedTransaction::Legacy(_) => 0,
+            TypedTransaction::AccessList(_) => ACCESS_LIST_TX_TYPE,
+            TypedTransaction::DynamicFee(_) => DYNAMIC_FEE_TX_TYPE,
+            TypedTransaction::Blob(_) => BLOB_TX_TYPE,
+        }
+    }
+
+    /// Returns the hash of the signed transaction, which identifies it
+    pub fn hash(&self) -> H256 {
+        H256::from(keccak256(&self.encode()))
+    }
+
+    /// Returns the hash that is signed by the sender
+    pub fn signing_hash(&self) -> H256 {
+        let stream = match *self {
+            TypedTransaction::Legacy(ref transaction) => {
+                return transaction.signing_hash(transaction.chain_id());
+            }
+            TypedTransaction::AccessList(ref transaction) => {
+                let mut stream = RlpStream::new_list(8);
+                transaction.rlp_append_unsigned(&mut stream);
+                stream
+            }
+            TypedTransaction::DynamicFee(ref transaction) => {
+                let mut stream = RlpStream::new_list(9);
+                transaction.rlp_append_unsigned(&mut stream);
+                stream
+            }
+            TypedTransaction::Blob(ref transaction) => {
+                let mut stream = RlpStream::new_list(11);
+                transaction.rlp_append_unsigned(&mut stream);
+                stream
+            }
+        };
+        let mut preimage = vec![self.transaction_type()];
+        preimage.extend_from_slice(&stream.out());
+        H256::from(keccak256(&preimage))
+    }
+
+    /// Signs the transaction with `secret_key` for `chain_id`. Legacy transactions are signed
+    /// following EIP-155; typed transactions have their chain id set to `chain_id` first.
+    pub fn sign(&mut self, secret_key: &SecretKey, chain_id: u64) -> Result<()> {
+        match *self {
+            TypedTransaction::Legacy(ref mut transaction) => {
+                return transaction.sign(secret_key, Some(chain_id));
+            }
+            TypedTransaction::AccessList(ref mut t) => t.chain_id = chain_id,
+            TypedTransaction::DynamicFee(ref mut t) => t.chain_id = chain_id,
+            TypedTransaction::Blob(ref mut t) => t.chain_id = chain_id,
+        }
+        let signing_hash = self.signing_hash();
+        let (y_parity, r, s) = match *self {
+            TypedTransaction::Legacy(_) => unreachable!("legacy transactions are signed above"),
+            TypedTransaction::AccessList(ref mut t) => (&mut t.y_parity, &mut t.r, &mut t.s),
+            TypedTransaction::DynamicFee(ref mut t) => (&mut t.y_parity, &mut t.r, &mut t.s),
+            TypedTransaction::Blob(ref mut t) => (&mut t.y_parity, &mut t.r, &mut t.s),
+        };
+        let (signature_r, signature_s, recovery_id) = sign_hash(secret_key, &signing_hash)?;
+        *y_parity = recovery_id == 1;
+        *r = signature_r;
+        *s = signature_s;
+        Ok(())
+    }
+
+    /// Recovers the address that signed the transaction with `recover_signer`
+    pub fn recover_sender(&self) -> Result<H160> {
+        let (y_parity, r, s) = match *self {
+            TypedTransaction::Legacy(ref transaction) => return transaction.recover_sender(),
+            TypedTransaction::AccessList(ref t) => (t.y_parity, t.r, t.s),
+            TypedTransaction::DynamicFee(ref t) => (t.y_parity, t.r, t.s),
+            TypedTransaction::Blob(ref t) => (t.y_parity, t.r, t.s),
+        };
+        recover_signer(&self.signing_hash(), r, s, y_parity as i32)
+    }
+
+    /// Returns the chain the transaction was signed for, or None for legacy transactions
+    /// signed before EIP-155
+    pub fn chain_id(&self) -> Option<u64> {
+        match *self {
+            TypedTransaction::Legacy(ref t) => t.chain_id(),
+            TypedTransaction::AccessList(ref t) => Some(t.chain_id),
+            TypedTransaction::DynamicFee(ref t) => Some(t.chain_id),
+            TypedTransaction::Blob(ref t) => Some(t.chain_id),
+        }
+    }
+
+    /// Returns the nonce of the transaction
+    pub fn nonce(&self) -> U256 {
+        match *self {
+            TypedTransaction::Legacy(ref t) => t.nonce,
+            TypedTransaction::AccessList(ref t) => t.nonce,
+            TypedTransaction::DynamicFee(ref t) => t.nonce,
+            TypedTransaction::Blob(ref t) => t.nonce,
+        }
+    }
+
+    /// Returns the maximum amount of gas the transaction can use
+    pub fn gas_limit(&self) -> U256 {
+        match *self {
+            TypedTransaction::Legacy(ref t) => t.start_gas,
+            TypedTransaction::AccessList(ref t) => t.gas_limit,
+            TypedTransaction::DynamicFee(ref t) => t.gas_limit,
+            TypedTransaction::Blob(ref t) => t.gas_limit,
+        }
+    }
+
+    /// Returns the recipient, or None for contract creations
+    pub fn to(&self) -> Option<H160> {
+        match *self {
+            TypedTransaction::Legacy(ref t) => t.to,
+            TypedTransaction::AccessList(ref t) => t.to,
+            TypedTransaction::DynamicFee(ref t) => t.to,
+            TypedTransaction::Blob(ref t) => Some(t.to),
+        }
+    }
+
+    /// Returns the value transferred to the recipient
+    pub fn value(&self) -> U256 {
+        match *self {
+            TypedTransaction::Legacy(ref t) => t.value,
+            TypedTransaction::AccessList(ref t) => t.value,
+            TypedTransaction::DynamicFee(ref t) => t.value,
+            TypedTransaction::Blob(ref t) => t.value,
+        }
+    }
+
+    /// Returns the call data, or the init code for contract creations
+    pub fn data(&self) -> &[u8] {
+        match *self {
+            TypedTransaction::Legacy(ref t) => &t.data,
+            TypedTransaction::AccessList(ref t) => &t.data,
+            TypedTransaction::DynamicFee(ref t) => &t.data,
+            TypedTransaction::Blob(ref t) => &t.data,
+        }
+    }
+
+    /// Returns the access list, which is empty for legacy transactions
+    pub fn access_list(&self) -> &[AccessListItem] {
+        match *self {
+            TypedTransaction::Legacy(_) => &[],
+            TypedTransaction::AccessList(ref t) => &t.access_list,
+            TypedTransaction::DynamicFee(ref t) => &t.access_list,
+            TypedTransaction::Blob(ref t) => &t.access_list,
+        }
+    }
+
+    /// Returns the maximum total fee per gas. For transactions without dynamic fees this is
+    /// their gas price.
+    pub fn max_fee_per_gas(&self) -> U256 {
+        match *self {
+            TypedTransaction::Legacy(ref t) => t.gas_price,
+            TypedTransaction::AccessList(ref t) => t.gas_price,
+            TypedTransaction::DynamicFee(ref t) => t.max_fee_per_gas,
+            TypedTransaction::Blob(ref t) => t.max_fee_per_gas,
+        }
+    }
+
+    /// Returns the maximum fee per gas paid to the block producer. For transactions without
+    /// dynamic fees this is their gas price.
+    pub fn max_priority_fee_per_gas(&self) -> U256 {
+        match *self {
+            TypedTransaction::Legacy(ref t) => t.gas_price,
+            TypedTransaction::AccessList(ref t) => t.gas_price,
+            TypedTransaction::DynamicFee(ref t) => t.max_priority_fee_per_gas,
+            TypedTransaction::Blob(ref t) => t.max_priority_fee_per_gas,
+        }
+    }
+
+    /// Returns the price paid per gas in a block with the given base fee. Callers are expected
+    /// to have checked that `max_fee_per_gas` covers the base fee.
+    pub fn effective_gas_price(&self, base_fee: U256) -> U256 {
+        cmp::min(
+            self.max_fee_per_gas(),
+            base_fee.saturating_add(self.max_priority_fee_per_gas()),
+        )
+    }
+
+    /// Returns the blob gas consumed by the transaction
+    pub fn blob_gas(&self) -> U256 {
+        match *self {
+            TypedTransaction::Blob(ref t) => {
+                U256::from(GAS_PER_BLOB) * U256::from(t.blob_versioned_hashes.len())
+            }
+            _ => U256::zero(),
+        }
+    }
+
+    /// Returns the maximum fee per unit of blob gas, zero for transactions without blobs
+    pub fn max_fee_per_blob_gas(&self) -> U256 {
+        match *self {
+            TypedTransaction::Blob(ref t) => t.max_fee_per_blob_gas,
+            _ => U256::zero(),
+        }
+    }
+}
+
+impl From<Transaction> for TypedTransaction {
+    fn from(transaction: Transaction) -> TypedTransaction {
+        TypedTransaction::Legacy(transaction)
+    }
+}
+
+impl From<AccessListTransaction> for TypedTransaction {
+    fn from(transaction: AccessListTransaction) -> TypedTransaction {
+        TypedTransaction::AccessList(transaction)
+    }
+}
+
+impl From<DynamicFeeTransaction> for TypedTransaction {
+    fn from(transaction: DynamicFeeTransaction) -> TypedTransaction {
+        TypedTransaction::DynamicFee(transaction)
+    }
+}
+
+impl From<BlobTransaction> for TypedTransaction {
+    fn from(transaction: BlobTransaction) -> TypedTransaction {
+        TypedTransaction::Blob(transaction)
+    }
+}
+
+/// Appends the recipient, or empty data for contract creations
+fn append_to(s: &mut RlpStream, to: Option<H160>) {
+    match to {
+        Some(to) => s.append(&convert::to_address(to)),
+        None => s.append_empty_data(),
+    };
+}
+
+/// Appends the access list as a list of `[address, [storage keys]]` items
+fn append_access_list(s: &mut RlpStream, access_list: &[AccessListItem]) {
+    s.begin_list(access_list.len());
+    for item in access_list {
+        s.append(item);
+    }
+}
+
+/// Appends the y parity and the `r` and `s` values of a signature
+fn append_signature(s: &mut RlpStream, y_parity: bool, r: U256, sig_s: U256) {
+    s.append(&bigint::U256::from(y_parity as u64));
+    s.append(&convert::to_u256(r));
+    s.append(&convert::to_u256(sig_s));
+}
+
+fn decode_chain_id(rlp: &UntrustedRlp) -> std::result::Result<u64, DecoderError> {
+    let chain_id = convert::from_u256(rlp.val_at(0)?);
+    if chain_id > U256::from(u64::max_value()) {
+        return Err(DecoderError::Custom("chain id is too large"));
+    }
+    Ok(chain_id.low_u64())
+}
+
+fn decode_to(rlp: &UntrustedRlp) -> std::result::Result<Option<H160>, DecoderError> {
+    if rlp.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(convert::from_address(rlp.as_val()?)))
+}
+
+fn decode_y_parity(rlp: &UntrustedRlp) -> std::result::Result<bool, DecoderError> {
+    let y_parity = convert::from_u256(rlp.as_val()?);
+    if y_parity > U256::one() {
+        return Err(DecoderError::Custom("invalid y parity"));
+    }
+    Ok(y_parity == U256::one())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secp256k1;
+
+    fn secret_key() -> SecretKey {
+        SecretKey::from_slice(&secp256k1::Secp256k1::new(), &[0x46; 32]).unwrap()
+    }
+
+    /// The address of `secret_key`
+    fn signer() -> H160 {
+        H160::from_slice(&[
+            0x9d, 0x8a, 0x62, 0xf6, 0x56, 0xa8, 0xd1, 0x61, 0x5c, 0x12, 0x94, 0xfd, 0x71, 0xe9,
+            0xcf, 0xb3, 0xe4, 0x85, 0x5a, 0x4f,
+        ])
+    }
+
+    fn access_list() -> Vec<AccessListItem> {
+        vec![AccessListItem {
+            address: H160::from_low_u64_be(0x1234),
+            storage_keys: vec![H256::from_low_u64_be(1), H256::from_low_u64_be(2)],
+        }]
+    }
+
+    fn dynamic_fee_transaction() -> DynamicFeeTransaction {
+        DynamicFeeTransaction {
+            chain_id: 250,
+            nonce: U256::from(3u64),
+            max_priority_fee_per_gas: U256::from(2u64),
+            max_fee_per_gas: U256::from(100u64),
+            gas_limit: U256::from(50_000u64),
+            to: Some(H160::from_low_u64_be(0x35)),
+            value: U256::from(1_000u64),
+            data: vec![1, 2, 3],
+            access_list: access_list(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn round_trips_typed_transactions() {
+        let transactions: Vec<TypedTransaction> = vec![
+            Transaction {
+                nonce: U256::one(),
+                to: Some(H160::from_low_u64_be(0x35)),
+                ..Default::default()
+            }
+            .into(),
+            AccessListTransaction {
+                chain_id: 1,
+                gas_price: U256::from(7u64),
+                access_list: access_list(),
+                ..Default::default()
+            }
+            .into(),
+            dynamic_fee_transaction().into(),
+            BlobTransaction {
+                chain_id: 1,
+                to: H160::from_low_u64_be(0x35),
+                max_fee_per_blob_gas: U256::from(9u64),
+                blob_versioned_hashes: vec![H256::from_low_u64_be(0x0100)],
+                ..Default::default()
+            }
+            .into(),
+        ];
+        for (transaction_type, transaction) in transactions.iter().enumerate() {
+            let raw = transaction.encode();
+            if transaction_type == 0 {
+                assert!(raw[0] >= 0xc0);
+            } else {
+                assert_eq!(raw[0], transaction_type as u8);
+            }
+            assert_eq!(&TypedTransaction::decode(&raw).unwrap(), transaction);
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_transaction_type() {
+        assert!(TypedTransaction::decode(&[0x05, 0xc0]).is_err());
+        assert!(TypedTransaction::decode(&[]).is_err());
+    }
+
+    #[test]
+    fn signs_and_recovers_typed_transaction() {
+        let secret_key = secret_key();
+        let mut transaction = TypedTransaction::from(dynamic_fee_transaction());
+        transaction.sign(&secret_key, 250).unwrap();
+        assert_eq!(transaction.recover_sender().unwrap(), signer());
+
+        let decoded = TypedTransaction::decode(&transaction.encode()).unwrap();
+        assert_eq!(decoded.recover_sender().unwrap(), signer());
+        if let TypedTransaction::DynamicFee(mut tampered) = decoded {
+            tampered.value = U256::from(2_000u64);
+            let tampered = TypedTransaction::from(tampered);
+            assert_ne!(tampered.recover_sender().ok(), Some(signer()));
+        }
+    }
+
+    #[test]
+    fn signs_typed_transaction_for_the_given_chain() {
+        let mut transaction = TypedTransaction::from(DynamicFeeTransaction::default());
+        transaction.sign(&secret_key(), 250).unwrap();
+        assert_eq!(transaction.chain_id(), Some(250));
+        let decoded = TypedTransaction::decode(&transaction.encode()).unwrap();
+        assert_eq!(decoded.chain_id(), Some(250));
+        assert_eq!(decoded.recover_sender().unwrap(), signer());
+    }
+
+    #[test]
+    fn computes_effective_gas_price() {
+        let transaction = TypedTransaction::from(dynamic_fee_transaction());
+        assert_eq!(
+            transaction.effective_gas_price(U256::from(10u64)),
+            U256::from(12u64)
+        );
+        assert_eq!(
+            transaction.effective_gas_price(U256::from(99u64)),
+            U256::from(100u64)
+        );
+        let legacy = TypedTransaction::from(Transaction {
+            gas_price: U256::from(20u64),
+            ..Default::default()
+        });
+        assert_eq!(
+            legacy.effective_gas_price(U256::from(10u64)),
+            U256::from(20u64)
+        );
+    }
+}
@@ -1,45 +1,114 @@
 //! Module that contains the VM that executes bytecode
 
-use bigint::{Address, H256, M256, MI256, U256};
-use tiny_keccak::Keccak;
+use bigint::{Address, H256, M256, MI256, U256, U512};
+use tiny_keccak::keccak256;
 
-use account::Account;
+use access_set::AccessSet;
+use block_env::{BlockEnv, Hardfork};
+use convert;
 use errors::{Result, VMError};
 use eth_log::Log;
 use ethereum_types::H160;
+use executor::{CODE_DEPOSIT_GAS, MAX_CODE_SIZE, MAX_INITCODE_SIZE};
+use gas_prices;
 use libvm::{Cpu, Instruction};
 use memory::{Memory, SimpleMemory};
 pub use opcodes::Opcode;
-use rlp::Encodable;
-use std::array::FixedSizeArray;
-use std::collections::HashMap;
+use rlp::RlpStream;
+use std::cmp;
+use std::collections::{HashMap, HashSet};
 use storage::Storage;
-use transaction::Transaction;
+use transaction::{contract_address, Transaction};
+use trie_root;
+
+/// Maximum depth of nested calls
+pub const CALL_DEPTH_LIMIT: usize = 1024;
 
 /// Core VM struct that executes bytecode
 pub struct VM {
-    accounts: HashMap<H160, Account>,
     account_gas: HashMap<H160, U256>,
     account_code: HashMap<H160, Vec<u8>>,
+    account_nonce: HashMap<H160, U256>,
+    account_storage: HashMap<H160, Storage>,
     address: Option<Address>,
     registers: [M256; 1024],
     memory: Option<Box<dyn Memory>>,
     storage: Option<Storage>,
     code: Vec<u8>,
+    jump_destinations: Vec<bool>,
     pc: usize,
     stack_pointer: usize,
     logs: Vec<Log>,
     current_transaction: Option<Transaction>,
     current_sender: Option<H160>,
+    block_env: BlockEnv,
+    gas_left: usize,
+    gas_refund: usize,
+    return_data: Vec<u8>,
+    access_set: AccessSet,
+    original_storage: HashMap<(H160, U256), M256>,
+    depth: usize,
+    selfdestructs: HashSet<H160>,
+    created_accounts: HashSet<H160>,
+    is_static: bool,
+    journal: Vec<JournalEntry>,
+    checkpoints: usize,
+}
+
+/// Position in the journal of state changes, used to undo the effects of a failed execution.
+/// Every checkpoint must be passed to either `commit` or `revert`.
+pub struct Checkpoint {
+    journal: usize,
+    logs: usize,
+    gas_refund: usize,
+}
+
+/// A change to the state, holding what is needed to undo it
+enum JournalEntry {
+    Balance(H160, Option<U256>),
+    Nonce(H160, Option<U256>),
+    Code(H160, Option<Vec<u8>>),
+    StorageSlot(H160, U256, M256),
+    AccessedAddress(H160),
+    AccessedStorageKey(H160, ethereum_types::H256),
+    Selfdestructed(H160),
+    Created(H160),
+    Destroyed {
+        address: H160,
+        balance: Option<U256>,
+        nonce: Option<U256>,
+        code: Option<Vec<u8>>,
+        storage: Option<Storage>,
+    },
+    TransactionStarted {
+        access_set: AccessSet,
+        selfdestructs: HashSet<H160>,
+        created_accounts: HashSet<H160>,
+    },
+}
+
+/// Execution context of a caller, saved while the callee runs
+struct Frame {
+    address: Option<Address>,
+    registers: [M256; 1024],
+    memory: Option<Box<dyn Memory>>,
+    code: Vec<u8>,
+    jump_destinations: Vec<bool>,
+    pc: usize,
+    stack_pointer: usize,
+    current_transaction: Option<Transaction>,
+    current_sender: Option<H160>,
+    is_static: bool,
 }
 
 impl VM {
     /// Creates and returns a new VM
     pub fn new(code: Vec<u8>) -> VM {
         VM {
-            accounts: HashMap::new(),
             account_code: HashMap::new(),
             account_gas: HashMap::new(),
+            account_nonce: HashMap::new(),
+            account_storage: HashMap::new(),
             address: None,
             current_transaction: None,
             current_sender: None,
@@ -47,9 +116,22 @@ impl VM {
             memory: None,
             storage: None,
             stack_pointer: 0,
+            jump_destinations: jump_destinations(&code),
             code,
             pc: 0,
             logs: vec![],
+            block_env: BlockEnv::default(),
+            gas_left: usize::max_value(),
+            gas_refund: 0,
+            return_data: vec![],
+            access_set: AccessSet::default(),
+            original_storage: HashMap::new(),
+            depth: 0,
+            selfdestructs: HashSet::new(),
+            created_accounts: HashSet::new(),
+            is_static: false,
+            journal: vec![],
+            checkpoints: 0,
         }
     }
 
@@ -71,631 +153,1459 @@ impl VM {
         self
     }
 
+    /// Sets the block the VM is executing in
+    pub fn with_block_env(mut self, block_env: BlockEnv) -> VM {
+        self.block_env = block_env;
+        self
+    }
+
     /// Creates a VM with a random address, mainly for testing purposes
     pub fn with_random_address(mut self) -> VM {
         self.address = Some(Address::random());
         self
     }
 
-    /// Starts the execution loop for the VM
+    /// Starts the execution loop for the VM. Runs until the end of the code is reached or an
+    /// instruction halts execution.
     pub fn execute(&mut self) -> Result<()> {
-        loop {
-            match self.execute_one() {
-                Ok(_) => {
-                    continue;
-                }
-                Err(e) => {
-                    return Err(e);
-                }
-            };
+        while self.pc < self.code.len() {
+            self.execute_one()?;
         }
+        Ok(())
     }
 
     /// Executes the next instruction only
     pub fn execute_one(&mut self) -> Result<()> {
         let opcode = Opcode::from(&self.code[self.pc]);
+        if let Some(cost) = gas_prices::get_cost(opcode) {
+            self.consume_gas(cost)?;
+        }
         self.execute_one_instruction(opcode)
     }
 
+    /// Sets how much gas the code being executed is allowed to consume
+    pub fn set_gas_limit(&mut self, gas_limit: usize) {
+        self.gas_left = gas_limit;
+        self.gas_refund = 0;
+    }
+
+    /// Gas that has not been consumed yet
+    pub fn gas_left(&self) -> usize {
+        self.gas_left
+    }
+
+    /// Gas that will be refunded at the end of the transaction
+    pub fn gas_refund(&self) -> usize {
+        self.gas_refund
+    }
+
+    /// Deducts `cost` from the gas left, failing if there is not enough
+    pub fn consume_gas(&mut self, cost: usize) -> Result<()> {
+        if cost > self.gas_left {
+            self.gas_left = 0;
+            return Err(VMError::OutOfGas.into());
+        }
+        self.gas_left -= cost;
+        Ok(())
+    }
+
+    /// Gets the balance of an account
+    pub fn balance(&self, address: H160) -> ethereum_types::U256 {
+        self.account_gas
+            .get(&address)
+            .map(|b| convert::from_u256(*b))
+            .unwrap_or_default()
+    }
+
+    /// Sets the balance of an account
+    pub fn set_balance(&mut self, address: H160, balance: ethereum_types::U256) {
+        let previous = self.account_gas.insert(address, convert::to_u256(balance));
+        self.record(JournalEntry::Balance(address, previous));
+    }
+
+    /// Gets the nonce of an account
+    pub fn nonce(&self, address: H160) -> ethereum_types::U256 {
+        self.account_nonce
+            .get(&address)
+            .map(|n| convert::from_u256(*n))
+            .unwrap_or_default()
+    }
+
+    /// Sets the nonce of an account
+    pub fn set_nonce(&mut self, address: H160, nonce: ethereum_types::U256) {
+        let previous = self.account_nonce.insert(address, convert::to_u256(nonce));
+        self.record(JournalEntry::Nonce(address, previous));
+    }
+
+    /// Gets the code deployed at an account
+    pub fn code(&self, address: H160) -> Vec<u8> {
+        self.account_code.get(&address).cloned().unwrap_or_default()
+    }
+
+    /// Deploys code at an account
+    pub fn set_code(&mut self, address: H160, code: Vec<u8>) {
+        let previous = self.account_code.insert(address, code);
+        self.record(JournalEntry::Code(address, previous));
+    }
+
+    /// Reads a value from the storage of an account
+    pub fn storage_at(&self, address: H160, index: ethereum_types::U256) -> ethereum_types::U256 {
+        let storage = match self.storage {
+            Some(ref s) if s.address() == convert::to_address(address) => Some(s),
+            _ => self.account_storage.get(&address),
+        };
+        storage
+            .and_then(|s| s.read(convert::to_u256(index)).ok())
+            .map(|v| convert::from_u256(v.into()))
+            .unwrap_or_default()
+    }
+
+    /// Returns true if the account has no code, a zero nonce and a zero balance
+    pub fn is_empty(&self, address: H160) -> bool {
+        self.code(address).is_empty()
+            && self.nonce(address).is_zero()
+            && self.balance(address).is_zero()
+    }
+
+    /// Moves `value` from the balance of `from` to the balance of `to`
+    pub fn transfer(&mut self, from: H160, to: H160, value: ethereum_types::U256) -> Result<()> {
+        let from_balance = self.balance(from);
+        if from_balance < value {
+            return Err(VMError::InsufficientBalance.into());
+        }
+        self.set_balance(from, from_balance - value);
+        let to_balance = self.balance(to);
+        self.set_balance(to, to_balance + value);
+        Ok(())
+    }
+
+    /// Prepares `address` for a contract created by `sender` with `value`: fails if the address
+    /// is already in use, then transfers the value, sets the nonce of the new account to one
+    /// (EIP-161) and marks it as created in this transaction
+    pub fn create_account(
+        &mut self,
+        sender: H160,
+        address: H160,
+        value: ethereum_types::U256,
+    ) -> Result<()> {
+        if !self.nonce(address).is_zero() || !self.code(address).is_empty() {
+            return Err(VMError::AddressCollision.into());
+        }
+        self.transfer(sender, address, value)?;
+        self.set_nonce(address, ethereum_types::U256::one());
+        self.mark_created(address);
+        Ok(())
+    }
+
+    /// Deploys the `code` returned by init code at `address`, charging the code deposit cost.
+    /// Fails if the code is too large (EIP-170) or starts with 0xEF (EIP-3541).
+    pub fn deploy_code(&mut self, address: H160, code: Vec<u8>) -> Result<()> {
+        if code.len() > MAX_CODE_SIZE {
+            return Err(VMError::CodeSizeLimit.into());
+        }
+        if code.first() == Some(&0xef) {
+            return Err(VMError::InvalidCodePrefix.into());
+        }
+        self.consume_gas(CODE_DEPOSIT_GAS * code.len())?;
+        self.set_code(address, code);
+        Ok(())
+    }
+
+    /// Marks the current state so the changes made after it can be undone with `revert`, or
+    /// kept with `commit`
+    pub fn checkpoint(&mut self) -> Checkpoint {
+        self.checkpoints += 1;
+        Checkpoint {
+            journal: self.journal.len(),
+            logs: self.logs.len(),
+            gas_refund: self.gas_refund,
+        }
+    }
+
+    /// Keeps the changes made since `checkpoint`. They can still be undone by reverting to an
+    /// earlier checkpoint.
+    pub fn commit(&mut self, checkpoint: Checkpoint) {
+        debug_assert!(checkpoint.journal <= self.journal.len());
+        self.checkpoints -= 1;
+        if self.checkpoints == 0 {
+            self.journal.clear();
+        }
+    }
+
+    /// Undoes every change made since `checkpoint`, newest first
+    pub fn revert(&mut self, checkpoint: Checkpoint) {
+        for entry in self.journal.split_off(checkpoint.journal).into_iter().rev() {
+            self.undo(entry);
+        }
+        self.logs.truncate(checkpoint.logs);
+        self.gas_refund = checkpoint.gas_refund;
+        self.checkpoints -= 1;
+        if self.checkpoints == 0 {
+            self.journal.clear();
+        }
+    }
+
+    /// Records a change to the state. Nothing is recorded when no checkpoint can revert it.
+    fn record(&mut self, entry: JournalEntry) {
+        if self.checkpoints > 0 {
+            self.journal.push(entry);
+        }
+    }
+
+    /// Undoes a single change to the state
+    fn undo(&mut self, entry: JournalEntry) {
+        match entry {
+            JournalEntry::Balance(address, previous) => {
+                restore(&mut self.account_gas, address, previous)
+            }
+            JournalEntry::Nonce(address, previous) => {
+                restore(&mut self.account_nonce, address, previous)
+            }
+            JournalEntry::Code(address, previous) => {
+                restore(&mut self.account_code, address, previous)
+            }
+            JournalEntry::StorageSlot(address, key, previous) => {
+                let _ = self.storage_mut(address).write(key, previous);
+            }
+            JournalEntry::AccessedAddress(address) => self.access_set.remove_address(address),
+            JournalEntry::AccessedStorageKey(address, key) => {
+                self.access_set.remove_storage_key(address, key)
+            }
+            JournalEntry::Selfdestructed(address) => {
+                self.selfdestructs.remove(&address);
+            }
+            JournalEntry::Created(address) => {
+                self.created_accounts.remove(&address);
+            }
+            JournalEntry::Destroyed {
+                address,
+                balance,
+                nonce,
+                code,
+                storage,
+            } => {
+                restore(&mut self.account_gas, address, balance);
+                restore(&mut self.account_nonce, address, nonce);
+                restore(&mut self.account_code, address, code);
+                if let Some(storage) = storage {
+                    match self.storage {
+                        Some(ref s) if s.address() == storage.address() => {
+                            self.storage = Some(storage)
+                        }
+                        _ => {
+                            self.account_storage.insert(address, storage);
+                        }
+                    }
+                }
+                self.selfdestructs.insert(address);
+            }
+            JournalEntry::TransactionStarted {
+                access_set,
+                selfdestructs,
+                created_accounts,
+            } => {
+                self.access_set = access_set;
+                self.selfdestructs = selfdestructs;
+                self.created_accounts = created_accounts;
+            }
+        }
+    }
+
+    /// Starts a new transaction with the given addresses and storage keys already warm
+    pub fn start_transaction(&mut self, access_set: AccessSet) {
+        let entry = JournalEntry::TransactionStarted {
+            access_set: std::mem::replace(&mut self.access_set, access_set),
+            selfdestructs: std::mem::replace(&mut self.selfdestructs, HashSet::new()),
+            created_accounts: std::mem::replace(&mut self.created_accounts, HashSet::new()),
+        };
+        self.record(entry);
+        self.original_storage.clear();
+    }
+
+    /// Records that `address` was created by the current transaction, so it can be destroyed
+    /// by SELFDESTRUCT under EIP-6780
+    pub fn mark_created(&mut self, address: H160) {
+        if self.created_accounts.insert(address) {
+            self.record(JournalEntry::Created(address));
+        }
+    }
+
+    /// Deletes the accounts that self-destructed during the transaction. Called once the
+    /// transaction has been fully applied.
+    pub fn destroy_accounts(&mut self) {
+        for address in std::mem::replace(&mut self.selfdestructs, HashSet::new()) {
+            let mut storage = self.account_storage.remove(&address);
+            if self.storage.as_ref().map(|s| s.address()) == Some(convert::to_address(address)) {
+                storage = self.storage.take();
+            }
+            let entry = JournalEntry::Destroyed {
+                address,
+                balance: self.account_gas.remove(&address),
+                nonce: self.account_nonce.remove(&address),
+                code: self.account_code.remove(&address),
+                storage,
+            };
+            self.record(entry);
+        }
+    }
+
+    /// Gets the addresses and storage keys that are warm for the current transaction
+    pub fn access_set(&self) -> &AccessSet {
+        &self.access_set
+    }
+
+    /// Gets the logs emitted so far
+    pub fn logs(&self) -> &[Log] {
+        &self.logs
+    }
+
+    /// Takes the logs emitted so far, leaving none behind
+    pub fn take_logs(&mut self) -> Vec<Log> {
+        std::mem::replace(&mut self.logs, vec![])
+    }
+
+    /// Gets the block the VM is executing in
+    pub fn block_env(&self) -> &BlockEnv {
+        &self.block_env
+    }
+
+    /// Sets the block the following transactions execute in
+    pub fn set_block_env(&mut self, block_env: BlockEnv) {
+        self.block_env = block_env;
+    }
+
+    /// Computes the root of the state trie over every non-empty account
+    pub fn state_root(&self) -> ethereum_types::H256 {
+        let mut addresses: HashSet<H160> = HashSet::new();
+        addresses.extend(self.account_gas.keys());
+        addresses.extend(self.account_nonce.keys());
+        addresses.extend(self.account_code.keys());
+        addresses.extend(self.account_storage.keys());
+        if let Some(ref storage) = self.storage {
+            addresses.insert(convert::from_address(storage.address()));
+        }
+        let empty_root = trie_root::secure_trie_root(Vec::<(Vec<u8>, Vec<u8>)>::new());
+        let accounts = addresses.into_iter().filter_map(|address| {
+            let storage_root = self.storage_root(address);
+            if self.is_empty(address) && storage_root == empty_root {
+                return None;
+            }
+            let mut stream = RlpStream::new_list(4);
+            stream.append(&convert::to_u256(self.nonce(address)));
+            stream.append(&convert::to_u256(self.balance(address)));
+            stream.append(&convert::to_h256(storage_root));
+            stream.append(&H256::from(&keccak256(&self.code(address))[..]));
+            Some((address.0.to_vec(), stream.out()))
+        });
+        trie_root::secure_trie_root(accounts)
+    }
+
+    /// Computes the root of the storage trie of `address` over its non-zero slots
+    fn storage_root(&self, address: H160) -> ethereum_types::H256 {
+        let storage = match self.storage {
+            Some(ref s) if s.address() == convert::to_address(address) => Some(s),
+            _ => self.account_storage.get(&address),
+        };
+        let slots = storage
+            .into_iter()
+            .flat_map(|s| s.iter())
+            .filter_map(|(index, value)| {
+                let value: U256 = (*value).into();
+                if value.is_zero() {
+                    return None;
+                }
+                let mut key = [0u8; 32];
+                index.to_big_endian(&mut key);
+                let mut stream = RlpStream::new();
+                stream.append(&value);
+                Some((key.to_vec(), stream.out()))
+            });
+        trie_root::secure_trie_root(slots)
+    }
+
+    /// Writes the storage of the executing account back into the account state
+    pub fn commit_storage(&mut self) {
+        if let Some(storage) = self.storage.take() {
+            self.account_storage
+                .insert(convert::from_address(storage.address()), storage);
+        }
+    }
+
+    /// Makes the storage of `address` the one used by SLOAD and SSTORE
+    fn load_storage(&mut self, address: H160) {
+        self.commit_storage();
+        let storage = self
+            .account_storage
+            .remove(&address)
+            .unwrap_or_else(|| Storage::new(convert::to_address(address)));
+        self.storage = Some(storage);
+    }
+
+    /// Gets the storage of `address`, wherever it is held
+    fn storage_mut(&mut self, address: H160) -> &mut Storage {
+        match self.storage {
+            Some(ref mut s) if s.address() == convert::to_address(address) => s,
+            _ => self
+                .account_storage
+                .entry(address)
+                .or_insert_with(|| Storage::new(convert::to_address(address))),
+        }
+    }
+
+    /// Writes storage slot `key` of the executing account
+    fn write_storage(&mut self, key: M256, value: M256) -> Result<()> {
+        let (address, previous) = match self.storage {
+            Some(ref mut store) => {
+                let previous = store.read(key.into())?;
+                store.write(key.into(), value)?;
+                (convert::from_address(store.address()), previous)
+            }
+            None => return Err(VMError::MemoryError.into()),
+        };
+        self.record(JournalEntry::StorageSlot(address, key.into(), previous));
+        Ok(())
+    }
+
+    /// Clears the stack, memory and program counter so new code can run
+    fn reset_frame(&mut self, code: Vec<u8>) {
+        self.jump_destinations = jump_destinations(&code);
+        self.code = code;
+        self.pc = 0;
+        self.stack_pointer = 0;
+        self.memory = Some(Box::new(SimpleMemory::new()));
+        self.return_data = vec![];
+    }
+
+    /// Gets the data returned by the last RETURN or REVERT
+    pub fn return_data(&self) -> &[u8] {
+        &self.return_data
+    }
+
     fn execute_one_instruction(&mut self, opcode: Opcode) -> Result<()> {
         match opcode {
             Opcode::STOP => {
-                return Ok(());
+                self.pc = self.code.len();
             }
             Opcode::ADD => {
-                self.stack_pointer -= 1;
-                let result =
-                    self.registers[self.stack_pointer] + self.registers[self.stack_pointer - 1];
-                self.registers[self.stack_pointer - 1] = result;
+                let (a, b) = (self.pop()?, self.pop()?);
+                self.push(a + b)?;
                 self.pc += 1;
             }
             Opcode::MUL => {
-                self.stack_pointer -= 1;
-                let result =
-                    self.registers[self.stack_pointer] * self.registers[self.stack_pointer - 1];
-                self.registers[self.stack_pointer - 1] = result;
+                let (a, b) = (self.pop()?, self.pop()?);
+                self.push(a * b)?;
                 self.pc += 1;
             }
             Opcode::SUB => {
-                self.stack_pointer -= 1;
-                let result =
-                    self.registers[self.stack_pointer] - self.registers[self.stack_pointer - 1];
-                self.registers[self.stack_pointer - 1] = result;
+                let (a, b) = (self.pop()?, self.pop()?);
+                self.push(a - b)?;
                 self.pc += 1;
             }
             Opcode::DIV => {
-                self.stack_pointer -= 1;
-                let result =
-                    self.registers[self.stack_pointer] / self.registers[self.stack_pointer - 1];
-                self.registers[self.stack_pointer - 1] = result;
+                let (a, b) = (self.pop()?, self.pop()?);
+                self.push(a / b)?;
                 self.pc += 1;
             }
             Opcode::SDIV => {
-                self.stack_pointer -= 1;
-                let s1 = MI256::from(self.registers[self.stack_pointer]);
-                let s2 = MI256::from(self.registers[self.stack_pointer - 1]);
-                let result = s1 / s2;
-                let result: M256 = result.into();
-                self.registers[self.stack_pointer - 1] = result;
-                self.pc += 1;
-            }
-            Opcode::SMOD => {
-                self.stack_pointer -= 1;
-                let s1 = MI256::from(self.registers[self.stack_pointer]);
-                let s2 = MI256::from(self.registers[self.stack_pointer - 1]);
-                let result = s1 / s2;
-                self.registers[self.stack_pointer - 1] = result.into();
+                let a = MI256::from(self.pop()?);
+                let b = MI256::from(self.pop()?);
+                self.push((a / b).into())?;
                 self.pc += 1;
             }
             Opcode::MOD => {
-                self.stack_pointer -= 1;
-                let result =
-                    self.registers[self.stack_pointer] % self.registers[self.stack_pointer - 1];
-                self.registers[self.stack_pointer - 1] = result;
+                let (a, b) = (self.pop()?, self.pop()?);
+                self.push(a % b)?;
                 self.pc += 1;
             }
-            Opcode::ADDMOD => {
-                self.stack_pointer -= 1;
-                let result = (self.registers[self.stack_pointer]
-                    + self.registers[self.stack_pointer - 1])
-                    % self.registers[self.stack_pointer - 2];
-                if result == self.registers[self.stack_pointer - 2] {
-                    self.registers[self.stack_pointer - 2] = result;
-                } else {
-                    self.registers[self.stack_pointer - 2] = 0.into();
-                }
+            Opcode::SMOD => {
+                let a = MI256::from(self.pop()?);
+                let b = MI256::from(self.pop()?);
+                self.push((a % b).into())?;
+                self.pc += 1;
             }
-            Opcode::MULMOD => {
-                self.stack_pointer -= 1;
-                let result = (self.registers[self.stack_pointer]
-                    * self.registers[self.stack_pointer - 1])
-                    % self.registers[self.stack_pointer - 2];
-                if result == self.registers[self.stack_pointer - 2] {
-                    self.registers[self.stack_pointer - 2] = result;
+            Opcode::ADDMOD | Opcode::MULMOD => {
+                // The intermediate result is computed on 512 bits so that it does not wrap
+                let a = U512::from(U256::from(self.pop()?));
+                let b = U512::from(U256::from(self.pop()?));
+                let modulus = U512::from(U256::from(self.pop()?));
+                let result = if modulus.is_zero() {
+                    U512::zero()
+                } else if opcode == Opcode::ADDMOD {
+                    (a + b) % modulus
                 } else {
-                    self.registers[self.stack_pointer - 2] = 0.into();
-                }
+                    (a * b) % modulus
+                };
+                self.push(U256::from(result).into())?;
+                self.pc += 1;
             }
             Opcode::EXP => {
-                let s1 = self.registers[self.stack_pointer];
-                let s2 = self.registers[self.stack_pointer - 1];
-                if s1 > M256::from(32) {
-                    self.registers[self.stack_pointer - 1] = s2;
-                } else {
-                    let mut ret = M256::zero();
-                    let len: usize = s1.as_usize();
-                    let t: usize = 8 * (len + 1) - 1;
-                    let t_bit_mask = M256::one() << t;
-                    let t_value = (s2 & t_bit_mask) >> t;
-                    for i in 0..256 {
-                        let bit_mask = M256::one() << i;
-                        let i_value = (s2 & bit_mask) >> i;
-                        if i <= t {
-                            ret = ret + (i_value << i);
-                        } else {
-                            ret = ret + (t_value << i);
-                        }
+                let mut base = self.pop()?;
+                let mut exponent: U256 = self.pop()?.into();
+                self.consume_gas((exponent.bits() + 7) / 8 * gas_prices::EXP_BYTE_GAS)?;
+                let mut result = M256::one();
+                while !exponent.is_zero() {
+                    if exponent.low_u64() & 1 == 1 {
+                        result = result * base;
                     }
-                    self.registers[self.stack_pointer - 1] = s2;
+                    base = base * base;
+                    exponent = exponent >> 1;
                 }
+                self.push(result)?;
+                self.pc += 1;
             }
             Opcode::SIGNEXTEND => {
-                let s1: U256 = self.registers[self.stack_pointer].into();
-                if s1 < U256::from(32) {
-                    let s2: U256 = self.registers[self.stack_pointer - 1].into();
-                    let bit_position = (s2.low_u64() * 8 + 7) as usize;
-
-                    let bit = s2.bit(bit_position);
+                let byte: U256 = self.pop()?.into();
+                let value: U256 = self.pop()?.into();
+                let result = if byte < U256::from(31u64) {
+                    let bit_position = byte.low_u64() as usize * 8 + 7;
                     let mask = (U256::one() << bit_position) - U256::one();
-                    if bit {
-                        self.registers[self.stack_pointer - 1] = (s2 | !mask).into()
+                    if value.bit(bit_position) {
+                        value | !mask
                     } else {
-                        self.registers[self.stack_pointer - 1] = (s2 & mask).into()
-                    };
-                }
+                        value & mask
+                    }
+                } else {
+                    value
+                };
+                self.push(result.into())?;
+                self.pc += 1;
             }
             Opcode::LT => {
-                self.stack_pointer -= 1;
-                if self.registers[self.stack_pointer] > self.registers[self.stack_pointer - 1] {
-                    self.registers[self.stack_pointer - 1] = 1.into();
-                } else {
-                    self.registers[self.stack_pointer - 1] = 0.into();
-                }
-                self.pc += 2;
+                let (a, b) = (self.pop()?, self.pop()?);
+                self.push((a < b).into())?;
+                self.pc += 1;
             }
             Opcode::GT => {
-                self.stack_pointer -= 1;
-                if self.registers[self.stack_pointer] < self.registers[self.stack_pointer - 1] {
-                    self.registers[self.stack_pointer - 1] = 1.into();
-                } else {
-                    self.registers[self.stack_pointer - 1] = 0.into();
-                }
+                let (a, b) = (self.pop()?, self.pop()?);
+                self.push((a > b).into())?;
                 self.pc += 1;
             }
             Opcode::SLT => {
-                self.stack_pointer -= 1;
-                let s1 = MI256::from(self.registers[self.stack_pointer]);
-                let s2 = MI256::from(self.registers[self.stack_pointer - 1]);
-                let result = s1 > s2;
-                self.registers[self.stack_pointer - 1] = result.into();
+                let a = MI256::from(self.pop()?);
+                let b = MI256::from(self.pop()?);
+                self.push((a < b).into())?;
                 self.pc += 1;
             }
             Opcode::SGT => {
-                self.stack_pointer -= 1;
-                let s1 = MI256::from(self.registers[self.stack_pointer]);
-                let s2 = MI256::from(self.registers[self.stack_pointer - 1]);
-                let result = s1 < s2;
-                self.registers[self.stack_pointer - 1] = result.into();
+                let a = MI256::from(self.pop()?);
+                let b = MI256::from(self.pop()?);
+                self.push((a > b).into())?;
                 self.pc += 1;
             }
             Opcode::EQ => {
-                self.stack_pointer -= 1;
-                if self.registers[self.stack_pointer] == self.registers[self.stack_pointer - 1] {
-                    self.registers[self.stack_pointer - 1] = 1.into();
-                } else {
-                    self.registers[self.stack_pointer - 1] = 0.into();
-                }
+                let (a, b) = (self.pop()?, self.pop()?);
+                self.push((a == b).into())?;
                 self.pc += 1;
             }
             Opcode::ISZERO => {
-                self.stack_pointer -= 1;
-                if self.registers[self.stack_pointer] == 0.into() {
-                    self.registers[self.stack_pointer] = 1.into()
-                } else {
-                    self.registers[self.stack_pointer] = 0.into()
-                }
+                let a = self.pop()?;
+                self.push((a == M256::zero()).into())?;
                 self.pc += 1;
             }
             Opcode::AND => {
-                self.stack_pointer -= 1;
-                let s1 = self.registers[self.stack_pointer];
-                let s2 = self.registers[self.stack_pointer - 1];
-                self.registers[self.stack_pointer - 1] = s1 & s2;
+                let (a, b) = (self.pop()?, self.pop()?);
+                self.push(a & b)?;
                 self.pc += 1;
             }
             Opcode::OR => {
-                self.stack_pointer -= 1;
-                let s1 = self.registers[self.stack_pointer];
-                let s2 = self.registers[self.stack_pointer - 1];
-                self.registers[self.stack_pointer - 1] = s1 | s2;
+                let (a, b) = (self.pop()?, self.pop()?);
+                self.push(a | b)?;
                 self.pc += 1;
             }
             Opcode::XOR => {
-                self.stack_pointer -= 1;
-                let s1 = self.registers[self.stack_pointer];
-                let s2 = self.registers[self.stack_pointer - 1];
-                self.registers[self.stack_pointer - 1] = s1 ^ s2;
+                let (a, b) = (self.pop()?, self.pop()?);
+                self.push(a ^ b)?;
                 self.pc += 1;
             }
             Opcode::NOT => {
-                self.stack_pointer -= 1;
-                let s1 = self.registers[self.stack_pointer];
-                self.registers[self.stack_pointer] = !s1;
+                let a = self.pop()?;
+                self.push(!a)?;
                 self.pc += 1;
             }
             Opcode::BYTE => {
-                self.stack_pointer -= 1;
-                let s1 = self.registers[self.stack_pointer];
-                let s2 = self.registers[self.stack_pointer - 1];
-                let mut ret = M256::zero();
-                for i in 0..256 {
-                    if i < 8 && s1 < 32.into() {
-                        let o: usize = s1.as_usize();
-                        let t = 255 - (7 - i + 8 * o);
-                        let bit_mask = M256::one() << t;
-                        let value = (s2 & bit_mask) >> t;
-                        ret = ret + (value << i);
-                    }
-                }
-                self.registers[self.stack_pointer] = ret;
+                let (index, value) = (self.pop()?, self.pop()?);
+                let result = if index < M256::from(32) {
+                    (value >> (8 * (31 - index.as_usize()))) & M256::from(0xff)
+                } else {
+                    M256::zero()
+                };
+                self.push(result)?;
+                self.pc += 1;
             }
             Opcode::SHA3 => {
-                let offset = self.registers[self.stack_pointer];
-                let size = self.registers[self.stack_pointer - 1];
-                if let Some(ref mut mem) = self.memory {
-                    let mut sha3 = Keccak::new_sha3_256();
-                    sha3.update(mem.read_slice(offset.into(), size.into()));
-                    let mut k: [u8; 32] = [0; 32];
-                    sha3.finalize(&mut k);
-                    println!("k is: {:?}", k);
-                    self.registers[self.stack_pointer - 1] = M256::from(k.as_ref());
-                    self.pc += 1;
-                }
+                let offset: U256 = self.pop()?.into();
+                let size: U256 = self.pop()?.into();
+                self.expand_memory(offset, size)?;
+                let data = match self.memory {
+                    Some(ref mem) => mem.copy_from_memory(offset, size),
+                    None => return Err(VMError::MemoryError.into()),
+                };
+                self.push(M256::from(&keccak256(&data)[..]))?;
+                self.pc += 1;
             }
             Opcode::ADDRESS => {
-                if self.address.is_some() {
-                    self.registers[self.stack_pointer] = self.address.unwrap().clone().into();
-                }
+                let address = self.executing_address();
+                self.push(convert::address_to_m256(address))?;
+                self.pc += 1;
             }
             Opcode::BALANCE => {
-                let sender = self.current_sender.ok_or(VMError::NoSender)?;
-                self.registers[self.stack_pointer] = self.account_gas[&sender].into();
+                let address = convert::m256_to_address(self.pop()?);
+                self.access_address(address)?;
+                let balance = self.balance(address);
+                self.push(convert::to_m256(balance))?;
+                self.pc += 1;
             }
             Opcode::ORIGIN => {
                 let sender = self.current_sender.ok_or(VMError::NoSender)?;
-                let sender_bytes = sender.0.to_vec();
-                self.registers[self.stack_pointer] = (sender_bytes.as_slice()).into();
+                self.push(convert::address_to_m256(sender))?;
+                self.pc += 1;
             }
             Opcode::CALLER => {
-                let to = self
-                    .current_transaction
-                    .as_ref()
-                    .map(|t| t.to.unwrap())
-                    .unwrap();
-                let to_bytes = to.0.to_vec();
-                self.registers[self.stack_pointer] = (to_bytes.as_slice()).into();
+                let caller = self.current_sender.ok_or(VMError::NoSender)?;
+                self.push(convert::address_to_m256(caller))?;
+                self.pc += 1;
             }
             Opcode::CALLVALUE => {
-                let value = self.current_transaction.as_ref().map(|t| t.value).unwrap();
-                let mut bytes = vec![];
-                #[cfg(target_endian = "big")]
-                value.to_bit_endian(&mut bytes);
-                #[cfg(not(target_endian = "big"))]
-                value.to_little_endian(&mut bytes);
-                self.registers[self.stack_pointer] = bytes.as_slice().into();
-            }
-            Opcode::CALLDATALOAD => {
-                let data = self
+                let value = self
                     .current_transaction
                     .as_ref()
-                    .map(|t| t.data.clone())
-                    .unwrap();
-                for (index, byte) in data.into_iter().enumerate() {
-                    self.registers[self.stack_pointer - index] = (byte as usize).into();
+                    .map(|t| t.value)
+                    .unwrap_or_default();
+                self.push(convert::to_m256(value))?;
+                self.pc += 1;
+            }
+            Opcode::CALLDATALOAD => {
+                let offset: U256 = self.pop()?.into();
+                let data = self.call_data();
+                let mut word = [0u8; 32];
+                if offset < U256::from(data.len()) {
+                    let start = offset.as_usize();
+                    let end = cmp::min(start + 32, data.len());
+                    word[..end - start].copy_from_slice(&data[start..end]);
                 }
+                self.push(M256::from(&word[..]))?;
+                self.pc += 1;
             }
             Opcode::CALLDATASIZE => {
-                let data_size = self
-                    .current_transaction
-                    .as_ref()
-                    .map(|t| t.data.len())
-                    .unwrap();
-                self.registers[self.stack_pointer] = data_size.into();
+                let size = self.call_data().len();
+                self.push(size.into())?;
+                self.pc += 1;
             }
             Opcode::CALLDATACOPY => {
-                let data = self
-                    .current_transaction
-                    .as_ref()
-                    .map(|t| t.data.clone())
-                    .unwrap();
-                let mem_offset = self.registers[self.stack_pointer].as_usize();
-                let data_offset = self.registers[self.stack_pointer - 1].as_usize();
-                let size = self.registers[self.stack_pointer - 2].as_usize();
-                if let Some(ref mut mem) = &mut self.memory {
-                    for i in 0..size {
-                        let value = data[data_offset + i] as usize;
-                        mem.write((mem_offset + i).into(), value.into())?;
+                let memory_offset: U256 = self.pop()?.into();
+                let data_offset: U256 = self.pop()?.into();
+                let size: U256 = self.pop()?.into();
+                self.expand_memory(memory_offset, size)?;
+                let data = self.call_data();
+                match self.memory {
+                    Some(ref mut mem) => {
+                        mem.copy_into_memory(&data, memory_offset, data_offset, size)
                     }
-                } else {
-                    return Err(VMError::MemoryError.into());
+                    None => return Err(VMError::MemoryError.into()),
                 }
+                self.pc += 1;
             }
             Opcode::CODESIZE => {
-                self.registers[self.stack_pointer] = self.code.len().into();
+                let size = self.code.len();
+                self.push(size.into())?;
+                self.pc += 1;
             }
             Opcode::CODECOPY => {
-                let memory_offset: U256 = self.registers[self.stack_pointer].into();
-                let code_offset = self.registers[self.stack_pointer - 1];
-                let size = self.registers[self.stack_pointer - 2];
-
-                for (i, b) in self
-                    .code
-                    .iter()
-                    .skip(code_offset.as_usize())
-                    .take(size.as_usize())
-                    .cloned()
-                    .enumerate()
-                {
-                    if let Some(ref mut s) = &mut self.storage {
-                        s.write(memory_offset + i.into(), ([b].as_slice()).into())?;
-                    } else {
-                        return Err(VMError::MemoryError.into());
+                let memory_offset: U256 = self.pop()?.into();
+                let code_offset: U256 = self.pop()?.into();
+                let size: U256 = self.pop()?.into();
+                self.expand_memory(memory_offset, size)?;
+                match self.memory {
+                    Some(ref mut mem) => {
+                        mem.copy_into_memory(&self.code, memory_offset, code_offset, size)
                     }
+                    None => return Err(VMError::MemoryError.into()),
                 }
+                self.pc += 1;
             }
             Opcode::GASPRICE => {
                 let gas_price = self
                     .current_transaction
                     .as_ref()
                     .map(|t| t.gas_price)
-                    .unwrap();
-                let mut bytes = vec![];
-                #[cfg(target_endian = "big")]
-                gas_price.to_bit_endian(&mut bytes);
-                #[cfg(not(target_endian = "big"))]
-                gas_price.to_little_endian(&mut bytes);
-                self.registers[self.stack_pointer] = bytes.as_slice().into();
+                    .unwrap_or_default();
+                self.push(convert::to_m256(gas_price))?;
+                self.pc += 1;
             }
             Opcode::EXTCODESIZE => {
-                let account = self.current_sender.ok_or(VMError::NoSender)?;
-                let size = self
-                    .account_code
-                    .get(&account)
-                    .map(|c| c.len())
-                    .ok_or(VMError::NoCodeInAccount)?;
-                self.registers[self.stack_pointer] = size.into();
+                let address = convert::m256_to_address(self.pop()?);
+                self.access_address(address)?;
+                let size = self.code(address).len();
+                self.push(size.into())?;
+                self.pc += 1;
             }
             Opcode::EXTCODECOPY => {
-                let memory_offset = self.registers[self.stack_pointer];
-                let code_offset = self.registers[self.stack_pointer - 1].as_usize();
-                let size = self.registers[self.stack_pointer - 2].as_usize();
-                let account = self.current_sender.ok_or(VMError::NoSender)?;
-                let code = self
-                    .account_code
-                    .get(&account)
-                    .cloned()
-                    .ok_or(VMError::NoCodeInAccount)?;
-                if let Some(ref mut mem) = &mut self.memory {
-                    for i in 0..size {
-                        let value = code[code_offset - i] as usize;
-                        mem.write(memory_offset + i.into(), value.into())?;
-                    }
+                let address = convert::m256_to_address(self.pop()?);
+                let memory_offset: U256 = self.pop()?.into();
+                let code_offset: U256 = self.pop()?.into();
+                let size: U256 = self.pop()?.into();
+                self.access_address(address)?;
+                self.expand_memory(memory_offset, size)?;
+                let code = self.code(address);
+                if let Some(ref mut mem) = self.memory {
+                    mem.copy_into_memory(&code, memory_offset, code_offset, size);
                 } else {
                     return Err(VMError::MemoryError.into());
                 }
+                self.pc += 1;
+            }
+            Opcode::EXTCODEHASH => {
+                let address = convert::m256_to_address(self.pop()?);
+                self.access_address(address)?;
+                let hash = if self.is_empty(address) {
+                    M256::zero()
+                } else {
+                    M256::from(&keccak256(&self.code(address))[..])
+                };
+                self.push(hash)?;
+                self.pc += 1;
             }
             Opcode::RETURNDATACOPY => {
-                let memory_offset = self.registers[self.stack_pointer];
-                let output_offset = self.registers[self.stack_pointer - 1].as_usize();
-                let size = self.registers[self.stack_pointer - 2].as_usize();
-                if let Some(ref mut mem) = &mut self.memory {
-                    for i in 0..size {
-                        let value = self.registers[output_offset - i];
-                        mem.write(memory_offset + i.into(), value)?;
+                let memory_offset: U256 = self.pop()?.into();
+                let data_offset: U256 = self.pop()?.into();
+                let size: U256 = self.pop()?.into();
+                // Reading past the end of the return data is an exceptional halt (EIP-211)
+                let available = U256::from(self.return_data.len());
+                if data_offset > available || size > available - data_offset {
+                    return Err(VMError::ReturnDataOutOfBounds.into());
+                }
+                self.expand_memory(memory_offset, size)?;
+                match self.memory {
+                    Some(ref mut mem) => {
+                        mem.copy_into_memory(&self.return_data, memory_offset, data_offset, size)
                     }
-                } else {
-                    return Err(VMError::MemoryError.into());
+                    None => return Err(VMError::MemoryError.into()),
                 }
+                self.pc += 1;
             }
             Opcode::RETURNDATASIZE => {
-                let opcode: Opcode = (&self.code[self.pc - 1]).into();
-                self.registers[self.stack_pointer] = opcode.size()?.into();
+                let size = self.return_data.len();
+                self.push(size.into())?;
+                self.pc += 1;
+            }
+            Opcode::BLOCKHASH => {
+                let number: U256 = self.pop()?.into();
+                let hash = self.block_env.block_hash(convert::from_u256(number));
+                self.push(convert::h256_to_m256(hash))?;
+                self.pc += 1;
+            }
+            Opcode::COINBASE => {
+                self.push(convert::address_to_m256(self.block_env.coinbase))?;
+                self.pc += 1;
+            }
+            Opcode::TIMESTAMP => {
+                self.push(convert::to_m256(self.block_env.timestamp))?;
+                self.pc += 1;
+            }
+            Opcode::NUMBER => {
+                self.push(convert::to_m256(self.block_env.number))?;
+                self.pc += 1;
+            }
+            Opcode::DIFFICULTY => {
+                self.push(convert::to_m256(self.block_env.difficulty))?;
+                self.pc += 1;
+            }
+            Opcode::GASLIMIT => {
+                self.push(convert::to_m256(self.block_env.gas_limit))?;
+                self.pc += 1;
+            }
+            Opcode::CHAINID => {
+                self.push(convert::to_m256(self.block_env.chain_id))?;
+                self.pc += 1;
+            }
+            Opcode::BASEFEE => {
+                self.push(convert::to_m256(self.block_env.base_fee))?;
+                self.pc += 1;
             }
             Opcode::PC => {
-                self.registers[self.stack_pointer] = (self.pc - 1).into();
+                let pc = self.pc;
+                self.push(pc.into())?;
+                self.pc += 1;
             }
             Opcode::POP => {
-                self.stack_pointer -= 1;
+                self.pop()?;
+                self.pc += 1;
             }
             Opcode::GAS => {
-                self.registers[self.stack_pointer] = self
-                    .account_gas
-                    .values()
-                    .fold(M256::from(0), |acc, a| acc + (*a).into());
+                let gas_left = self.gas_left;
+                self.push(gas_left.into())?;
+                self.pc += 1;
             }
             Opcode::JUMP => {
-                let new_pc = self.registers[self.stack_pointer];
-                self.pc = new_pc.as_usize();
+                let destination = self.pop()?;
+                self.pc = self.jump_destination(destination)?;
             }
             Opcode::JUMPI => {
-                self.stack_pointer -= 1;
-                let destination = self.registers[self.stack_pointer];
-                let check = self.registers[self.stack_pointer - 1];
-                if check.as_usize() == 0 {
-                    self.pc = destination.as_usize();
+                let destination = self.pop()?;
+                let condition = self.pop()?;
+                if condition == M256::zero() {
+                    self.pc += 1;
+                } else {
+                    self.pc = self.jump_destination(destination)?;
                 }
             }
-            Opcode::JUMPDEST => {}
+            Opcode::JUMPDEST => {
+                self.pc += 1;
+            }
             Opcode::CREATE => {
-                let bytes = self.registers[self.stack_pointer].rlp_bytes().into_vec();
-                let mut id_bytes = [0u8; 20];
-                for (n, byte) in bytes.into_iter().take(20).enumerate() {
-                    id_bytes[n] = byte;
-                }
-                let id: H160 = id_bytes.into();
-                let start_offset = self.registers[self.stack_pointer - 1].into();
-                let size = self.registers[self.stack_pointer - 2].into();
-                if let Some(ref mut store) = self.storage {
-                    let mut code = vec![];
-                    let mut counter = start_offset;
-                    while counter < start_offset + size {
-                        code.push(store.read(counter)?.as_u32() as u8);
-                        counter = counter + 1.into();
+                self.require_non_static()?;
+                let value = convert::from_u256(self.pop()?.into());
+                let offset: U256 = self.pop()?.into();
+                let size: U256 = self.pop()?.into();
+                self.expand_memory(offset, size)?;
+                if self.block_env.hardfork >= Hardfork::Shanghai {
+                    // EIP-3860: init code is limited in size and paid for by the word
+                    if size > U256::from(MAX_INITCODE_SIZE as u64) {
+                        return Err(VMError::InitCodeSizeLimit.into());
                     }
-                    let account = Account::new(format!("{}", id), 0, "".parse().unwrap())?;
-                    self.accounts.insert(id.clone(), account);
-                    self.account_code.insert(id, code);
-                } else {
-                    return Err(VMError::MemoryError.into());
+                    let words = gas_prices::word_count(size.as_usize());
+                    self.consume_gas(words * gas_prices::INITCODE_WORD_GAS)?;
                 }
+                let init_code = match self.memory {
+                    Some(ref mem) => mem.copy_from_memory(offset, size),
+                    None => return Err(VMError::MemoryError.into()),
+                };
+                self.execute_create(value, init_code)?;
+                self.pc += 1;
             }
-            Opcode::CALL => self.execute_call()?,
-            Opcode::CALLCODE => {
-                let to = self
-                    .current_transaction
-                    .as_ref()
-                    .map(|t| t.to.unwrap())
-                    .unwrap();
-                self.current_sender = Some(to);
-                self.execute_call()?
+            Opcode::CALL | Opcode::CALLCODE | Opcode::DELEGATECALL | Opcode::STATICCALL => {
+                self.execute_call(opcode)?;
+                self.pc += 1;
             }
             Opcode::RETURN => {
+                let offset: U256 = self.pop()?.into();
+                let size: U256 = self.pop()?.into();
+                self.expand_memory(offset, size)?;
+                if let Some(ref mem) = self.memory {
+                    self.return_data = mem.copy_from_memory(offset, size);
+                } else {
+                    return Err(VMError::MemoryError.into());
+                }
                 self.pc = self.code.len();
-                let offset = self.registers[self.stack_pointer];
-                let size = self.registers[self.stack_pointer - 1];
+            }
+            Opcode::REVERT => {
+                // Halts like RETURN, but the state changes are undone. Unlike an exceptional
+                // halt the gas left is kept and the return data is passed back to the caller.
+                let offset: U256 = self.pop()?.into();
+                let size: U256 = self.pop()?.into();
+                self.expand_memory(offset, size)?;
                 if let Some(ref mem) = self.memory {
-                    let info = mem.read_slice(offset.into(), size.into());
-                    self.registers[self.stack_pointer] = info.into();
+                    self.return_data = mem.copy_from_memory(offset, size);
                 } else {
                     return Err(VMError::MemoryError.into());
                 }
+                self.pc = self.code.len();
+                return Err(VMError::Revert.into());
+            }
+            Opcode::INVALID => {
+                // Bytes that are not a known opcode decode to INVALID too. Either way this is an
+                // exceptional halt, which consumes all the gas left.
+                self.gas_left = 0;
+                return Err(VMError::InvalidInstruction.into());
             }
-            Opcode::DELEGATECALL => self.execute_call()?,
-            Opcode::INVALID => return Err(VMError::InvalidInstruction.into()),
             Opcode::SUICIDE => {
-                let from = self.current_sender.ok_or(VMError::NoSender)?;
+                self.require_non_static()?;
+                let beneficiary = convert::m256_to_address(self.pop()?);
+                let address = self.executing_address();
+                let balance = self.balance(address);
+                let mut cost = gas_prices::SELFDESTRUCT_GAS;
+                if self.warm_address(beneficiary) {
+                    cost += gas_prices::COLD_ACCOUNT_ACCESS_COST;
+                }
+                if !balance.is_zero() && self.is_empty(beneficiary) {
+                    cost += gas_prices::CALL_NEW_ACCOUNT_GAS;
+                }
+                self.consume_gas(cost)?;
+
+                let hardfork = self.block_env.hardfork;
+                if hardfork < Hardfork::London && !self.selfdestructs.contains(&address) {
+                    self.gas_refund += gas_prices::SELFDESTRUCT_REFUND;
+                }
+                // A beneficiary equal to the account keeps the balance, unless the account
+                // is destroyed at the end of the transaction
+                self.set_balance(address, ethereum_types::U256::zero());
+                let beneficiary_balance = self.balance(beneficiary);
+                self.set_balance(beneficiary, beneficiary_balance + balance);
+                let destroyed =
+                    hardfork < Hardfork::Cancun || self.created_accounts.contains(&address);
+                if destroyed && self.selfdestructs.insert(address) {
+                    self.record(JournalEntry::Selfdestructed(address));
+                }
                 self.pc = self.code.len();
-                self.account_code.remove(&from);
-                self.accounts.remove(&from);
             }
             Opcode::SLOAD => {
-                self.stack_pointer -= 1;
-                let s1 = self.registers[self.stack_pointer];
-                if let Some(ref mut store) = self.storage {
-                    self.registers[self.stack_pointer] = store.read(s1.into()).unwrap();
+                let key = self.pop()?;
+                let cost = if self.access_storage_key(key) {
+                    gas_prices::COLD_SLOAD_COST
                 } else {
-                    return Err(VMError::MemoryError.into());
-                }
+                    gas_prices::WARM_STORAGE_READ_COST
+                };
+                self.consume_gas(cost)?;
+                let value = match self.storage {
+                    Some(ref store) => store.read(key.into())?,
+                    None => return Err(VMError::MemoryError.into()),
+                };
+                self.push(value)?;
+                self.pc += 1;
             }
             Opcode::SSTORE => {
-                self.stack_pointer -= 1;
-                let s1 = self.registers[self.stack_pointer];
-                let s2 = self.registers[self.stack_pointer - 1];
-                if let Some(ref mut store) = self.storage {
-                    match store.write(s1.into(), s2) {
-                        Ok(_) => {}
-                        Err(_e) => return Err(VMError::MemoryError.into()),
-                    }
+                self.require_non_static()?;
+                let key = self.pop()?;
+                let value = self.pop()?;
+                if self.gas_left <= gas_prices::SSTORE_SENTRY_GAS {
+                    return Err(VMError::OutOfGas.into());
+                }
+                let cold_cost = if self.access_storage_key(key) {
+                    gas_prices::COLD_SLOAD_COST
                 } else {
-                    return Err(VMError::MemoryError.into());
+                    0
+                };
+                let current = match self.storage {
+                    Some(ref store) => store.read(key.into())?,
+                    None => return Err(VMError::MemoryError.into()),
+                };
+                let original = self.original_value(key, current);
+                let hardfork = self.block_env.hardfork;
+                let (cost, refund) = gas_prices::sstore_gas(original, current, value, hardfork);
+                self.consume_gas(cold_cost + cost)?;
+                if refund < 0 {
+                    self.gas_refund = self.gas_refund.saturating_sub(-refund as usize);
+                } else {
+                    self.gas_refund += refund as usize;
                 }
+                self.write_storage(key, value)?;
+                self.pc += 1;
             }
             Opcode::MLOAD => {
-                self.stack_pointer -= 1;
-                let offset = self.registers[self.stack_pointer];
-                if let Some(ref mut mem) = self.memory {
-                    self.registers[self.stack_pointer] = mem.read(offset);
-                } else {
-                    return Err(VMError::MemoryError.into());
-                }
+                let offset = self.pop()?;
+                self.expand_memory(offset.into(), U256::from(32u64))?;
+                let value = match self.memory {
+                    Some(ref mem) => mem.read(offset),
+                    None => return Err(VMError::MemoryError.into()),
+                };
+                self.push(value)?;
+                self.pc += 1;
             }
             Opcode::MSTORE => {
-                self.stack_pointer -= 1;
-                let offset = self.registers[self.stack_pointer];
-                let value = self.registers[self.stack_pointer - 1];
-                if let Some(ref mut mem) = self.memory {
-                    mem.write(offset, value)?;
-                    self.pc += 1;
-                } else {
-                    return Err(VMError::MemoryError.into());
+                let offset = self.pop()?;
+                let value = self.pop()?;
+                self.expand_memory(offset.into(), U256::from(32u64))?;
+                match self.memory {
+                    Some(ref mut mem) => mem.write(offset, value)?,
+                    None => return Err(VMError::MemoryError.into()),
                 }
+                self.pc += 1;
             }
             Opcode::MSTORE8 => {
-                self.stack_pointer -= 1;
-                let offset = self.registers[self.stack_pointer];
-                let value = self.registers[self.stack_pointer - 1] % 256.into();
-                if let Some(ref mut mem) = self.memory {
-                    mem.write_byte(offset, (value.0.low_u32() & 0xFF) as u8)?;
-                    self.pc += 1;
+                let offset = self.pop()?;
+                let value = self.pop()?;
+                self.expand_memory(offset.into(), U256::one())?;
+                match self.memory {
+                    Some(ref mut mem) => mem.write_byte(offset, value.0.low_u32() as u8)?,
+                    None => return Err(VMError::MemoryError.into()),
                 }
+                self.pc += 1;
             }
             Opcode::MSIZE => {
-                if let Some(ref mut mem) = self.memory {
-                    self.registers[self.stack_pointer] = mem.size();
-                    self.pc += 1;
-                } else {
-                    return Err(VMError::MemoryError.into());
+                let size = match self.memory {
+                    Some(ref mem) => mem.size(),
+                    None => return Err(VMError::MemoryError.into()),
+                };
+                self.push(size)?;
+                self.pc += 1;
+            }
+            Opcode::PUSH0 => {
+                if self.block_env.hardfork < Hardfork::Shanghai {
+                    self.gas_left = 0;
+                    return Err(VMError::InvalidInstruction.into());
                 }
+                self.push(M256::zero())?;
+                self.pc += 1;
             }
             Opcode::PUSH(bytes) => {
-                let range = &self.code[self.pc + 1..self.pc + 1 + bytes as usize];
-                self.registers[self.stack_pointer] = M256::from(range);
-                self.stack_pointer += 1;
+                // Code that ends in the middle of the immediate reads as padded with zeros
+                let start = cmp::min(self.pc + 1, self.code.len());
+                let end = cmp::min(start + bytes as usize, self.code.len());
+                let mut immediate = vec![0u8; bytes as usize];
+                immediate[..end - start].copy_from_slice(&self.code[start..end]);
+                self.push(M256::from(immediate.as_slice()))?;
                 self.pc += bytes as usize + 1;
             }
-            Opcode::DUP(bytes) => {
-                let val = self.registers[bytes as usize - 1];
-                self.registers[self.stack_pointer] = val;
+            Opcode::DUP(n) => {
+                let n = n as usize;
+                if self.stack_pointer < n {
+                    return Err(VMError::StackUnderflow.into());
+                }
+                let value = self.registers[self.stack_pointer - n];
+                self.push(value)?;
+                self.pc += 1;
             }
-            Opcode::SWAP(bytes) => {
-                let val1 = self.registers[self.stack_pointer - 1];
-                let val2 = self.registers[bytes as usize - 1];
-                self.registers[self.stack_pointer - 1] = val2;
-                self.registers[bytes as usize - 1] = val1;
+            Opcode::SWAP(n) => {
+                let n = n as usize;
+                if self.stack_pointer <= n {
+                    return Err(VMError::StackUnderflow.into());
+                }
+                let top = self.stack_pointer - 1;
+                self.registers.swap(top, top - n);
+                self.pc += 1;
             }
-            Opcode::LOG(bytes) => {
-                self.stack_pointer -= 1;
-                let index = self.registers[self.stack_pointer];
-                let len = self.registers[self.stack_pointer - 1];
-                if let Some(ref mut mem) = self.memory {
-                    let data = mem.copy_from_memory(index.into(), len.into());
-                    let mut topics: Vec<H256> = Vec::new();
-                    for _ in 0..bytes {
-                        let pointer = self.stack_pointer + (bytes as usize + 1);
-                        topics.push(H256::from(self.registers[pointer]));
-                    }
-                    println!("Pushing logs");
-                    self.logs.push(Log {
-                        address: self.address.unwrap(),
-                        data,
-                        topics,
-                    });
-                } else {
-                    return Err(VMError::MemoryError.into());
+            Opcode::LOG(topic_count) => {
+                self.require_non_static()?;
+                let offset: U256 = self.pop()?.into();
+                let size: U256 = self.pop()?.into();
+                let mut topics: Vec<H256> = Vec::new();
+                for _ in 0..topic_count {
+                    topics.push(H256::from(self.pop()?));
                 }
+                let max_size = U256::from((usize::max_value() / gas_prices::LOG_DATA_GAS) as u64);
+                if size > max_size {
+                    self.gas_left = 0;
+                    return Err(VMError::OutOfGas.into());
+                }
+                self.consume_gas(size.as_u64() as usize * gas_prices::LOG_DATA_GAS)?;
+                self.expand_memory(offset, size)?;
+                let data = match self.memory {
+                    Some(ref mem) => mem.copy_from_memory(offset, size),
+                    None => return Err(VMError::MemoryError.into()),
+                };
+                let address = convert::to_address(self.executing_address());
+                self.logs.push(Log {
+                    address,
+                    data,
+                    topics,
+                });
+                self.pc += 1;
             }
-            _ => unimplemented!(),
         };
         Ok(())
     }
 
-    fn execute_call(&mut self) -> Result<()> {
-        self.current_sender.ok_or(VMError::NoSender)?;
-        let to_bytes = self.registers[self.stack_pointer].rlp_bytes().into_vec();
-        let mut id_bytes = [0u8; 20];
-        for (n, byte) in to_bytes.into_iter().take(20).enumerate() {
-            id_bytes[n] = byte;
+    /// Pushes a word onto the stack
+    fn push(&mut self, value: M256) -> Result<()> {
+        if self.stack_pointer >= self.registers.len() {
+            return Err(VMError::StackOverflow.into());
+        }
+        self.registers[self.stack_pointer] = value;
+        self.stack_pointer += 1;
+        Ok(())
+    }
+
+    /// Pops the word on top of the stack
+    fn pop(&mut self) -> Result<M256> {
+        if self.stack_pointer == 0 {
+            return Err(VMError::StackUnderflow.into());
+        }
+        self.stack_pointer -= 1;
+        Ok(self.registers[self.stack_pointer])
+    }
+
+    /// Grows the memory to cover `size` bytes from `offset`, rounded up to a whole word, and
+    /// charges for the words added. Touching no bytes never expands the memory.
+    fn expand_memory(&mut self, offset: U256, size: U256) -> Result<()> {
+        if size.is_zero() {
+            return Ok(());
+        }
+        let (end, overflow) = offset.overflowing_add(size);
+        // The cost of a memory this large is far above any gas limit
+        if overflow || end > U256::from(u64::from(u32::max_value())) {
+            self.gas_left = 0;
+            return Err(VMError::OutOfGas.into());
+        }
+        let words = gas_prices::word_count(end.as_usize());
+        let current_words = match self.memory {
+            Some(ref mem) => gas_prices::word_count(mem.size().as_usize()),
+            None => return Err(VMError::MemoryError.into()),
+        };
+        if words > current_words {
+            let cost = gas_prices::memory_gas(words) - gas_prices::memory_gas(current_words);
+            self.consume_gas(cost)?;
+            if let Some(ref mut mem) = self.memory {
+                mem.resize(words * 32);
+            }
+        }
+        Ok(())
+    }
+
+    /// Data of the message being executed
+    fn call_data(&self) -> Vec<u8> {
+        self.current_transaction
+            .as_ref()
+            .map(|t| t.data.clone())
+            .unwrap_or_default()
+    }
+
+    /// Checks that `destination` is a JUMPDEST instruction, rather than any other instruction
+    /// or a byte of the immediate of a PUSH, and returns it as the new pc
+    fn jump_destination(&self, destination: M256) -> Result<usize> {
+        if destination < M256::from(self.jump_destinations.len())
+            && self.jump_destinations[destination.as_usize()]
+        {
+            Ok(destination.as_usize())
+        } else {
+            Err(VMError::InvalidJump.into())
         }
-        let to: H160 = id_bytes.into();
-        let new_code = self.account_code[&to].clone();
-        let old_code = self.code.clone();
-        let old_pc = self.pc;
-        self.code = new_code;
-        self.pc = 0;
-        self.execute()?;
-        self.code = old_code;
-        self.pc = old_pc;
-        let _in_offset = self.registers[self.stack_pointer - 3];
-        let in_size = self.registers[self.stack_pointer - 4];
-        let out_offset = self.registers[self.stack_pointer - 5];
-        let _out_size = self.registers[self.stack_pointer - 6];
-        if let Some(ref mut mem) = self.memory {
-            let slice = mem.read_slice(out_offset.into(), in_size.into());
-            self.registers[self.stack_pointer - 6] = slice.into();
+    }
+
+    /// Fails if the executing frame was entered through STATICCALL (EIP-214)
+    fn require_non_static(&self) -> Result<()> {
+        if self.is_static {
+            return Err(VMError::StaticStateChange.into());
+        }
+        Ok(())
+    }
+
+    /// Address of the account whose code is executing
+    fn executing_address(&self) -> H160 {
+        self.address.map(convert::from_address).unwrap_or_default()
+    }
+
+    /// Marks `address` as accessed and charges the cold or warm access cost (EIP-2929)
+    fn access_address(&mut self, address: H160) -> Result<()> {
+        let cost = if self.warm_address(address) {
+            gas_prices::COLD_ACCOUNT_ACCESS_COST
+        } else {
+            gas_prices::WARM_STORAGE_READ_COST
+        };
+        self.consume_gas(cost)
+    }
+
+    /// Marks storage slot `key` of the executing account as accessed, returning whether it
+    /// was cold
+    fn access_storage_key(&mut self, key: M256) -> bool {
+        let address = self.executing_address();
+        let key = convert::m256_to_h256(key);
+        let cold = self.access_set.insert_storage_key(address, key);
+        if cold {
+            self.record(JournalEntry::AccessedStorageKey(address, key));
+        }
+        cold
+    }
+
+    /// Marks `address` as accessed, returning whether it was cold
+    fn warm_address(&mut self, address: H160) -> bool {
+        let cold = self.access_set.insert_address(address);
+        if cold {
+            self.record(JournalEntry::AccessedAddress(address));
+        }
+        cold
+    }
+
+    /// Returns the value storage slot `key` of the executing account had at the start of the
+    /// transaction, given its `current` value
+    fn original_value(&mut self, key: M256, current: M256) -> M256 {
+        let address = self.executing_address();
+        *self
+            .original_storage
+            .entry((address, key.into()))
+            .or_insert(current)
+    }
+
+    /// Runs a CALL, CALLCODE, DELEGATECALL or STATICCALL. The callee runs in a new frame with
+    /// the forwarded gas; if it fails, its state changes and the addresses and storage keys it
+    /// accessed are reverted, and unless it ran REVERT the gas it was given is consumed. Pushes 1
+    /// on success and 0 on failure.
+    fn execute_call(&mut self, opcode: Opcode) -> Result<()> {
+        let requested_gas: U256 = self.pop()?.into();
+        let to = convert::m256_to_address(self.pop()?);
+        let value = match opcode {
+            Opcode::CALL | Opcode::CALLCODE => convert::from_u256(self.pop()?.into()),
+            Opcode::DELEGATECALL => self
+                .current_transaction
+                .as_ref()
+                .map(|t| t.value)
+                .unwrap_or_default(),
+            _ => ethereum_types::U256::zero(),
+        };
+        let in_offset: U256 = self.pop()?.into();
+        let in_size: U256 = self.pop()?.into();
+        let out_offset: U256 = self.pop()?.into();
+        let out_size: U256 = self.pop()?.into();
+
+        let transfers_value =
+            (opcode == Opcode::CALL || opcode == Opcode::CALLCODE) && !value.is_zero();
+        if transfers_value && opcode == Opcode::CALL {
+            self.require_non_static()?;
+        }
+        self.access_address(to)?;
+        if transfers_value {
+            self.consume_gas(gas_prices::CALL_VALUE_TRANSFER_GAS)?;
+            if opcode == Opcode::CALL && self.is_empty(to) {
+                self.consume_gas(gas_prices::CALL_NEW_ACCOUNT_GAS)?;
+            }
+        }
+        self.expand_memory(in_offset, in_size)?;
+        self.expand_memory(out_offset, out_size)?;
+        let input = match self.memory {
+            Some(ref mem) => mem.copy_from_memory(in_offset, in_size),
+            None => return Err(VMError::MemoryError.into()),
+        };
+        // EIP-150: all but one 64th of the remaining gas can be forwarded
+        let available = self.gas_left - self.gas_left / 64;
+        let mut gas = if requested_gas > U256::from(available) {
+            available
+        } else {
+            requested_gas.as_u64() as usize
+        };
+        self.consume_gas(gas)?;
+        if transfers_value {
+            gas += gas_prices::CALL_STIPEND;
+        }
+
+        let caller = self.executing_address();
+        let (address, sender) = match opcode {
+            Opcode::CALL | Opcode::STATICCALL => (to, caller),
+            Opcode::CALLCODE => (caller, caller),
+            _ => (caller, self.current_sender.ok_or(VMError::NoSender)?),
+        };
+        let context = Transaction {
+            to: Some(address),
+            value,
+            data: input,
+            gas_price: self
+                .current_transaction
+                .as_ref()
+                .map(|t| t.gas_price)
+                .unwrap_or_default(),
+            ..Default::default()
+        };
+        let can_call =
+            self.depth < CALL_DEPTH_LIMIT && (!transfers_value || self.balance(caller) >= value);
+        if !can_call {
+            self.gas_left += gas;
+            self.return_data = vec![];
+            return self.push(M256::zero());
+        }
+
+        let gas_left = self.gas_left;
+        let checkpoint = self.checkpoint();
+        let frame = self.enter_frame();
+        self.is_static = self.is_static || opcode == Opcode::STATICCALL;
+        let result = if opcode == Opcode::CALL {
+            self.transfer(caller, to, value)
+        } else {
             Ok(())
+        };
+        let code = self.code(to);
+        let result = result.and_then(|()| self.run_frame(code, address, sender, context, gas));
+        let child_gas_left = if result.is_ok() || is_revert(&result) {
+            self.gas_left
         } else {
-            Err(VMError::MemoryError.into())
+            0
+        };
+        let return_data = std::mem::replace(&mut self.return_data, vec![]);
+        if result.is_ok() {
+            self.commit(checkpoint);
+        } else {
+            self.revert(checkpoint);
+        }
+        self.leave_frame(frame);
+        self.load_storage(caller);
+        self.gas_left = gas_left + child_gas_left;
+
+        let copied = cmp::min(out_size, U256::from(return_data.len()));
+        if let Some(ref mut mem) = self.memory {
+            mem.copy_into_memory(&return_data, out_offset, U256::zero(), copied);
         }
+        self.return_data = return_data;
+        self.push(if result.is_ok() {
+            M256::from(1)
+        } else {
+            M256::zero()
+        })
     }
 
-    /// Utility function to print the values of a range of registers
-    pub fn print_registers(&self, start: usize, end: usize) {
-        println!("Stack Pointer is: {:?}", self.stack_pointer);
-        println!("Registers are: ");
-        for register in self.registers[start..end].iter() {
-            print!("{:?} ", register);
+    /// Runs a CREATE of a contract holding `value` with `init_code`. The contract address is
+    /// derived from the creator and its nonce, and the init code runs in a new frame with all
+    /// but one 64th of the remaining gas. If it fails, or the code it returns cannot be
+    /// deployed, its state changes are reverted and the gas it was given is consumed; init code
+    /// that runs REVERT keeps its gas left and return data. Pushes the contract address on
+    /// success and 0 on failure.
+    fn execute_create(&mut self, value: ethereum_types::U256, init_code: Vec<u8>) -> Result<()> {
+        let creator = self.executing_address();
+        // EIP-150: all but one 64th of the remaining gas is given to the init code
+        let gas = self.gas_left - self.gas_left / 64;
+        self.consume_gas(gas)?;
+        self.return_data = vec![];
+        if self.depth >= CALL_DEPTH_LIMIT || self.balance(creator) < value {
+            self.gas_left += gas;
+            return self.push(M256::zero());
+        }
+        let nonce = self.nonce(creator);
+        self.set_nonce(creator, nonce + ethereum_types::U256::one());
+        let address = contract_address(creator, nonce);
+        // EIP-2929: the contract address is warm even if the creation fails
+        self.warm_address(address);
+        let context = Transaction {
+            value,
+            gas_price: self
+                .current_transaction
+                .as_ref()
+                .map(|t| t.gas_price)
+                .unwrap_or_default(),
+            ..Default::default()
+        };
+
+        let gas_left = self.gas_left;
+        let checkpoint = self.checkpoint();
+        let frame = self.enter_frame();
+        let result = self
+            .create_account(creator, address, value)
+            .and_then(|()| self.run_frame(init_code, address, creator, context, gas))
+            .and_then(|()| {
+                let code = std::mem::replace(&mut self.return_data, vec![]);
+                self.deploy_code(address, code)
+            });
+        let reverted = is_revert(&result);
+        let child_gas_left = if result.is_ok() || reverted {
+            self.gas_left
+        } else {
+            0
+        };
+        if !reverted {
+            self.return_data = vec![];
         }
-        println!("\nEnd of Registers");
+        if result.is_ok() {
+            self.commit(checkpoint);
+        } else {
+            self.revert(checkpoint);
+        }
+        self.leave_frame(frame);
+        self.load_storage(creator);
+        self.gas_left = gas_left + child_gas_left;
+        self.push(if result.is_ok() {
+            convert::address_to_m256(address)
+        } else {
+            M256::zero()
+        })
+    }
+
+    /// Runs `code` in the context of `address`
+    fn run_frame(
+        &mut self,
+        code: Vec<u8>,
+        address: H160,
+        sender: H160,
+        context: Transaction,
+        gas: usize,
+    ) -> Result<()> {
+        self.address = Some(convert::to_address(address));
+        self.load_storage(address);
+        self.reset_frame(code);
+        self.current_transaction = Some(context);
+        self.current_sender = Some(sender);
+        self.gas_left = gas;
+        self.execute()
+    }
+
+    /// Saves the execution context of the caller and enters a nested call
+    fn enter_frame(&mut self) -> Frame {
+        self.depth += 1;
+        Frame {
+            address: self.address,
+            registers: self.registers,
+            memory: self.memory.take(),
+            code: std::mem::replace(&mut self.code, vec![]),
+            jump_destinations: std::mem::replace(&mut self.jump_destinations, vec![]),
+            pc: self.pc,
+            stack_pointer: self.stack_pointer,
+            current_transaction: self.current_transaction.take(),
+            current_sender: self.current_sender,
+            is_static: self.is_static,
+        }
+    }
+
+    /// Restores the execution context of the caller once a nested call is done
+    fn leave_frame(&mut self, frame: Frame) {
+        self.depth -= 1;
+        self.address = frame.address;
+        self.registers = frame.registers;
+        self.memory = frame.memory;
+        self.code = frame.code;
+        self.jump_destinations = frame.jump_destinations;
+        self.pc = frame.pc;
+        self.stack_pointer = frame.stack_pointer;
+        self.current_transaction = frame.current_transaction;
+        self.current_sender = frame.current_sender;
+        self.is_static = frame.is_static;
+    }
+
+    /// Utility function to log the stack pointer and the values of a range of registers at
+    /// debug level
+    pub fn print_registers(&self, start: usize, end: usize) {
+        debug!(
+            "stack pointer: {}, registers {}..{}: {:?}",
+            self.stack_pointer,
+            start,
+            end,
+            &self.registers[start..end]
+        );
     }
 
+    /// Prepares the VM to run a transaction. Message calls run the code deployed at the
+    /// recipient, contract creations run the transaction data as init code in the context of
+    /// the new contract address.
     pub fn set_transaction(&mut self, transaction: Transaction, sender: H160) {
-        let code = transaction.data.clone();
-        self.code = code;
+        let (address, code) = match transaction.to {
+            Some(to) => (to, self.code(to)),
+            None => (
+                contract_address(sender, transaction.nonce),
+                transaction.data.clone(),
+            ),
+        };
+        self.address = Some(convert::to_address(address));
+        self.load_storage(address);
+        self.reset_frame(code);
         self.current_transaction = Some(transaction);
         self.current_sender = Some(sender);
     }
 }
 
+/// Whether `result` comes from a REVERT, which keeps the gas left and the return data
+pub fn is_revert(result: &Result<()>) -> bool {
+    match result {
+        Err(e) => match e.downcast_ref::<VMError>() {
+            Some(VMError::Revert) => true,
+            _ => false,
+        },
+        Ok(()) => false,
+    }
+}
+
+/// Marks the positions of `code` that hold a JUMPDEST instruction, skipping the immediates of
+/// PUSH instructions
+fn jump_destinations(code: &[u8]) -> Vec<bool> {
+    let mut destinations = vec![false; code.len()];
+    let mut pc = 0;
+    while pc < code.len() {
+        pc += match Opcode::from(&code[pc]) {
+            Opcode::JUMPDEST => {
+                destinations[pc] = true;
+                1
+            }
+            Opcode::PUSH(bytes) => bytes as usize + 1,
+            _ => 1,
+        };
+    }
+    destinations
+}
+
+/// Puts back the value an entry of `map` had before it was changed
+fn restore<V>(map: &mut HashMap<H160, V>, address: H160, previous: Option<V>) {
+    match previous {
+        Some(value) => {
+            map.insert(address, value);
+        }
+        None => {
+            map.remove(&address);
+        }
+    }
+}
+
 impl Default for VM {
     fn default() -> VM {
         VM {
@@ -706,14 +1616,28 @@ impl Default for VM {
             storage: None,
             stack_pointer: 0,
             code: vec![],
+            jump_destinations: vec![],
             pc: 0,
             logs: vec![],
-            accounts: HashMap::default(),
             account_code: HashMap::default(),
             account_gas: HashMap::default(),
+            account_nonce: HashMap::default(),
+            account_storage: HashMap::default(),
             current_transaction: None,
             current_sender: None,
             address: None,
+            block_env: BlockEnv::default(),
+            gas_left: usize::max_value(),
+            gas_refund: 0,
+            return_data: vec![],
+            access_set: AccessSet::default(),
+            original_storage: HashMap::new(),
+            depth: 0,
+            selfdestructs: HashSet::new(),
+            created_accounts: HashSet::new(),
+            is_static: false,
+            journal: vec![],
+            checkpoints: 0,
         }
     }
 }
@@ -751,6 +1675,7 @@ impl Cpu<Opcode, H160> for VM {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rustc_serialize::hex::FromHex;
 
     #[test]
     fn test_create_vm() {
@@ -759,6 +1684,57 @@ mod tests {
         assert_eq!(vm.registers.len(), 1024);
     }
 
+    #[test]
+    fn test_unknown_opcode_halts_and_consumes_all_gas() {
+        for &byte in &[0x0c, 0xfe] {
+            let mut vm = VM::new(vec![byte]);
+            vm.set_gas_limit(1_000);
+            let err = vm.execute().unwrap_err();
+            match err.downcast_ref::<VMError>() {
+                Some(VMError::InvalidInstruction) => (),
+                other => panic!("expected an invalid instruction, got {:?}", other),
+            }
+            assert_eq!(vm.gas_left(), 0);
+        }
+    }
+
+    #[test]
+    fn test_push0_opcode() {
+        let mut vm = VM::new(vec![0x60, 0x07, 0x5f]);
+        vm.set_gas_limit(1_000);
+        assert!(vm.execute().is_ok());
+        assert_eq!(vm.stack_pointer, 2);
+        assert_eq!(vm.registers[1], M256::zero());
+        assert_eq!(vm.gas_left(), 1_000 - 3 - 2);
+
+        // PUSH0 is only available from Shanghai on
+        let mut env = BlockEnv::default();
+        env.hardfork = Hardfork::London;
+        let mut vm = VM::new(vec![0x5f]).with_block_env(env);
+        vm.set_gas_limit(1_000);
+        let err = vm.execute().unwrap_err();
+        match err.downcast_ref::<VMError>() {
+            Some(VMError::InvalidInstruction) => (),
+            other => panic!("expected an invalid instruction, got {:?}", other),
+        }
+        assert_eq!(vm.gas_left(), 0);
+    }
+
+    #[test]
+    fn test_revert_keeps_gas_and_return_data() {
+        // PUSH1 0x2a PUSH1 0x00 MSTORE8 PUSH1 0x01 PUSH1 0x00 REVERT
+        let default_code = vec![0x60, 0x2a, 0x60, 0x00, 0x53, 0x60, 0x01, 0x60, 0x00, 0xfd];
+        let mut vm = VM::new(default_code).with_simple_memory();
+        vm.set_gas_limit(1_000);
+        let err = vm.execute().unwrap_err();
+        match err.downcast_ref::<VMError>() {
+            Some(VMError::Revert) => (),
+            other => panic!("expected a revert, got {:?}", other),
+        }
+        assert_eq!(vm.gas_left(), 1_000 - 4 * 3 - 3 - 3);
+        assert_eq!(vm.return_data(), &[0x2a]);
+    }
+
     #[test]
     fn test_stop_opcode() {
         let default_code = vec![0];
@@ -837,7 +1813,6 @@ mod tests {
         assert!(result.is_ok());
         let result = vm.execute_one();
         assert!(result.is_ok());
-        vm.print_registers(0, 10);
         assert_eq!(vm.registers[0], 1.into());
     }
 
@@ -869,7 +1844,7 @@ mod tests {
 
     #[test]
     fn test_lt_opcode() {
-        let default_code = vec![0x60, 0xa, 0x60, 0xb, 0x10];
+        let default_code = vec![0x60, 0xb, 0x60, 0xa, 0x10];
         let mut vm = VM::new(default_code);
         let result = vm.execute_one();
         assert!(result.is_ok());
@@ -882,7 +1857,7 @@ mod tests {
 
     #[test]
     fn test_gt_opcode() {
-        let default_code = vec![0x60, 0xa, 0x60, 0xb, 0x11];
+        let default_code = vec![0x60, 0xb, 0x60, 0xa, 0x11];
         let mut vm = VM::new(default_code);
         let result = vm.execute_one();
         assert!(result.is_ok());
@@ -934,28 +1909,20 @@ mod tests {
 
     #[test]
     fn test_addmod_opcode() {
-        let default_code = vec![0x60, 0x0d, 0x60, 0x03, 0x60, 0x05, 0x08];
+        let default_code = vec![0x60, 0x0d, 0x60, 0x0c, 0x60, 0x05, 0x08];
         let mut vm = VM::new(default_code);
-        let result = vm.execute_one();
-        assert!(result.is_ok());
-        let result = vm.execute_one();
-        assert!(result.is_ok());
-        let result = vm.execute_one();
-        assert!(result.is_ok());
-        assert_eq!(vm.registers[0], 13.into());
+        assert!(vm.execute().is_ok());
+        assert_eq!(vm.stack_pointer, 1);
+        assert_eq!(vm.registers[0], 4.into());
     }
 
     #[test]
     fn test_mulmod_opcode() {
         let default_code = vec![0x60, 0x10, 0x60, 0x05, 0x60, 0x05, 0x09];
         let mut vm = VM::new(default_code);
-        let result = vm.execute_one();
-        assert!(result.is_ok());
-        let result = vm.execute_one();
-        assert!(result.is_ok());
-        let result = vm.execute_one();
-        assert!(result.is_ok());
-        assert_eq!(vm.registers[0], 16.into());
+        assert!(vm.execute().is_ok());
+        assert_eq!(vm.stack_pointer, 1);
+        assert_eq!(vm.registers[0], 9.into());
     }
 
     #[test]
@@ -988,20 +1955,51 @@ mod tests {
 
     #[test]
     fn test_memload_opcode() {
-        let default_code = vec![0x60, 0x05, 0x60, 0x01, 0x52, 0x01, 0x51];
+        let default_code = vec![0x60, 0x05, 0x60, 0x01, 0x52, 0x60, 0x01, 0x51];
         let mut vm = VM::new(default_code).with_simple_memory();
-        let result = vm.execute_one();
-        assert!(result.is_ok());
-        let result = vm.execute_one();
-        assert!(result.is_ok());
-        let result = vm.execute_one();
-        assert!(result.is_ok());
+        assert!(vm.execute().is_ok());
+        assert_eq!(vm.stack_pointer, 1);
         assert_eq!(vm.registers[0], M256::from(5));
     }
 
+    #[test]
+    fn test_memory_expansion_is_charged_by_the_word() {
+        // PUSH1 0x01 PUSH1 0x00 MSTORE PUSH1 0x3f MLOAD PUSH1 0x01 PUSH1 0x00 MSTORE8 MSIZE
+        let default_code = vec![
+            0x60, 0x01, 0x60, 0x00, 0x52, 0x60, 0x3f, 0x51, 0x60, 0x01, 0x60, 0x00, 0x53, 0x59,
+        ];
+        let mut vm = VM::new(default_code).with_simple_memory();
+        vm.set_gas_limit(1_000);
+        assert!(vm.execute().is_ok());
+        // Five PUSH1 and MSIZE, then the first word and the next two words of memory
+        assert_eq!(vm.gas_left(), 1_000 - 5 * 3 - 2 - 3 - (9 - 3));
+        assert_eq!(vm.registers[1], M256::from(96));
+
+        // MLOAD far beyond any affordable memory size
+        let mut vm = VM::new(vec![0x63, 0xff, 0xff, 0xff, 0xff, 0x51]).with_simple_memory();
+        vm.set_gas_limit(1_000_000);
+        assert!(vm.execute().is_err());
+        assert_eq!(vm.gas_left(), 0);
+    }
+
+    #[test]
+    fn test_create_limits_init_code_from_shanghai() {
+        // PUSH2 0xc001 PUSH1 0x00 PUSH1 0x00 CREATE, with 49153 bytes of init code
+        let default_code = vec![0x61, 0xc0, 0x01, 0x60, 0x00, 0x60, 0x00, 0xf0];
+        let mut vm = VM::new(default_code)
+            .with_simple_memory()
+            .with_random_address();
+        vm.set_gas_limit(10_000_000);
+        let err = vm.execute().unwrap_err();
+        match err.downcast_ref::<VMError>() {
+            Some(VMError::InitCodeSizeLimit) => (),
+            other => panic!("expected too large init code, got {:?}", other),
+        }
+    }
+
     #[test]
     fn test_dup_opcode() {
-        let default_code = vec![0x60, 0x05, 0x60, 0x01, 0x80];
+        let default_code = vec![0x60, 0x05, 0x60, 0x01, 0x81];
         let mut vm = VM::new(default_code).with_simple_memory();
         let result = vm.execute_one();
         assert!(result.is_ok());
@@ -1009,6 +2007,7 @@ mod tests {
         assert!(result.is_ok());
         let result = vm.execute_one();
         assert!(result.is_ok());
+        assert_eq!(vm.stack_pointer, 3);
         assert_eq!(vm.registers[2], M256::from(5));
     }
 
@@ -1045,6 +2044,57 @@ mod tests {
         assert!(vm.logs.len() > 0);
     }
 
+    #[test]
+    fn test_log_opcode_pops_offset_size_and_topics() {
+        // PUSH1 0xab PUSH1 0x00 MSTORE8, then LOG2 with topics 1 and 2 over one byte
+        let default_code = vec![
+            0x60, 0xab, 0x60, 0x00, 0x53, 0x60, 0x02, 0x60, 0x01, 0x60, 0x01, 0x60, 0x00, 0xa2,
+        ];
+        let mut vm = VM::new(default_code)
+            .with_simple_memory()
+            .with_random_address();
+        vm.set_gas_limit(100_000);
+        assert!(vm.execute().is_ok());
+        // six PUSH1, one word of memory, LOG2 and one byte of data
+        assert_eq!(vm.gas_left(), 100_000 - 6 * 3 - 3 - 1_125 - 8);
+        let log = &vm.logs()[0];
+        assert_eq!(log.address, vm.address.unwrap());
+        assert_eq!(log.data, vec![0xab]);
+        assert_eq!(
+            log.topics,
+            vec![H256::from(M256::from(1)), H256::from(M256::from(2))]
+        );
+    }
+
+    #[test]
+    fn test_log_rejected_in_static_call() {
+        // STATICCALL 0x42 without data, which runs PUSH1 0x00 PUSH1 0x00 LOG0
+        let default_code = vec![
+            0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x42, 0x61, 0xff, 0xff, 0xfa,
+        ];
+        let mut vm = VM::new(default_code)
+            .with_simple_memory()
+            .with_random_address();
+        vm.set_code(
+            H160::from_low_u64_be(0x42),
+            vec![0x60, 0x00, 0x60, 0x00, 0xa0],
+        );
+        assert!(vm.execute().is_ok());
+        assert_eq!(vm.registers[0], M256::zero());
+        assert!(vm.logs().is_empty());
+    }
+
+    #[test]
+    fn test_log_attributed_to_callee() {
+        let mut vm = vm_calling(vec![0x60, 0x00, 0x60, 0x00, 0xa0]);
+        assert!(vm.execute().is_ok());
+        assert_eq!(vm.registers[0], M256::from(1));
+        assert_eq!(
+            vm.logs()[0].address,
+            convert::to_address(H160::from_low_u64_be(0x42))
+        );
+    }
+
     #[test]
     fn test_sload_opcode() {
         let default_code = vec![0x60, 0x05, 0x60, 0x01, 0x54];
@@ -1070,15 +2120,434 @@ mod tests {
         assert!(vm.execute_one().is_ok());
     }
 
+    #[test]
+    fn test_sstore_clear_refund_depends_on_hardfork() {
+        // PUSH1 0x00 PUSH1 0x00 SSTORE on a slot holding 1
+        for &(hardfork, refund) in &[(Hardfork::Berlin, 15_000), (Hardfork::London, 4_800)] {
+            let mut env = BlockEnv::default();
+            env.hardfork = hardfork;
+            let mut vm = VM::new(vec![0x60, 0x00, 0x60, 0x00, 0x55])
+                .with_random_address()
+                .with_block_env(env);
+            let mut storage = Storage::new(vm.address.unwrap());
+            storage.write(U256::zero(), M256::from(1)).unwrap();
+            vm.storage = Some(storage);
+            vm.set_gas_limit(10_000);
+            assert!(vm.execute().is_ok());
+            assert_eq!(vm.gas_refund(), refund);
+        }
+    }
+
+    #[test]
+    fn test_sload_charges_cold_then_warm() {
+        // PUSH1 0x00 SLOAD PUSH1 0x00 SLOAD
+        let default_code = vec![0x60, 0x00, 0x54, 0x60, 0x00, 0x54];
+        let mut vm = VM::new(default_code)
+            .with_simple_memory()
+            .with_random_address();
+        vm.storage = Some(Storage::new(vm.address.unwrap()));
+        vm.set_gas_limit(10_000);
+        assert!(vm.execute().is_ok());
+        assert_eq!(vm.gas_left(), 10_000 - 3 - 2_100 - 3 - 100);
+    }
+
+    #[test]
+    fn test_balance_charges_cold_then_warm() {
+        // PUSH1 0x05 BALANCE PUSH1 0x05 BALANCE
+        let default_code = vec![0x60, 0x05, 0x31, 0x60, 0x05, 0x31];
+        let mut vm = VM::new(default_code).with_simple_memory();
+        vm.set_balance(H160::from_low_u64_be(5), ethereum_types::U256::from(7u64));
+        vm.set_gas_limit(10_000);
+        assert!(vm.execute().is_ok());
+        assert_eq!(vm.gas_left(), 10_000 - 3 - 2_600 - 3 - 100);
+        assert_eq!(vm.registers[1], M256::from(7));
+    }
+
+    /// Calls the code at 0x42 without value or data
+    fn vm_calling(callee_code: Vec<u8>) -> VM {
+        let default_code = vec![
+            0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x42, 0x61, 0xff,
+            0xff, 0xf1,
+        ];
+        let mut vm = VM::new(default_code)
+            .with_simple_memory()
+            .with_random_address();
+        vm.set_code(H160::from_low_u64_be(0x42), callee_code);
+        vm
+    }
+
+    #[test]
+    fn test_call_keeps_callee_accesses() {
+        // PUSH1 0x09 BALANCE STOP
+        let mut vm = vm_calling(vec![0x60, 0x09, 0x31, 0x00]);
+        assert!(vm.execute().is_ok());
+        assert_eq!(vm.stack_pointer, 1);
+        assert_eq!(vm.registers[0], M256::from(1));
+        assert!(vm
+            .access_set()
+            .contains_address(H160::from_low_u64_be(0x42)));
+        assert!(vm
+            .access_set()
+            .contains_address(H160::from_low_u64_be(0x09)));
+    }
+
+    #[test]
+    fn test_failed_call_reverts_callee_accesses() {
+        // PUSH1 0x09 BALANCE INVALID
+        let mut vm = vm_calling(vec![0x60, 0x09, 0x31, 0xfe]);
+        assert!(vm.execute().is_ok());
+        assert_eq!(vm.stack_pointer, 1);
+        assert_eq!(vm.registers[0], M256::zero());
+        assert!(vm
+            .access_set()
+            .contains_address(H160::from_low_u64_be(0x42)));
+        assert!(!vm
+            .access_set()
+            .contains_address(H160::from_low_u64_be(0x09)));
+    }
+
+    #[test]
+    fn test_failed_call_reverts_callee_storage() {
+        // PUSH1 0x01 PUSH1 0x00 SSTORE INVALID
+        let mut vm = vm_calling(vec![0x60, 0x01, 0x60, 0x00, 0x55, 0xfe]);
+        assert!(vm.execute().is_ok());
+        assert_eq!(vm.registers[0], M256::zero());
+        assert!(vm
+            .storage_at(H160::from_low_u64_be(0x42), ethereum_types::U256::zero())
+            .is_zero());
+    }
+
+    #[test]
+    fn test_reverted_call_keeps_gas_and_return_data() {
+        // PUSH1 0x01 PUSH1 0x00 SSTORE PUSH1 0x2a PUSH1 0x00 MSTORE8 PUSH1 0x01 PUSH1 0x00 REVERT
+        let mut vm = vm_calling(vec![
+            0x60, 0x01, 0x60, 0x00, 0x55, 0x60, 0x2a, 0x60, 0x00, 0x53, 0x60, 0x01, 0x60, 0x00,
+            0xfd,
+        ]);
+        vm.set_gas_limit(100_000);
+        assert!(vm.execute().is_ok());
+        assert_eq!(vm.registers[0], M256::zero());
+        assert_eq!(vm.return_data(), &[0x2a]);
+        assert!(vm
+            .storage_at(H160::from_low_u64_be(0x42), ethereum_types::U256::zero())
+            .is_zero());
+
+        // The same callee failing with INVALID consumes the gas it was given
+        let mut failed = vm_calling(vec![0x60, 0x01, 0x60, 0x00, 0x55, 0xfe]);
+        failed.set_gas_limit(100_000);
+        assert!(failed.execute().is_ok());
+        assert!(vm.gas_left() > failed.gas_left() + 50_000);
+    }
+
+    #[test]
+    fn test_revert_undoes_changes_since_checkpoint() {
+        let mut vm = VM::default();
+        let address = H160::from_low_u64_be(7);
+        vm.set_balance(address, ethereum_types::U256::from(5u64));
+        let outer = vm.checkpoint();
+        vm.set_nonce(address, ethereum_types::U256::one());
+        let inner = vm.checkpoint();
+        vm.set_code(address, vec![0x00]);
+        vm.load_storage(address);
+        vm.write_storage(M256::from(1), M256::from(2)).unwrap();
+        vm.commit_storage();
+        vm.selfdestructs.insert(address);
+        vm.destroy_accounts();
+        assert!(vm.nonce(address).is_zero());
+        vm.commit(inner);
+        vm.revert(outer);
+        assert_eq!(vm.balance(address), ethereum_types::U256::from(5u64));
+        assert!(vm.nonce(address).is_zero());
+        assert!(vm.code(address).is_empty());
+        assert!(vm
+            .storage_at(address, ethereum_types::U256::one())
+            .is_zero());
+        assert!(vm.journal.is_empty());
+    }
+
+    /// Runs CREATE from 0x10 with init code that returns the single byte `code`, and returns
+    /// the VM and the address of the contract
+    fn vm_creating(code: u8) -> (VM, H160) {
+        // PUSH1 code PUSH1 0x00 MSTORE8 PUSH1 0x01 PUSH1 0x00 RETURN
+        let init_code = vec![0x60, code, 0x60, 0x00, 0x53, 0x60, 0x01, 0x60, 0x00, 0xf3];
+        // PUSH1 0x0a PUSH1 0x00 PUSH1 0x00 CREATE
+        let default_code = vec![0x60, 0x0a, 0x60, 0x00, 0x60, 0x00, 0xf0];
+        let creator = H160::from_low_u64_be(0x10);
+        let mut vm = VM::new(default_code)
+            .with_simple_memory()
+            .with_address(convert::to_address(creator));
+        vm.memory.as_mut().unwrap().copy_into_memory(
+            &init_code,
+            U256::zero(),
+            U256::zero(),
+            U256::from(init_code.len() as u64),
+        );
+        vm.set_gas_limit(100_000);
+        assert!(vm.execute().is_ok());
+        assert_eq!(vm.stack_pointer, 1);
+        assert_eq!(vm.nonce(creator), ethereum_types::U256::one());
+        (vm, contract_address(creator, ethereum_types::U256::zero()))
+    }
+
+    #[test]
+    fn test_create_deploys_at_address_derived_from_nonce() {
+        let (vm, address) = vm_creating(0x2a);
+        assert_eq!(vm.registers[0], convert::address_to_m256(address));
+        assert_eq!(vm.code(address), vec![0x2a]);
+        assert_eq!(vm.nonce(address), ethereum_types::U256::one());
+        assert!(vm.access_set().contains_address(address));
+    }
+
+    #[test]
+    fn test_create_rejects_code_starting_with_0xef() {
+        let (vm, address) = vm_creating(0xef);
+        assert_eq!(vm.registers[0], M256::zero());
+        assert!(vm.code(address).is_empty());
+        assert!(vm.nonce(address).is_zero());
+    }
+
+    /// Runs PUSH1 0x05 SELFDESTRUCT from an account holding 100 wei
+    fn vm_selfdestructing(hardfork: Hardfork, created: bool) -> (VM, H160) {
+        let default_code = vec![0x60, 0x05, 0xff];
+        let mut env = BlockEnv::default();
+        env.hardfork = hardfork;
+        let mut vm = VM::new(default_code.clone())
+            .with_simple_memory()
+            .with_random_address()
+            .with_block_env(env);
+        let address = convert::from_address(vm.address.unwrap());
+        vm.set_code(address, default_code);
+        vm.set_balance(address, ethereum_types::U256::from(100u64));
+        if created {
+            vm.mark_created(address);
+        }
+        assert!(vm.execute().is_ok());
+        assert_eq!(
+            vm.balance(H160::from_low_u64_be(5)),
+            ethereum_types::U256::from(100u64)
+        );
+        assert!(vm.balance(address).is_zero());
+        (vm, address)
+    }
+
+    #[test]
+    fn test_selfdestruct_keeps_existing_account_under_cancun() {
+        let (mut vm, address) = vm_selfdestructing(Hardfork::Cancun, false);
+        vm.destroy_accounts();
+        assert!(!vm.code(address).is_empty());
+        assert_eq!(vm.gas_refund(), 0);
+    }
+
+    #[test]
+    fn test_selfdestruct_deletes_account_created_in_transaction() {
+        let (mut vm, address) = vm_selfdestructing(Hardfork::Cancun, true);
+        assert!(!vm.code(address).is_empty());
+        vm.destroy_accounts();
+        assert!(vm.code(address).is_empty());
+    }
+
+    #[test]
+    fn test_selfdestruct_refunds_before_london() {
+        let (mut vm, address) = vm_selfdestructing(Hardfork::Berlin, false);
+        assert_eq!(vm.gas_refund(), 24_000);
+        vm.destroy_accounts();
+        assert!(vm.code(address).is_empty());
+    }
+
+    #[test]
+    fn test_block_env_opcodes() {
+        let default_code = vec![0x41, 0x42, 0x43, 0x45, 0x46];
+        let coinbase = H160::from_low_u64_be(0xc0ffee);
+        let mut env = BlockEnv::new(ethereum_types::U256::from(42u64));
+        env.coinbase = coinbase;
+        env.timestamp = ethereum_types::U256::from(1_000u64);
+        env.gas_limit = ethereum_types::U256::from(8_000_000u64);
+        env.chain_id = ethereum_types::U256::from(250u64);
+        let mut vm = VM::new(default_code).with_block_env(env);
+        for _ in 0..5 {
+            assert!(vm.execute_one().is_ok());
+        }
+        assert_eq!(vm.registers[0], convert::address_to_m256(coinbase));
+        assert_eq!(vm.registers[1], M256::from(1_000));
+        assert_eq!(vm.registers[2], M256::from(42));
+        assert_eq!(vm.registers[3], M256::from(8_000_000));
+        assert_eq!(vm.registers[4], M256::from(250));
+    }
+
+    #[test]
+    fn test_blockhash_opcode() {
+        let default_code = vec![0x60, 0x09, 0x40, 0x60, 0x01, 0x40];
+        let hash = ethereum_types::H256::from_low_u64_be(0xbeef);
+        let env = BlockEnv::new(ethereum_types::U256::from(10u64))
+            .with_block_hash(ethereum_types::U256::from(9u64), hash);
+        let mut vm = VM::new(default_code).with_block_env(env);
+        for _ in 0..4 {
+            assert!(vm.execute_one().is_ok());
+        }
+        assert_eq!(vm.registers[0], convert::h256_to_m256(hash));
+        assert_eq!(vm.registers[1], M256::zero());
+    }
+
     #[test]
     fn test_sha3_opcode() {
-        let default_code = vec![0x60, 0x05, 0x60, 0x00, 0x52, 0x20];
+        // Hashes the word 5 stored at offset 0
+        let default_code = vec![0x60, 0x05, 0x60, 0x00, 0x52, 0x60, 0x20, 0x60, 0x00, 0x20];
         let mut vm = VM::new(default_code)
             .with_simple_memory()
             .with_random_address();
-        assert!(vm.execute_one().is_ok());
-        assert!(vm.execute_one().is_ok());
-        assert!(vm.execute_one().is_ok());
-        assert!(vm.execute_one().is_ok());
+        assert!(vm.execute().is_ok());
+        assert_eq!(vm.stack_pointer, 1);
+        assert_eq!(
+            vm.registers[0],
+            M256::from(
+                &"036b6384b5eca791c62761152d0c79bb0604c104a5fb6f4eb0703f3154bb3db0"
+                    .from_hex()
+                    .unwrap()[..]
+            )
+        );
+    }
+
+    #[test]
+    fn test_opcodes_fail_on_stack_underflow() {
+        // ADD, EQ, SHA3, DUP2 and SWAP1 with a single item on the stack
+        for &opcode in &[0x01, 0x14, 0x20, 0x81, 0x90] {
+            let mut vm = VM::new(vec![0x60, 0x01, opcode]).with_simple_memory();
+            let err = vm.execute().unwrap_err();
+            match err.downcast_ref::<VMError>() {
+                Some(VMError::StackUnderflow) => (),
+                other => panic!("expected a stack underflow, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_unary_opcodes_replace_the_top_of_the_stack() {
+        // PUSH1 0x07 PUSH1 0x00 ISZERO PUSH1 0x00 NOT ADDRESS
+        let default_code = vec![0x60, 0x07, 0x60, 0x00, 0x15, 0x60, 0x00, 0x19, 0x30];
+        let mut vm =
+            VM::new(default_code).with_address(convert::to_address(H160::from_low_u64_be(0x42)));
+        assert!(vm.execute().is_ok());
+        assert_eq!(vm.stack_pointer, 4);
+        assert_eq!(vm.registers[0], M256::from(7));
+        assert_eq!(vm.registers[1], M256::from(1));
+        assert_eq!(vm.registers[2], !M256::zero());
+        assert_eq!(vm.registers[3], M256::from(0x42));
+    }
+
+    #[test]
+    fn test_exp_signextend_and_byte_opcodes() {
+        // PUSH1 0x0a PUSH1 0x02 EXP, PUSH1 0xff PUSH1 0x00 SIGNEXTEND,
+        // PUSH2 0x1234 PUSH1 0x1e BYTE
+        let default_code = vec![
+            0x60, 0x0a, 0x60, 0x02, 0x0a, 0x60, 0xff, 0x60, 0x00, 0x0b, 0x61, 0x12, 0x34, 0x60,
+            0x1e, 0x1a,
+        ];
+        let mut vm = VM::new(default_code);
+        vm.set_gas_limit(1_000);
+        assert!(vm.execute().is_ok());
+        assert_eq!(vm.stack_pointer, 3);
+        assert_eq!(vm.registers[0], M256::from(1024));
+        assert_eq!(vm.registers[1], !M256::zero());
+        assert_eq!(vm.registers[2], M256::from(0x12));
+        assert_eq!(vm.gas_left(), 1_000 - 6 * 3 - 10 - 50 - 5 - 3);
+    }
+
+    #[test]
+    fn test_pc_advances_past_every_opcode() {
+        // PC PUSH1 0x08 JUMP INVALID INVALID INVALID INVALID JUMPDEST PC
+        let default_code = vec![0x58, 0x60, 0x08, 0x56, 0xfe, 0xfe, 0xfe, 0xfe, 0x5b, 0x58];
+        let mut vm = VM::new(default_code);
+        assert!(vm.execute().is_ok());
+        assert_eq!(vm.stack_pointer, 2);
+        assert_eq!(vm.registers[0], M256::from(0));
+        assert_eq!(vm.registers[1], M256::from(9));
+    }
+
+    #[test]
+    fn test_jumpi_jumps_only_to_jumpdest_when_condition_is_set() {
+        // PUSH1 0x01 PUSH1 0x06 JUMPI INVALID JUMPDEST
+        let mut vm = VM::new(vec![0x60, 0x01, 0x60, 0x06, 0x57, 0xfe, 0x5b]);
+        assert!(vm.execute().is_ok());
+        // PUSH1 0x00 PUSH1 0x07 JUMPI STOP INVALID JUMPDEST
+        let mut vm = VM::new(vec![0x60, 0x00, 0x60, 0x07, 0x57, 0x00, 0xfe, 0x5b]);
+        assert!(vm.execute().is_ok());
+        // A JUMPDEST byte inside a PUSH immediate is not a destination:
+        // PUSH1 0x04 JUMP PUSH1 0x5b
+        let mut vm = VM::new(vec![0x60, 0x04, 0x56, 0x60, 0x5b]);
+        let err = vm.execute().unwrap_err();
+        match err.downcast_ref::<VMError>() {
+            Some(VMError::InvalidJump) => (),
+            other => panic!("expected an invalid jump, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_jump_destinations_skip_push_immediates() {
+        // JUMPDEST PUSH2 0x5b5b JUMPDEST PUSH32 at the end of the code with a single 0x5b byte
+        let code = vec![0x5b, 0x61, 0x5b, 0x5b, 0x5b, 0x7f, 0x5b];
+        assert_eq!(
+            jump_destinations(&code),
+            vec![true, false, false, false, true, false, false]
+        );
+    }
+
+    #[test]
+    fn test_calldata_opcodes() {
+        // CALLDATASIZE PUSH1 0x01 CALLDATALOAD, then CALLDATACOPY 2 bytes from 1 to 0
+        let code = vec![
+            0x36, 0x60, 0x01, 0x35, 0x60, 0x02, 0x60, 0x01, 0x60, 0x00, 0x37,
+        ];
+        let to = H160::from_low_u64_be(0x42);
+        let mut vm = VM::new(vec![]).with_simple_memory();
+        vm.set_code(to, code);
+        vm.set_transaction(
+            Transaction {
+                to: Some(to),
+                data: vec![0xaa, 0xbb, 0xcc],
+                ..Default::default()
+            },
+            H160::from_low_u64_be(1),
+        );
+        assert!(vm.execute().is_ok());
+        assert_eq!(vm.stack_pointer, 2);
+        let mut expected = [0u8; 32];
+        expected[0] = 0xbb;
+        expected[1] = 0xcc;
+        assert_eq!(vm.registers[0], M256::from(3));
+        assert_eq!(vm.registers[1], M256::from(&expected[..]));
+        let memory = vm.memory.unwrap();
+        assert_eq!(
+            memory.copy_from_memory(U256::zero(), U256::from(2u64)),
+            vec![0xbb, 0xcc]
+        );
+    }
+
+    #[test]
+    fn state_root_covers_accounts_and_storage() {
+        let mut vm = VM::new(vec![]);
+        let first = H160::from_low_u64_be(1);
+        let second = H160::from_low_u64_be(2);
+        vm.set_balance(first, ethereum_types::U256::from(1_000u64));
+        assert_eq!(
+            format!("{:x}", vm.state_root()),
+            "2806544196f145d2a429f4474bb4978420f16628b0bc8cbd4ec16acc1c03b18e"
+        );
+        vm.set_nonce(second, ethereum_types::U256::one());
+        vm.set_code(second, vec![0x60, 0x00]);
+        vm.load_storage(second);
+        vm.storage
+            .as_mut()
+            .unwrap()
+            .write(U256::zero(), M256::from(0x2a))
+            .unwrap();
+        assert_eq!(
+            format!("{:x}", vm.state_root()),
+            "468473ffd4760db49049bd856ef1585d39bbc3f7301913f01e33b35560bf2890"
+        );
+        vm.commit_storage();
+        assert_eq!(
+            format!("{:x}", vm.state_root()),
+            "468473ffd4760db49049bd856ef1585d39bbc3f7301913f01e33b35560bf2890"
+        );
     }
 }